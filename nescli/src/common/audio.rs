@@ -16,15 +16,13 @@ const HOST_AUDIO_RATE: f32 = 44100.0;
 
 pub struct AudioStreamSource {
     queue: VecDeque<Sample>,
+    input_rate: f32,
     output_rate: f32,
 }
 
 impl Default for AudioStreamSource {
     fn default() -> Self {
-        AudioStreamSource {
-            queue: VecDeque::new(),
-            output_rate: HOST_AUDIO_RATE,
-        }
+        AudioStreamSource::new(APU_OUTPUT_RATE)
     }
 }
 
@@ -46,8 +44,17 @@ impl AudioCallback for AudioStreamSource {
 }
 
 impl AudioStreamSource {
+    /// Create an audio source for APU output at the given rate
+    pub fn new(input_rate: f32) -> Self {
+        AudioStreamSource {
+            queue: VecDeque::new(),
+            input_rate,
+            output_rate: HOST_AUDIO_RATE,
+        }
+    }
+
     pub fn update(&mut self, buffer: SampleBuffer) {
-        for sample in DownSampler::new(buffer, APU_OUTPUT_RATE, self.output_rate).into_iter() {
+        for sample in DownSampler::new(buffer, self.input_rate, self.output_rate).into_iter() {
             self.queue.push_back(sample);
         }
     }
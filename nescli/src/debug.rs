@@ -0,0 +1,412 @@
+//
+// debug.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 23 2021
+//
+
+use clap::Clap;
+
+use nescore::{Nes, Cartridge, Breakpoint, BreakpointHit, Budget, StopReason};
+use nescore::asm;
+use nescore::gdb::GdbServer;
+
+use std::io::{self, BufRead, Write};
+
+// Frames to run, when continuing, before giving control back to the prompt
+const DEFAULT_CONTINUE_FRAMES: u64 = 3600;
+// Number of instructions shown by `list`
+const DEFAULT_LIST_COUNT: usize = 10;
+// Number of bytes shown by `mem`
+const DEFAULT_MEM_LEN: u16 = 64;
+// Number of recently executed instructions shown before the PC
+const HISTORY_LEN: usize = 3;
+
+const HELP: &str = "\
+Commands:
+  s, step [N]              Execute N instructions
+  n, next                  Execute the next instruction, stepping over subroutine calls
+  c, continue [FRAMES]     Run until a breakpoint is hit
+  b, break ADDR            Break before executing the instruction at ADDR
+  w, watch START [END]     Break on CPU writes to the address range
+  rw, rwatch START [END]   Break on CPU reads from the address range
+  vw, vwatch START [END]   Break on PPU writes to the VRAM address range
+  d, delete ID             Remove a breakpoint
+  bl, breakpoints          List breakpoints
+  r, regs                  Show CPU registers
+  x, mem ADDR [LEN]        Dump CPU memory
+  l, list [ADDR] [N]       Disassemble N instructions (Defaults to the PC)
+  ppu                      Show PPU registers
+  stack                    Show the stack
+  h, help                  Show this message
+  q, quit                  Exit
+
+Addresses are hexadecimal ($C000, 0xC000 or C000). An empty line repeats the last command";
+
+#[derive(Clap)]
+pub struct Options {
+    /// Serve the GDB remote protocol on the port, instead of using the prompt
+    #[clap(long = "gdb")]
+    gdb: Option<u16>,
+    /// ROM file
+    rom: String,
+}
+
+/// Interactive debugger state
+struct Debugger {
+    nes: Nes,
+    history: Vec<u16>, // Recently stepped instructions
+}
+
+pub fn dispatch(opts: Options) {
+    let nes = Cartridge::from_path(&opts.rom)
+                .map_err(|e| e.to_string())
+                .and_then(|cart| Nes::default().try_with_cart(cart).map_err(|e| e.to_string()));
+
+    let nes = match nes {
+        Ok(nes) => nes,
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", opts.rom, e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(port) = opts.gdb {
+        serve_gdb(nes, port);
+        return;
+    }
+
+    let mut debugger = Debugger { nes, history: vec![] };
+
+    // Run through reset so the PC is at the entry point
+    debugger.nes.step_instruction();
+    debugger.print_location();
+
+    let stdin = io::stdin();
+    let mut last_command = String::new();
+
+    loop {
+        print!("(nes) ");
+        io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+
+        let line = if line.trim().is_empty() { last_command.clone() } else { line.trim().to_string() };
+        if line.is_empty() {
+            continue;
+        }
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match debugger.execute(&args) {
+            Ok(true) => break,
+            Ok(false) => {},
+            Err(e) => println!("{}", e),
+        }
+
+        last_command = line;
+    }
+}
+
+fn serve_gdb(mut nes: Nes, port: u16) {
+    let result = GdbServer::bind(("127.0.0.1", port)).and_then(|server| {
+        println!("Waiting for a debugger on port {}", port);
+        server.serve(&mut nes)
+    });
+
+    if let Err(e) = result {
+        eprintln!("GDB server error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+impl Debugger {
+    /// Run a command. Returns true if the debugger should exit
+    fn execute(&mut self, args: &[&str]) -> Result<bool, String> {
+        match args[0] {
+            "s" | "step" => {
+                let count = parse_count(args.get(1), 1)?;
+                for _ in 0..count {
+                    self.step();
+                }
+                self.print_location();
+            },
+            "n" | "next" => {
+                self.next();
+                self.print_location();
+            },
+            "c" | "continue" => {
+                let frames = parse_count(args.get(1), DEFAULT_CONTINUE_FRAMES as usize)? as u64;
+                self.continue_for(frames);
+            },
+            "b" | "break" => {
+                let addr = parse_addr(args.get(1))?;
+                self.add_breakpoint(Breakpoint::Execute(addr));
+            },
+            "w" | "watch" => {
+                let (start, end) = parse_range(args)?;
+                self.add_breakpoint(Breakpoint::Write(start, end));
+            },
+            "rw" | "rwatch" => {
+                let (start, end) = parse_range(args)?;
+                self.add_breakpoint(Breakpoint::Read(start, end));
+            },
+            "vw" | "vwatch" => {
+                let (start, end) = parse_range(args)?;
+                self.add_breakpoint(Breakpoint::VramWrite(start, end));
+            },
+            "d" | "delete" => {
+                let id = args.get(1).ok_or_else(|| "Expected a breakpoint id".to_string())?;
+                let id = id.parse().map_err(|_| format!("Invalid breakpoint id `{}`", id))?;
+                if !self.nes.remove_breakpoint(id) {
+                    return Err(format!("No breakpoint {}", id));
+                }
+            },
+            "bl" | "breakpoints" => self.print_breakpoints(),
+            "r" | "regs" => self.print_registers(),
+            "x" | "mem" => {
+                let addr = parse_addr(args.get(1))?;
+                let len = parse_count(args.get(2), DEFAULT_MEM_LEN as usize)?;
+                if len > u16::MAX as usize {
+                    return Err(format!("Length must be at most {}", u16::MAX));
+                }
+                self.print_memory(addr, len as u16);
+            },
+            "l" | "list" => {
+                let pc = self.nes.get_program_counter();
+                let addr = args.get(1).map(|arg| parse_addr(Some(arg))).unwrap_or(Ok(pc))?;
+                let count = parse_count(args.get(2), DEFAULT_LIST_COUNT)?;
+                self.print_disassembly(addr, count);
+            },
+            "ppu" => self.print_ppu(),
+            "stack" => self.print_stack(),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(true),
+            cmd => return Err(format!("Unknown command `{}`. Try `help`", cmd)),
+        }
+
+        Ok(false)
+    }
+
+    fn step(&mut self) {
+        self.push_history(self.nes.get_program_counter());
+        self.nes.step_instruction();
+    }
+
+    /// Step over JSR by running until the instruction after it
+    fn next(&mut self) {
+        let pc = self.nes.get_program_counter();
+        let is_jsr = !is_io(pc) && matches!(asm::decode(self.nes.read_cpu_ram(pc)), (asm::Instruction::JSR, _));
+
+        if is_jsr {
+            self.push_history(pc);
+            self.nes.step_instruction();
+
+            match self.nes.run_until_bounded(pc.wrapping_add(3), Budget::Frames(DEFAULT_CONTINUE_FRAMES)) {
+                StopReason::Breakpoint(hit) => println!("{}", describe_hit(&hit)),
+                StopReason::BudgetExhausted => println!("Subroutine did not return"),
+                StopReason::Jammed(addr) => println!("CPU jammed at ${:04X}", addr),
+                _ => {},
+            }
+        }
+        else {
+            self.step();
+        }
+    }
+
+    fn continue_for(&mut self, frames: u64) {
+        self.history.clear();
+
+        for _ in 0..frames {
+            self.nes.emulate_frame();
+
+            if let Some(hit) = self.nes.breakpoint_hit() {
+                println!("{}", describe_hit(&hit));
+                self.print_location();
+                return;
+            }
+
+            if let Some(addr) = self.nes.jammed() {
+                println!("CPU jammed at ${:04X}", addr);
+                self.print_location();
+                return;
+            }
+        }
+
+        println!("No breakpoint hit after {} frames", frames);
+        self.print_location();
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        let id = self.nes.add_breakpoint(breakpoint);
+        println!("{}: {}", id, describe_breakpoint(&breakpoint));
+    }
+
+    fn push_history(&mut self, pc: u16) {
+        self.history.push(pc);
+        if self.history.len() > HISTORY_LEN {
+            self.history.remove(0);
+        }
+    }
+
+    /// Show recently executed instructions, the next instruction and the registers
+    fn print_location(&self) {
+        for addr in &self.history {
+            println!("  {}", self.disassemble(*addr).0);
+        }
+
+        let pc = self.nes.get_program_counter();
+        println!("> {}", self.disassemble(pc).0);
+
+        self.print_registers();
+    }
+
+    fn print_registers(&self) {
+        let regs = self.nes.get_cpu_registers();
+        let (scanline, dot) = self.nes.get_ppu_position();
+
+        println!("A:{:02X} X:{:02X} Y:{:02X} P:{:02X} [{}] SP:{:02X} PC:{:04X}  PPU:{:3},{:3}",
+                 regs.a, regs.x, regs.y, regs.p, flags(regs.p), regs.sp, regs.pc, scanline, dot);
+    }
+
+    fn print_disassembly(&self, addr: u16, count: usize) {
+        let pc = self.nes.get_program_counter();
+        let mut addr = addr;
+
+        for _ in 0..count {
+            let (line, len) = self.disassemble(addr);
+            let marker = if addr == pc { ">" } else { " " };
+            println!("{} {}", marker, line);
+
+            addr = addr.wrapping_add(len);
+        }
+    }
+
+    /// Disassemble the instruction at the address. Returns the line and instruction length
+    fn disassemble(&self, addr: u16) -> (String, u16) {
+        // Reading IO registers has side effects
+        if is_io(addr) {
+            return (format!("{:04X}        ??  ???", addr), 1);
+        }
+
+        let (instr, mode) = asm::decode(self.nes.read_cpu_ram(addr));
+        let len = mode.operand_len();
+
+        if (1..=len).any(|i| is_io(addr.wrapping_add(i as u16))) {
+            return (format!("{:04X}        ??  ???", addr), 1);
+        }
+
+        let data: Vec<u8> = (0..=len).map(|i| self.nes.read_cpu_ram(addr.wrapping_add(i as u16))).collect();
+
+        let line = format!("{:04X}  {}  {}", addr, asm::operands(&data, len), asm::disassemble(instr, mode, &data[1..]));
+        (line, (len + 1) as u16)
+    }
+
+    fn print_memory(&self, addr: u16, len: u16) {
+        for row in (0..len).step_by(16) {
+            let row_addr = addr.wrapping_add(row);
+            let bytes: Vec<String> = (0..16.min(len - row)).map(|i| {
+                let addr = row_addr.wrapping_add(i);
+                // Reading IO registers has side effects
+                if is_io(addr) { "--".to_string() } else { format!("{:02X}", self.nes.read_cpu_ram(addr)) }
+            }).collect();
+
+            println!("{:04X}: {}", row_addr, bytes.join(" "));
+        }
+    }
+
+    fn print_stack(&self) {
+        let sp = self.nes.get_cpu_registers().sp;
+
+        if sp == 0xFF {
+            println!("Stack is empty");
+            return;
+        }
+
+        for offset in (sp as u16 + 1)..=0xFF {
+            let addr = 0x100 + offset;
+            println!("{:04X}: {:02X}", addr, self.nes.read_cpu_ram(addr));
+        }
+    }
+
+    fn print_ppu(&self) {
+        let regs = self.nes.get_ppu_registers();
+        let (scanline, dot) = self.nes.get_ppu_position();
+
+        println!("PPUCTRL:   {:02X}", regs.ctrl);
+        println!("PPUMASK:   {:02X}", regs.mask);
+        println!("PPUSTATUS: {:02X}", regs.status);
+        println!("OAMADDR:   {:02X}", regs.oam_addr);
+        println!("v: {:04X} t: {:04X} x: {} w: {}", regs.v, regs.t, regs.x, regs.w as u8);
+        println!("Scanline: {} Dot: {}", scanline, dot);
+    }
+
+    fn print_breakpoints(&self) {
+        let breakpoints = self.nes.breakpoints();
+
+        if breakpoints.is_empty() {
+            println!("No breakpoints");
+        }
+
+        for (id, breakpoint) in breakpoints {
+            println!("{}: {}", id, describe_breakpoint(&breakpoint));
+        }
+    }
+}
+
+fn describe_breakpoint(breakpoint: &Breakpoint) -> String {
+    match *breakpoint {
+        Breakpoint::Execute(addr) => format!("break ${:04X}", addr),
+        Breakpoint::Read(start, end) => format!("read ${:04X}-${:04X}", start, end),
+        Breakpoint::Write(start, end) => format!("write ${:04X}-${:04X}", start, end),
+        Breakpoint::VramWrite(start, end) => format!("vram write ${:04X}-${:04X}", start, end),
+    }
+}
+
+fn describe_hit(hit: &BreakpointHit) -> String {
+    match hit.value {
+        Some(value) => format!("Hit {} ({}): ${:04X} = ${:02X} by ${:04X}",
+                               hit.id, describe_breakpoint(&hit.breakpoint), hit.addr, value, hit.pc),
+        None => format!("Hit {} ({})", hit.id, describe_breakpoint(&hit.breakpoint)),
+    }
+}
+
+/// Status flags as NV-BDIZC, with clear flags shown as `-`
+fn flags(p: u8) -> String {
+    "NV-BDIZC".chars().enumerate().map(|(i, c)| {
+        if p & (0x80 >> i) != 0 { c } else { '-' }
+    }).collect()
+}
+
+/// PPU, APU and IO registers
+fn is_io(addr: u16) -> bool {
+    (0x2000..=0x401F).contains(&addr)
+}
+
+fn parse_addr(arg: Option<&&str>) -> Result<u16, String> {
+    let arg = arg.ok_or_else(|| "Expected an address".to_string())?;
+    let digits = arg.trim_start_matches('$').trim_start_matches("0x");
+
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address `{}`", arg))
+}
+
+fn parse_range(args: &[&str]) -> Result<(u16, u16), String> {
+    let start = parse_addr(args.get(1))?;
+    let end = if args.len() > 2 { parse_addr(args.get(2))? } else { start };
+
+    if end < start {
+        return Err(format!("Invalid range ${:04X}-${:04X}", start, end));
+    }
+
+    Ok((start, end))
+}
+
+fn parse_count(arg: Option<&&str>, default: usize) -> Result<usize, String> {
+    match arg {
+        Some(arg) => arg.parse().map_err(|_| format!("Invalid number `{}`", arg)),
+        None => Ok(default),
+    }
+}
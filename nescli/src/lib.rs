@@ -12,12 +12,13 @@ pub mod img;
 pub mod apu;
 pub mod audio;
 pub mod perf;
+pub mod debug;
 
 use clap::Clap;
 
 #[derive(Clap)]
 pub enum Command {
-    /// Run the specified ROM file
+    /// Run the specified ROM file (Press R to reset, P to power cycle)
     #[clap(name = "run", version = "1.0", author = "Natesh Narain")]
     Run(run::Options),
     /// Dump the header information of the specified ROM file
@@ -35,6 +36,9 @@ pub enum Command {
     /// Do nothing but run the emulator
     #[clap(name = "perf", version = "1.0", author = "Natesh Narain")]
     Perf(perf::Options),
+    /// Interactive debugger
+    #[clap(name = "debug", version = "1.0", author = "Natesh Narain")]
+    Debug(debug::Options),
 }
 
 #[derive(Clap)]
@@ -17,5 +17,6 @@ fn main() {
         Command::Apu(opts)   => nescli::apu::dispatch(opts),
         Command::Audio(opts) => nescli::audio::dispatch(opts),
         Command::Perf(opts)  => nescli::perf::dispatch(opts),
+        Command::Debug(opts) => nescli::debug::dispatch(opts),
     }
 }
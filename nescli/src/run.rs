@@ -13,8 +13,9 @@ use sdl2::rect::Rect;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 
-use nescore::{Nes, CartridgeLoader, Button};
-use nescore::specs::{DISPLAY_WIDTH, DISPLAY_HEIGHT};
+use nescore::{Nes, CartridgeLoader, Button, Region, Palette, BuiltinPalette, NtscPaletteParams};
+use nescore::specs::{DISPLAY_WIDTH, DISPLAY_HEIGHT, PixelFormat};
+use nescore::utils::ntsc::{NtscFilter, NtscSetup, NTSC_DISPLAY_WIDTH};
 
 use std::io::prelude::*;
 use std::fs::File;
@@ -26,6 +27,11 @@ use crate::common::audio::AudioStreamSource;
 const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 600;
 
+// A rewind snapshot is taken every other frame
+const REWIND_INTERVAL: usize = 2;
+// Frames to rewind, before emulating the next frame, while the rewind key is held
+const REWIND_FRAMES: usize = 5;
+
 #[derive(Clap, Debug)]
 pub struct Options {
     /// Debug mode
@@ -34,19 +40,88 @@ pub struct Options {
     /// Enable saves
     #[clap(short = 's')]
     pub save: bool,
+    /// Length of the rewind buffer in seconds (Hold backspace to rewind)
+    #[clap(long = "rewind", default_value = "10")]
+    pub rewind: usize,
+    /// Console region: auto, ntsc, pal or dendy
+    #[clap(long = "region", default_value = "auto")]
+    pub region: String,
+    /// Palette: 2c02, 2c03, 2c05, 2c07, ntsc (generated) or the path to a .pal file. Defaults to the region's palette
+    #[clap(long = "palette")]
+    pub palette: Option<String>,
+    /// NTSC video filter: composite, svideo or rgb
+    #[clap(long = "ntsc")]
+    pub ntsc: Option<String>,
+    /// Hue rotation in degrees of the generated NTSC palette or NTSC filter
+    #[clap(long = "hue", default_value = "0.0")]
+    pub hue: f64,
+    /// Saturation of the generated NTSC palette or NTSC filter
+    #[clap(long = "saturation", default_value = "1.0")]
+    pub saturation: f64,
+    /// Contrast of the generated NTSC palette or NTSC filter
+    #[clap(long = "contrast", default_value = "1.0")]
+    pub contrast: f64,
+    /// Brightness of the generated NTSC palette or NTSC filter
+    #[clap(long = "brightness", default_value = "0.0")]
+    pub brightness: f64,
+    /// Draw all sprites on a scanline instead of at most 8 (reduces flicker)
+    #[clap(long = "no-sprite-limit")]
+    pub no_sprite_limit: bool,
     /// The ROM file to run
     pub rom: String,
 }
 
 pub fn dispatch(opts: Options) {
+    let region = match parse_region(&opts.region) {
+        Ok(region) => region,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let palette = match load_palette(&opts) {
+        Ok(palette) => palette,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut ntsc = match ntsc_setup(&opts) {
+        Ok(setup) => setup.map(NtscFilter::new),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let save_file_path = format!("{}.sav", &opts.rom);
 
-    let mut nes = CartridgeLoader::default()
+    let nes = CartridgeLoader::default()
                         .rom_path(&opts.rom)
                         .save_path(&save_file_path)
                         .load()
-                        .map(|cart| Nes::from(cart).debug_mode(opts.debug))
-                        .unwrap();
+                        .map_err(|e| e.to_string())
+                        .and_then(|cart| Nes::default().try_with_cart(cart).map_err(|e| e.to_string()));
+
+    let mut nes = match nes {
+        Ok(mut nes) => {
+            nes.set_region(region);
+            nes.set_palette(palette);
+            nes.set_sprite_limit(!opts.no_sprite_limit);
+
+            let rewind_frames = (opts.rewind as f64 * nes.get_region().frame_rate()) as usize;
+            let nes = nes.debug_mode(opts.debug).rewind_buffer(rewind_frames / REWIND_INTERVAL, REWIND_INTERVAL);
+
+            // The NTSC filter works on palette indices
+            if ntsc.is_some() { nes.pixel_format(PixelFormat::Indexed) } else { nes }
+        },
+        Err(e) => {
+            eprintln!("Failed to load {}: {}", opts.rom, e);
+            std::process::exit(1);
+        }
+    };
 
     // Setup console logger
     let cpu_events = nes.cpu_event_channel();
@@ -70,8 +145,10 @@ pub fn dispatch(opts: Options) {
     let mut canvas = window.into_canvas().build().unwrap();
     let texture_creator = canvas.texture_creator();
 
+    let display_width = if ntsc.is_some() { NTSC_DISPLAY_WIDTH } else { DISPLAY_WIDTH };
+
     let mut display = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24,
-                                                               DISPLAY_WIDTH as u32,
+                                                               display_width as u32,
                                                                DISPLAY_HEIGHT as u32).unwrap();
 
     let desired_spec = AudioSpecDesired {
@@ -81,7 +158,7 @@ pub fn dispatch(opts: Options) {
     };
 
     let mut audio_device = audio_subsystem.open_playback(None, &desired_spec, |_| {
-        AudioStreamSource::default()
+        AudioStreamSource::new(nes.get_region().apu_output_rate())
     }).unwrap();
 
     audio_device.resume();
@@ -90,6 +167,8 @@ pub fn dispatch(opts: Options) {
 
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    let mut rewinding = false;
+
     'running: loop {
         let instant = Instant::now();
 
@@ -98,6 +177,18 @@ pub fn dispatch(opts: Options) {
                 Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                    rewinding = true;
+                },
+                Event::KeyUp { keycode: Some(Keycode::Backspace), .. } => {
+                    rewinding = false;
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), repeat: false, .. } => {
+                    nes.reset();
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), repeat: false, .. } => {
+                    nes.power_cycle();
+                },
                 Event::KeyDown {keycode, ..} => {
                     let btn = keycode.map(map_nes_key).flatten();
                     if let Some(btn) = btn {
@@ -114,10 +205,15 @@ pub fn dispatch(opts: Options) {
             }
         }
 
+        if rewinding {
+            nes.rewind(REWIND_FRAMES);
+        }
+
         // Run the nescore for a single frame
         let (framebuffer, samplebuffer) = nes.emulate_frame();
 
-        {
+        // Audio played in reverse is just noise
+        if !rewinding {
             // update audio stream
             let mut audio_lock = audio_device.lock();
             audio_lock.update(samplebuffer);
@@ -126,14 +222,19 @@ pub fn dispatch(opts: Options) {
         // Update screen
         canvas.clear();
 
+        let framebuffer = match ntsc {
+            Some(ref mut filter) => filter.filter(framebuffer),
+            None => framebuffer,
+        };
+
         // Update the on screen texture
-        display.update(None, framebuffer, DISPLAY_WIDTH * 3).unwrap();
+        display.update(None, framebuffer, display_width * 3).unwrap();
         // Update the canvas
         canvas.copy(&display, None, Some(Rect::new(0, 0, WINDOW_WIDTH, WINDOW_HEIGHT))).unwrap();
 
         canvas.present();
 
-        let refresh_duration = Duration::from_millis(16);
+        let refresh_duration = Duration::from_secs_f64(1.0 / nes.get_region().frame_rate());
 
         let sleep_duration = if instant.elapsed() > refresh_duration {
             Duration::from_millis(1)
@@ -157,6 +258,57 @@ pub fn dispatch(opts: Options) {
     }
 }
 
+fn parse_region(region: &str) -> Result<Option<Region>, String> {
+    match region.to_lowercase().as_str() {
+        "auto" => Ok(None),
+        "ntsc" => Ok(Some(Region::Ntsc)),
+        "pal" => Ok(Some(Region::Pal)),
+        "dendy" => Ok(Some(Region::Dendy)),
+        _ => Err(format!("Invalid region: {}", region)),
+    }
+}
+
+fn ntsc_setup(opts: &Options) -> Result<Option<NtscSetup>, String> {
+    let setup = match opts.ntsc.as_deref().map(str::to_lowercase).as_deref() {
+        Some("composite") => NtscSetup::composite(),
+        Some("svideo") => NtscSetup::svideo(),
+        Some("rgb") => NtscSetup::rgb(),
+        Some(name) => return Err(format!("Invalid NTSC filter: {}", name)),
+        None => return Ok(None),
+    };
+
+    Ok(Some(NtscSetup {
+        hue: opts.hue,
+        saturation: opts.saturation,
+        contrast: opts.contrast,
+        brightness: opts.brightness,
+        ..setup
+    }))
+}
+
+fn load_palette(opts: &Options) -> Result<Option<Palette>, String> {
+    let name = match opts.palette {
+        Some(ref name) => name,
+        None => return Ok(None),
+    };
+
+    if name.to_lowercase() == "ntsc" {
+        let params = NtscPaletteParams {
+            hue: opts.hue,
+            saturation: opts.saturation,
+            contrast: opts.contrast,
+            brightness: opts.brightness,
+        };
+
+        return Ok(Some(Palette::generate(params)));
+    }
+
+    match name.parse::<BuiltinPalette>() {
+        Ok(builtin) => Ok(Some(Palette::from(builtin))),
+        Err(_) => Palette::from_path(name).map(Some).map_err(|e| e.to_string()),
+    }
+}
+
 fn map_nes_key(keycode: Keycode) -> Option<Button> {
     match keycode {
         Keycode::W => Some(Button::Up),
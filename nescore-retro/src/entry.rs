@@ -0,0 +1,150 @@
+//
+// entry.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 20 2021
+//
+// libretro entry points. These are the same as the ones generated by `libretro_core!`, except the environment
+// callback is kept so core options can be registered and queried
+//
+
+use libretro_backend::{Retro, construct, libc, libretro_sys};
+
+use crate::NescoreRetro;
+use crate::options;
+
+static mut LIBRETRO_INSTANCE: *mut Retro<NescoreRetro> = std::ptr::null_mut();
+
+unsafe fn instance() -> &'static mut Retro<NescoreRetro> {
+    assert!(!LIBRETRO_INSTANCE.is_null());
+    &mut *LIBRETRO_INSTANCE
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> libc::c_uint {
+    libretro_sys::API_VERSION
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_init() {
+    assert!(LIBRETRO_INSTANCE.is_null());
+    LIBRETRO_INSTANCE = Box::into_raw(Box::new(construct::<NescoreRetro>()));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_deinit() {
+    assert!(!LIBRETRO_INSTANCE.is_null());
+    drop(Box::from_raw(LIBRETRO_INSTANCE));
+    LIBRETRO_INSTANCE = std::ptr::null_mut();
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_environment(callback: libretro_sys::EnvironmentFn) {
+    Retro::<NescoreRetro>::on_set_environment(callback);
+    options::register(callback);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_video_refresh(callback: libretro_sys::VideoRefreshFn) {
+    instance().on_set_video_refresh(callback)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_audio_sample(callback: libretro_sys::AudioSampleFn) {
+    instance().on_set_audio_sample(callback)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_audio_sample_batch(callback: libretro_sys::AudioSampleBatchFn) {
+    instance().on_set_audio_sample_batch(callback)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_input_poll(callback: libretro_sys::InputPollFn) {
+    instance().on_set_input_poll(callback)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_input_state(callback: libretro_sys::InputStateFn) {
+    instance().on_set_input_state(callback)
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_system_info(info: *mut libretro_sys::SystemInfo) {
+    Retro::<NescoreRetro>::on_get_system_info(info)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut libretro_sys::SystemAvInfo) {
+    instance().on_get_system_av_info(info)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_set_controller_port_device(port: libc::c_uint, device: libc::c_uint) {
+    instance().on_set_controller_port_device(port, device)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_reset() {
+    instance().on_reset()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_run() {
+    instance().on_run()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize_size() -> libc::size_t {
+    instance().on_serialize_size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut libc::c_void, size: libc::size_t) -> bool {
+    instance().on_serialize(data, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const libc::c_void, size: libc::size_t) -> bool {
+    instance().on_unserialize(data, size)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_reset() {
+    instance().on_cheat_reset()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: libc::c_uint, is_enabled: bool, code: *const libc::c_char) {
+    instance().on_cheat_set(index, is_enabled, code)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const libretro_sys::GameInfo) -> bool {
+    instance().on_load_game(game)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game_special(game_type: libc::c_uint, info: *const libretro_sys::GameInfo, num_info: libc::size_t) -> bool {
+    instance().on_load_game_special(game_type, info, num_info)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unload_game() {
+    instance().on_unload_game()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_region() -> libc::c_uint {
+    instance().on_get_region()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_memory_data(id: libc::c_uint) -> *mut libc::c_void {
+    instance().on_get_memory_data(id)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_memory_size(id: libc::c_uint) -> libc::size_t {
+    instance().on_get_memory_size(id)
+}
@@ -1,18 +1,22 @@
-use nescore::{Nes, Cartridge, Button,
-    specs::{DISPLAY_HEIGHT, DISPLAY_WIDTH, APU_OUTPUT_RATE, PixelFormat as NesCorePixelFormat},
-    utils::sampler::DownSampler
+use nescore::{Nes, Cartridge, Button, Region as NesCoreRegion,
+    specs::{DISPLAY_HEIGHT, DISPLAY_WIDTH, PixelFormat as NesCorePixelFormat},
+    utils::sampler::DownSampler,
+    utils::ntsc::{NtscFilter, RgbFormat, NTSC_DISPLAY_WIDTH},
 };
 use libretro_backend::{
     AudioVideoInfo, Core, CoreInfo, GameData, LoadGameResult,
     PixelFormat, Region, RuntimeHandle, JoypadButton,
-    libretro_core
 };
 
+mod entry;
+mod options;
+
 const HOST_PLAYBACK_RATE: f64 = 44100.0;
 
-struct NescoreRetro {
+pub struct NescoreRetro {
     core: Nes,
     game_data: Option<GameData>,
+    ntsc: Option<NtscFilter>,
 }
 
 impl Default for NescoreRetro {
@@ -20,6 +24,7 @@ impl Default for NescoreRetro {
         NescoreRetro {
             core: Nes::default().pixel_format(NesCorePixelFormat::BGRA8),
             game_data: None,
+            ntsc: None,
         }
     }
 }
@@ -47,19 +52,44 @@ impl Core for NescoreRetro {
 
             match cart {
                 Ok(cart) => {
-                    self.game_data = Some(game_data);
-                    let tv_system = if cart.info.tv_system_pal { Region::PAL } else { Region::NTSC };
-                    // FIXME: This will panic on invalid cartridge type
-                    self.core.insert(cart);
-
-                    LoadGameResult::Success(
-                        AudioVideoInfo::new()
-                            .video(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32, 60.0, PixelFormat::ARGB8888)
-                            .audio(HOST_PLAYBACK_RATE)
-                            .region(tv_system)
-                    )
+                    match self.core.try_insert(cart) {
+                        Ok(_) => {
+                            // Start from power up, a previous game may have been running
+                            self.core.power_cycle();
+                            self.core.set_palette(options::palette());
+                            self.core.set_sprite_limit(options::sprite_limit());
+
+                            // The output size is fixed once the game is loaded, so the filter can only change here
+                            self.ntsc = options::ntsc_setup().map(|setup| NtscFilter::new(setup).pixel_format(RgbFormat::BGRA8));
+                            self.core.set_pixel_format(if self.ntsc.is_some() { NesCorePixelFormat::Indexed } else { NesCorePixelFormat::BGRA8 });
+
+                            let width = if self.ntsc.is_some() { NTSC_DISPLAY_WIDTH } else { DISPLAY_WIDTH };
+                            self.game_data = Some(game_data);
+
+                            let region = self.core.get_region();
+                            let tv_system = match region {
+                                NesCoreRegion::Ntsc => Region::NTSC,
+                                NesCoreRegion::Pal | NesCoreRegion::Dendy => Region::PAL,
+                            };
+
+                            LoadGameResult::Success(
+                                AudioVideoInfo::new()
+                                    .video(width as u32, DISPLAY_HEIGHT as u32, region.frame_rate(), PixelFormat::ARGB8888)
+                                    .aspect_ratio(DISPLAY_WIDTH as f32 / DISPLAY_HEIGHT as f32)
+                                    .audio(HOST_PLAYBACK_RATE)
+                                    .region(tv_system)
+                            )
+                        },
+                        Err(e) => {
+                            eprintln!("Failed to load game: {}", e);
+                            LoadGameResult::Failed(game_data)
+                        },
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Failed to load game: {}", e);
+                    LoadGameResult::Failed(game_data)
                 },
-                Err(_) => LoadGameResult::Failed(game_data),
             }
         }
     }
@@ -69,6 +99,11 @@ impl Core for NescoreRetro {
     }
 
     fn on_run(&mut self, handle: &mut RuntimeHandle) {
+        if options::updated() {
+            self.core.set_palette(options::palette());
+            self.core.set_sprite_limit(options::sprite_limit());
+        }
+
         // Handle joypad key presses
         for btn in [JoypadButton::A, JoypadButton::B,
                     JoypadButton::Up, JoypadButton::Down, JoypadButton::Left, JoypadButton::Right,
@@ -81,15 +116,19 @@ impl Core for NescoreRetro {
 
         // Run for a full frame
         let (framebuffer, audiobuffer) = self.core.emulate_frame();
-        handle.upload_video_frame(framebuffer);
+        match self.ntsc {
+            Some(ref mut filter) => handle.upload_video_frame(filter.filter(framebuffer)),
+            None => handle.upload_video_frame(framebuffer),
+        }
 
         // process audio to match host system and libretro api
         // downsample apu output
         // convert to i16 data type
         // convert to stereo
+        let apu_output_rate = self.core.get_region().apu_output_rate();
         let audiobuffer: Vec<i16> = {
             let mut buf: Vec<i16> = Vec::new();
-            for sample in DownSampler::new(audiobuffer, APU_OUTPUT_RATE, HOST_PLAYBACK_RATE as f32)
+            for sample in DownSampler::new(audiobuffer, apu_output_rate, HOST_PLAYBACK_RATE as f32)
                                         .into_iter()
                                         .map(|sample| sample as i16) {
                 buf.push(sample);
@@ -102,7 +141,7 @@ impl Core for NescoreRetro {
     }
 
     fn on_reset(&mut self) {
-        // TODO: Allow resetting the nescore
+        self.core.reset();
     }
 
     fn save_memory(&mut self) -> Option< &mut [u8] > {
@@ -122,5 +161,3 @@ fn map_joypad(button: JoypadButton) -> Result<Button, ()> {
         _ => Err(()),
     }
 }
-
-libretro_core!(NescoreRetro);
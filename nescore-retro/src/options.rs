@@ -0,0 +1,143 @@
+//
+// options.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 20 2021
+//
+// Core options presented by the frontend
+//
+
+use libretro_backend::{libc, libretro_sys};
+use nescore::{Palette, BuiltinPalette, NtscPaletteParams};
+use nescore::utils::ntsc::NtscSetup;
+
+use std::ffi::CStr;
+use std::ptr;
+
+const PALETTE_KEY: &[u8] = b"nescore_palette\0";
+const PALETTE_VALUES: &[u8] = b"Palette; auto|2c02|2c03|2c05|2c07|ntsc\0";
+const NTSC_HUE_KEY: &[u8] = b"nescore_ntsc_hue\0";
+const NTSC_HUE_VALUES: &[u8] = b"NTSC palette hue (degrees); 0|5|10|15|20|25|30|-30|-25|-20|-15|-10|-5\0";
+const NTSC_SATURATION_KEY: &[u8] = b"nescore_ntsc_saturation\0";
+const NTSC_SATURATION_VALUES: &[u8] = b"NTSC palette saturation; 1.0|1.1|1.2|1.3|1.4|1.5|1.6|1.7|1.8|1.9|2.0|0.0|0.1|0.2|0.3|0.4|0.5|0.6|0.7|0.8|0.9\0";
+const NTSC_CONTRAST_KEY: &[u8] = b"nescore_ntsc_contrast\0";
+const NTSC_CONTRAST_VALUES: &[u8] = b"NTSC palette contrast; 1.0|1.1|1.2|1.3|1.4|1.5|0.5|0.6|0.7|0.8|0.9\0";
+const NTSC_BRIGHTNESS_KEY: &[u8] = b"nescore_ntsc_brightness\0";
+const NTSC_BRIGHTNESS_VALUES: &[u8] = b"NTSC palette brightness; 0.0|0.05|0.1|0.15|0.2|-0.2|-0.15|-0.1|-0.05\0";
+const NTSC_FILTER_KEY: &[u8] = b"nescore_ntsc_filter\0";
+const NTSC_FILTER_VALUES: &[u8] = b"NTSC filter (restart); disabled|composite|svideo|rgb\0";
+const SPRITE_LIMIT_KEY: &[u8] = b"nescore_sprite_limit\0";
+const SPRITE_LIMIT_VALUES: &[u8] = b"Sprite limit; enabled|disabled\0";
+
+static mut ENVIRONMENT: Option<libretro_sys::EnvironmentFn> = None;
+
+/// Keep the environment callback and declare the core options
+pub unsafe fn register(callback: libretro_sys::EnvironmentFn) {
+    ENVIRONMENT = Some(callback);
+
+    let variables = [
+        libretro_sys::Variable {
+            key: PALETTE_KEY.as_ptr() as *const libc::c_char,
+            value: PALETTE_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: NTSC_HUE_KEY.as_ptr() as *const libc::c_char,
+            value: NTSC_HUE_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: NTSC_SATURATION_KEY.as_ptr() as *const libc::c_char,
+            value: NTSC_SATURATION_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: NTSC_CONTRAST_KEY.as_ptr() as *const libc::c_char,
+            value: NTSC_CONTRAST_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: NTSC_BRIGHTNESS_KEY.as_ptr() as *const libc::c_char,
+            value: NTSC_BRIGHTNESS_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: NTSC_FILTER_KEY.as_ptr() as *const libc::c_char,
+            value: NTSC_FILTER_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: SPRITE_LIMIT_KEY.as_ptr() as *const libc::c_char,
+            value: SPRITE_LIMIT_VALUES.as_ptr() as *const libc::c_char,
+        },
+        libretro_sys::Variable {
+            key: ptr::null(),
+            value: ptr::null(),
+        },
+    ];
+
+    callback(libretro_sys::ENVIRONMENT_SET_VARIABLES, variables.as_ptr() as *mut libc::c_void);
+}
+
+/// Check if the frontend has changed any options since they were last read
+pub fn updated() -> bool {
+    let mut updated = false;
+
+    match unsafe { ENVIRONMENT } {
+        Some(callback) => unsafe {
+            callback(libretro_sys::ENVIRONMENT_GET_VARIABLE_UPDATE, &mut updated as *mut bool as *mut libc::c_void) && updated
+        },
+        None => false,
+    }
+}
+
+/// The selected palette. `None` uses the region's palette
+pub fn palette() -> Option<Palette> {
+    match get_variable(PALETTE_KEY)?.as_str() {
+        "ntsc" => Some(Palette::generate(ntsc_params())),
+        name => name.parse::<BuiltinPalette>().ok().map(Palette::from),
+    }
+}
+
+/// Parameters of the generated NTSC palette
+fn ntsc_params() -> NtscPaletteParams {
+    let defaults = NtscPaletteParams::default();
+
+    NtscPaletteParams {
+        hue: get_number(NTSC_HUE_KEY).unwrap_or(defaults.hue),
+        saturation: get_number(NTSC_SATURATION_KEY).unwrap_or(defaults.saturation),
+        contrast: get_number(NTSC_CONTRAST_KEY).unwrap_or(defaults.contrast),
+        brightness: get_number(NTSC_BRIGHTNESS_KEY).unwrap_or(defaults.brightness),
+    }
+}
+
+/// The selected NTSC filter. `None` when the filter is disabled
+pub fn ntsc_setup() -> Option<NtscSetup> {
+    match get_variable(NTSC_FILTER_KEY)?.as_str() {
+        "composite" => Some(NtscSetup::composite()),
+        "svideo" => Some(NtscSetup::svideo()),
+        "rgb" => Some(NtscSetup::rgb()),
+        _ => None,
+    }
+}
+
+/// Whether rendering is limited to 8 sprites per scanline
+pub fn sprite_limit() -> bool {
+    get_variable(SPRITE_LIMIT_KEY).as_deref() != Some("disabled")
+}
+
+fn get_number(key: &[u8]) -> Option<f64> {
+    get_variable(key)?.parse().ok()
+}
+
+fn get_variable(key: &[u8]) -> Option<String> {
+    let callback = unsafe { ENVIRONMENT }?;
+
+    let mut variable = libretro_sys::Variable {
+        key: key.as_ptr() as *const libc::c_char,
+        value: ptr::null(),
+    };
+
+    let ok = unsafe { callback(libretro_sys::ENVIRONMENT_GET_VARIABLE, &mut variable as *mut libretro_sys::Variable as *mut libc::c_void) };
+
+    if ok && !variable.value.is_null() {
+        unsafe { CStr::from_ptr(variable.value) }.to_str().ok().map(String::from)
+    }
+    else {
+        None
+    }
+}
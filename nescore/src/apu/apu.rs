@@ -7,9 +7,12 @@
 use super::seq::{FrameSequencer, Event};
 use super::chnl::{SoundChannel, Pulse, Triangle, Noise, Dmc, LengthCounterUnit, EnvelopeUnit, NegateAddMode};
 
-use crate::common::{IoAccess, IoAccessRef, Clockable, Register, Interrupt};
+use crate::common::{IoAccess, IoAccessRef, Clockable, Register};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::region::Region;
 
 pub type Sample = f32;
+/// Rate of the raw APU output on NTSC systems. See `Region::apu_output_rate`
 pub const APU_OUTPUT_RATE: f32 = 895_000.0;
 
 #[cfg(feature="events")]
@@ -43,6 +46,8 @@ pub struct Apu {
 
     bus: Option<IoAccessRef>,
 
+    region: Region,
+
     // Event logging
     #[cfg(feature="events")]
     logger: Option<Sender<events::ApuEvent>>,
@@ -80,6 +85,8 @@ impl Default for Apu {
 
             bus: None,
 
+            region: Region::default(),
+
             #[cfg(feature="events")]
             logger: None,
         }
@@ -99,12 +106,8 @@ impl Clockable<Sample> for Apu {
                     self.clock_length();
                     self.clock_sweep();
                 },
-                Event::Irq => {
-                    if let Some(ref mut bus) = self.bus {
-                        bus.borrow_mut().raise_interrupt(Interrupt::Irq);
-                    }
-                },
-                Event::None => {}
+                // The frame IRQ flag is held by the sequencer and drives the IRQ line
+                Event::Irq | Event::None => {}
             }
         }
 
@@ -169,6 +172,18 @@ impl IoAccess for Apu {
             _ => panic!("Invalid address for APU: ${:04X}", addr),
         }
     }
+
+    fn irq_line(&self) -> bool {
+        self.sequencer.frame_irq() || self.dmc.irq()
+    }
+
+    fn dmc_dma_request(&self) -> Option<u16> {
+        self.dmc.dma_request()
+    }
+
+    fn dmc_dma_complete(&mut self, data: u8) {
+        self.dmc.load_sample(data);
+    }
 }
 
 impl Apu {
@@ -211,6 +226,7 @@ impl Apu {
         | (self.noise.length_status() as u8) << 3
         | (self.dmc.status() as u8) << 4
         | (self.sequencer.irq_status() as u8) << 6
+        | (self.dmc.irq() as u8) << 7
     }
 
     fn clock_length(&mut self) {
@@ -231,8 +247,37 @@ impl Apu {
         self.pulse2.clock_sweep();
     }
 
+    /// Reset the APU. Channels are silenced and the frame counter restarts in its current mode
+    pub fn reset(&mut self) {
+        self.write_byte(0x4015, 0x00);
+
+        let frame_counter = self.sequencer.value();
+        self.sequencer.load(frame_counter);
+    }
+
+    /// Return the APU to its power up state. The bus must be reloaded
+    pub fn power_on(&mut self) {
+        let region = self.region;
+
+        *self = Apu {
+            #[cfg(feature="events")]
+            logger: self.logger.take(),
+            ..Apu::default()
+        };
+
+        self.set_region(region);
+    }
+
+    /// Set the frame sequencer timing and noise and DMC period tables for the region
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+
+        self.sequencer.set_region(region);
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+    }
+
     pub fn load_bus(&mut self, bus: IoAccessRef) {
-        self.dmc.load_bus(bus.clone());
         self.bus = Some(bus);
     }
 
@@ -242,6 +287,28 @@ impl Apu {
     }
 }
 
+impl Snapshot for Apu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.pulse1.save_state(state);
+        self.pulse2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+
+        self.sequencer.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.pulse1.load_state(state)?;
+        self.pulse2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+
+        self.sequencer.load_state(state)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -287,6 +354,33 @@ mod tests {
         assert!(bit_is_clear!(status, 1));
     }
 
+    #[test]
+    fn save_state_round_trip() {
+        let mut apu = init_apu();
+
+        apu.write_byte(0x4015, 0x0F);
+        apu.write_byte(0x4000, 0xBF);
+        apu.write_byte(0x4002, 0x40);
+        apu.write_byte(0x4003, 0x00);
+        apu.write_byte(0x400E, 0x03);
+        apu.write_byte(0x400F, 0x00);
+
+        run_for_step4_frame(&mut apu);
+
+        let mut writer = StateWriter::default();
+        apu.save_state(&mut writer);
+        let data = writer.into_inner();
+
+        let expected: Vec<Sample> = (0..1000).map(|_| apu.tick()).collect();
+
+        let mut restored = init_apu();
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+
+        let actual: Vec<Sample> = (0..1000).map(|_| restored.tick()).collect();
+
+        assert_eq!(actual, expected);
+    }
+
     fn run_for_step4_frame(apu: &mut dyn Clockable<Sample>) {
         for _ in 0..14915 {
             apu.tick();
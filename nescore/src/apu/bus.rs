@@ -5,18 +5,16 @@
 // @date Jun 21 2020
 //
 
-use crate::common::{IoAccess, IoAccessRef, Interrupt};
+use crate::common::IoAccess;
 use crate::mapper::Mapper;
 
 pub struct ApuIoBus {
-    cpu: IoAccessRef,
     mapper: Mapper,
 }
 
 impl ApuIoBus {
-    pub fn new(cpu: IoAccessRef, mapper: Mapper) -> Self {
+    pub fn new(mapper: Mapper) -> Self {
         ApuIoBus {
-            cpu,
             mapper,
         }
     }
@@ -30,8 +28,4 @@ impl IoAccess for ApuIoBus {
     fn write_byte(&mut self, addr: u16, value: u8) {
         self.mapper.borrow_mut().write(addr, value);
     }
-
-    fn raise_interrupt(&mut self, interrupt_type: Interrupt) {
-        self.cpu.borrow_mut().raise_interrupt(interrupt_type);
-    }
 }
@@ -6,6 +6,7 @@
 //
 
 use crate::common::Clockable;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 /// Outputs a clock periodically
 pub struct Divider {
@@ -49,6 +50,20 @@ impl Divider {
     // }
 }
 
+impl Snapshot for Divider {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u32(self.counter);
+        state.write_u32(self.period);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.counter = state.read_u32()?;
+        self.period = state.read_u32()?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
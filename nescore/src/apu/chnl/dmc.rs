@@ -5,19 +5,26 @@
 // @date Jun 20 2020
 //
 
-use crate::common::{Clockable, IoAccess, IoAccessRef};
+use crate::common::{Clockable, IoAccess};
 use super::{SoundChannel, Timer};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::region::Region;
 
-// Frequency lookup table in CPU cycles
-const FREQ_LOOKUP: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+// Frequency lookup tables in CPU cycles
+const FREQ_LOOKUP_NTSC: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const FREQ_LOOKUP_PAL: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
 
+/// Delta modulation channel
+///
+/// Sample bytes are fetched by the CPU using DMA. The channel requests a fetch whenever the sample buffer is empty and
+/// bytes remain in the current sample
 #[derive(Default)]
 pub struct Dmc {
     irq_enabled: bool,
     loop_enabled: bool,
     sample_address: u16,
     sample_length: u16,
-    enabled: bool,
+    irq: bool,
 
     // Output unit
     bits_remaining: u8,
@@ -28,50 +35,25 @@ pub struct Dmc {
 
     timer: Timer,
 
+    // Memory reader
     sample_buffer: Option<u8>,
     current_addr: u16,
     remaining_bytes: u16,
 
-    bus: Option<IoAccessRef>,
+    region: Region,
 }
 
 impl Clockable for Dmc {
     fn tick(&mut self) {
         if self.timer.tick() {
-            if self.sample_buffer.is_none() && self.remaining_bytes > 0 {
-                if let Some(ref bus) = self.bus {
-                    self.sample_buffer = Some(bus.borrow().read_byte(self.current_addr));
-                }
-
-                // Advance sample address. Wrap around to $8000 if needed
-                if self.current_addr == 0xFFFF {
-                    self.current_addr = 0x8000;
-                }
-                else {
-                    self.current_addr += 1;
-                }
-
-                if self.remaining_bytes > 0 {
-
-                    self.remaining_bytes -= 1;
-
-                    if self.remaining_bytes == 0 {
-                        if self.loop_enabled {
-                            self.start_cycle();
-                        }
-                        else if self.irq_enabled {
-                            // TODO: Raise IRQ Interrupt
-                        }
-                    }
-                }
-            }
-
             // Output unit
-            if !self.silence && self.output >= 2 && self.output <= 125 {
+            if !self.silence {
                 if bit_is_set!(self.shift, 0) {
-                    self.output += 2;
+                    if self.output <= 125 {
+                        self.output += 2;
+                    }
                 }
-                else {
+                else if self.output >= 2 {
                     self.output -= 2;
                 }
             }
@@ -80,10 +62,10 @@ impl Clockable for Dmc {
 
             if self.bits_remaining > 0 {
                 self.bits_remaining -= 1;
+            }
 
-                if self.bits_remaining == 0 {
-                    self.start_cycle();
-                }
+            if self.bits_remaining == 0 {
+                self.start_output_cycle();
             }
         }
     }
@@ -106,9 +88,14 @@ impl IoAccess for Dmc {
             0 => {
                 self.irq_enabled = bit_is_set!(data, 7);
                 self.loop_enabled = bit_is_set!(data, 6);
-                self.timer.set_period(FREQ_LOOKUP[(data & 0x0F) as usize] / 2);
+                let lookup = if self.region == Region::Pal { &FREQ_LOOKUP_PAL } else { &FREQ_LOOKUP_NTSC };
+                self.timer.set_period(lookup[(data & 0x0F) as usize] / 2);
+
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
             },
-            1 => self.output = data & 0xEF,
+            1 => self.output = data & 0x7F,
             2 => self.sample_address = 0xC000 | (data as u16) << 6,
             3 => self.sample_length = (data as u16) << 4 | 0x01,
             _ => panic!("Invalid register for DMC"),
@@ -117,35 +104,230 @@ impl IoAccess for Dmc {
 }
 
 impl Dmc {
-    pub fn load_bus(&mut self, bus: IoAccessRef) {
-        self.bus = Some(bus);
+    /// Select the rate table for the region
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
     }
 
+    /// Enable or disable the channel using $4015. This also clears the interrupt flag
     pub fn set_enable(&mut self, e: bool) {
-        self.enabled = e;
+        self.irq = false;
+
+        if !e {
+            self.remaining_bytes = 0;
+        }
+        else if self.remaining_bytes == 0 {
+            self.restart();
+        }
+    }
+
+    /// Address of the next sample byte, if the sample buffer needs to be filled
+    pub fn dma_request(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.remaining_bytes > 0 {
+            Some(self.current_addr)
+        }
+        else {
+            None
+        }
+    }
 
-        if self.enabled {
-            self.start_cycle();
+    /// Fill the sample buffer with the byte fetched by DMA
+    pub fn load_sample(&mut self, data: u8) {
+        // The channel may have been disabled while the fetch was in progress
+        if self.dma_request().is_none() {
+            return;
         }
+
+        self.sample_buffer = Some(data);
+
+        // Advance sample address. Wrap around to $8000 if needed
+        self.current_addr = if self.current_addr == 0xFFFF { 0x8000 } else { self.current_addr + 1 };
+
+        self.remaining_bytes -= 1;
+
+        if self.remaining_bytes == 0 {
+            if self.loop_enabled {
+                self.restart();
+            }
+            else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    pub fn status(&self) -> bool {
+        self.remaining_bytes > 0
     }
 
-    fn start_cycle(&mut self) {
+    pub fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn restart(&mut self) {
         self.current_addr = self.sample_address;
         self.remaining_bytes = self.sample_length;
+    }
 
+    fn start_output_cycle(&mut self) {
         self.bits_remaining = 8;
 
-        if self.sample_buffer.is_some() {
-            self.shift = self.sample_buffer.unwrap();
-            self.sample_buffer = None;
-            self.silence = false;
+        match self.sample_buffer.take() {
+            Some(sample) => {
+                self.shift = sample;
+                self.silence = false;
+            },
+            None => self.silence = true,
         }
-        else {
-            self.silence = true;
+    }
+}
+
+impl Snapshot for Dmc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.loop_enabled);
+        state.write_u16(self.sample_address);
+        state.write_u16(self.sample_length);
+        state.write_bool(self.irq);
+
+        state.write_u8(self.bits_remaining);
+        state.write_bool(self.silence);
+        state.write_u8(self.shift);
+
+        state.write_u8(self.output);
+
+        self.timer.save_state(state);
+
+        state.write_bool(self.sample_buffer.is_some());
+        state.write_u8(self.sample_buffer.unwrap_or(0));
+        state.write_u16(self.current_addr);
+        state.write_u16(self.remaining_bytes);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enabled = state.read_bool()?;
+        self.loop_enabled = state.read_bool()?;
+        self.sample_address = state.read_u16()?;
+        self.sample_length = state.read_u16()?;
+        self.irq = state.read_bool()?;
+
+        self.bits_remaining = state.read_u8()?;
+        self.silence = state.read_bool()?;
+        self.shift = state.read_u8()?;
+
+        self.output = state.read_u8()?;
+
+        self.timer.load_state(state)?;
+
+        let has_sample = state.read_bool()?;
+        let sample = state.read_u8()?;
+        self.sample_buffer = if has_sample { Some(sample) } else { None };
+        self.current_addr = state.read_u16()?;
+        self.remaining_bytes = state.read_u16()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dma_request() {
+        let mut dmc = init_dmc(0x00);
+        assert_eq!(dmc.dma_request(), None);
+
+        dmc.set_enable(true);
+        assert_eq!(dmc.dma_request(), Some(0xC000));
+
+        dmc.load_sample(0xAA);
+        // The buffer is full
+        assert_eq!(dmc.dma_request(), None);
+        assert!(dmc.status());
+    }
+
+    #[test]
+    fn irq_at_end_of_sample() {
+        let mut dmc = init_dmc(0x80);
+        dmc.set_enable(true);
+
+        dmc.load_sample(0xAA);
+        dmc.clock_output_cycle();
+        dmc.load_sample(0xAA);
+        assert!(!dmc.irq());
+
+        dmc.clock_output_cycle();
+        dmc.load_sample(0xAA);
+        assert!(dmc.irq());
+        assert!(!dmc.status());
+
+        // Cleared by writing $4015
+        dmc.set_enable(false);
+        assert!(!dmc.irq());
+    }
+
+    #[test]
+    fn loop_restarts_sample() {
+        let mut dmc = init_dmc(0xC0);
+        dmc.set_enable(true);
+
+        for _ in 0..3 {
+            dmc.load_sample(0xAA);
+            dmc.clock_output_cycle();
         }
+
+        assert_eq!(dmc.dma_request(), Some(0xC000));
+        assert!(!dmc.irq());
     }
 
-    pub fn status(&self) -> bool {
-        self.remaining_bytes > 0
+    #[test]
+    fn disable_cancels_fetch() {
+        let mut dmc = init_dmc(0x00);
+        dmc.set_enable(true);
+        dmc.set_enable(false);
+
+        assert_eq!(dmc.dma_request(), None);
+
+        // A fetch that was already in progress is discarded
+        dmc.load_sample(0xAA);
+        assert_eq!(dmc.sample_buffer, None);
+    }
+
+    #[test]
+    fn output_level_clamped() {
+        let mut dmc = init_dmc(0x0F);
+        dmc.write_byte(1, 0x7F);
+        dmc.set_enable(true);
+        dmc.load_sample(0xFF);
+
+        // Shift out the sample. The level stops increasing at 127
+        for _ in 0..(10 * (dmc.timer.period() as usize + 1)) {
+            dmc.tick();
+        }
+
+        assert_eq!(dmc.output(), 0x7F);
+    }
+
+    /// Sample at $C000 that is 3 bytes long
+    fn init_dmc(flags: u8) -> Dmc {
+        let mut dmc = Dmc::default();
+        dmc.write_byte(0, flags);
+        dmc.write_byte(2, 0x00);
+        dmc.write_byte(3, 0x00);
+        dmc.sample_length = 3;
+
+        dmc
+    }
+
+    impl Dmc {
+        /// Clock the timer until the sample buffer is emptied
+        fn clock_output_cycle(&mut self) {
+            for _ in 0..(8 * (self.timer.period() as usize + 1) + 1) {
+                if self.sample_buffer.is_none() {
+                    break;
+                }
+                self.tick();
+            }
+        }
     }
 }
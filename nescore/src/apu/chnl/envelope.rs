@@ -6,6 +6,7 @@
 //
 use super::Divider;
 use crate::common::Clockable;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 const DECAY_RELOAD: u8 = 15;
 
@@ -87,6 +88,28 @@ impl Envelope {
     }
 }
 
+impl Snapshot for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.divider.save_state(state);
+        state.write_u8(self.decay);
+        state.write_u8(self.volume);
+        state.write_bool(self.constant);
+        state.write_bool(self.loop_flag);
+        state.write_bool(self.start_flag);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.divider.load_state(state)?;
+        self.decay = state.read_u8()?;
+        self.volume = state.read_u8()?;
+        self.constant = state.read_bool()?;
+        self.loop_flag = state.read_bool()?;
+        self.start_flag = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,6 +5,7 @@
 // @date Apr 02 2020
 //
 use crate::common::Clockable;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 pub trait LengthCounterUnit {
     fn enable_length(&mut self, e: bool);
@@ -73,6 +74,22 @@ impl LengthCounter {
     }
 }
 
+impl Snapshot for LengthCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.enabled);
+        state.write_bool(self.halted);
+        state.write_usize(self.counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.read_bool()?;
+        self.halted = state.read_bool()?;
+        self.counter = state.read_usize()?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
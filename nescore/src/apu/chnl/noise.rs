@@ -6,8 +6,11 @@
 //
 use crate::common::{Clockable, IoAccess};
 use super::{SoundChannel, LengthCounter, LengthCounterUnit, Envelope, EnvelopeUnit, Timer};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::region::Region;
 
-const TIMER_PERIODS: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const TIMER_PERIODS_NTSC: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const TIMER_PERIODS_PAL: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
 
 pub struct Noise {
     timer: Timer,
@@ -17,6 +20,8 @@ pub struct Noise {
 
     loop_noise: bool,
     shift_register: u16,
+
+    region: Region,
 }
 
 impl Default for Noise {
@@ -29,6 +34,8 @@ impl Default for Noise {
 
             loop_noise: false,
             shift_register: 1,
+
+            region: Region::default(),
         }
     }
 }
@@ -80,7 +87,8 @@ impl IoAccess for Noise {
             1 => {}, // Unused
             2 => {
                 self.loop_noise = bit_is_set!(data, 7);
-                self.timer.set_period(TIMER_PERIODS[(data & 0x0F) as usize]);
+                let periods = if self.region == Region::Pal { &TIMER_PERIODS_PAL } else { &TIMER_PERIODS_NTSC };
+                self.timer.set_period(periods[(data & 0x0F) as usize]);
             },
             3 => {
                 self.lenctr.load(bit_group!(data, 0x1F, 3) as usize);
@@ -91,3 +99,34 @@ impl IoAccess for Noise {
         }
     }
 }
+
+impl Noise {
+    /// Select the period table for the region
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.timer.save_state(state);
+
+        self.lenctr.save_state(state);
+        self.envelope.save_state(state);
+
+        state.write_bool(self.loop_noise);
+        state.write_u16(self.shift_register);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.timer.load_state(state)?;
+
+        self.lenctr.load_state(state)?;
+        self.envelope.load_state(state)?;
+
+        self.loop_noise = state.read_bool()?;
+        self.shift_register = state.read_u16()?;
+
+        Ok(())
+    }
+}
@@ -6,6 +6,7 @@
 //
 use crate::common::{IoAccess, Clockable};
 use super::{SoundChannel, LengthCounter, LengthCounterUnit, Envelope, EnvelopeUnit, Divider, Timer};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 struct WaveformSequencer {
     duty: usize,
@@ -54,6 +55,27 @@ impl WaveformSequencer {
     }
 }
 
+impl Snapshot for WaveformSequencer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.duty);
+        state.write_usize(self.counter);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let duty = state.read_usize()?;
+        let counter = state.read_usize()?;
+
+        if duty >= self.waveform.len() || counter >= self.waveform[0].len() {
+            return Err(StateError::InvalidData);
+        }
+
+        self.duty = duty;
+        self.counter = counter;
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum NegateAddMode {
     OnesComplement, TwosComplement,
@@ -198,6 +220,38 @@ impl IoAccess for Pulse {
     }
 }
 
+impl Snapshot for Pulse {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.timer.save_state(state);
+
+        self.sweep_divider.save_state(state);
+        state.write_bool(self.sweep_enabled);
+        state.write_bool(self.sweep_negate);
+        state.write_u8(self.sweep_shift);
+        state.write_bool(self.sweep_reload);
+
+        self.lenctr.save_state(state);
+        self.envelope.save_state(state);
+        self.waveform.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.timer.load_state(state)?;
+
+        self.sweep_divider.load_state(state)?;
+        self.sweep_enabled = state.read_bool()?;
+        self.sweep_negate = state.read_bool()?;
+        self.sweep_shift = state.read_u8()?;
+        self.sweep_reload = state.read_bool()?;
+
+        self.lenctr.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.waveform.load_state(state)?;
+
+        Ok(())
+    }
+}
+
 mod util {
     use super::NegateAddMode;
 
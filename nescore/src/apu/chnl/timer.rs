@@ -7,6 +7,7 @@
 
 use crate::common::Clockable;
 use super::Divider;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 /// Generic timer
 #[derive(Default)]
@@ -53,6 +54,20 @@ impl Timer {
     }
 }
 
+impl Snapshot for Timer {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.divider.save_state(state);
+        state.write_u16(self.period);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.divider.load_state(state)?;
+        self.period = state.read_u16()?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -6,6 +6,7 @@
 //
 use crate::common::{Clockable, IoAccess};
 use super::{SoundChannel, LengthCounter, LengthCounterUnit, Timer};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 pub struct Triangle {
     timer: Timer,
@@ -101,6 +102,38 @@ impl IoAccess for Triangle {
     }
 }
 
+impl Snapshot for Triangle {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.timer.save_state(state);
+        self.lenctr.save_state(state);
+
+        state.write_usize(self.linear_counter);
+        state.write_usize(self.reload_value);
+        state.write_bool(self.reload_flag);
+        state.write_bool(self.ctrl_flag);
+
+        state.write_usize(self.sequence_idx);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.timer.load_state(state)?;
+        self.lenctr.load_state(state)?;
+
+        self.linear_counter = state.read_usize()?;
+        self.reload_value = state.read_usize()?;
+        self.reload_flag = state.read_bool()?;
+        self.ctrl_flag = state.read_bool()?;
+
+        let sequence_idx = state.read_usize()?;
+        if sequence_idx >= self.sequence.len() {
+            return Err(StateError::InvalidData);
+        }
+        self.sequence_idx = sequence_idx;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -5,6 +5,8 @@
 // @date Apr 01 2020
 //
 use crate::common::{Clockable, Register};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::region::Region;
 use std::cell::RefCell;
 
 pub enum Event {
@@ -33,6 +35,7 @@ pub struct FrameSequencer {
     mode: Mode,
     irq_inhibit: bool,
     frame_irq: RefCell<bool>,
+    region: Region,
 }
 
 impl Default for FrameSequencer {
@@ -42,6 +45,7 @@ impl Default for FrameSequencer {
             mode: Mode::Step4,
             irq_inhibit: false,
             frame_irq: RefCell::new(false),
+            region: Region::default(),
         }
     }
 }
@@ -70,7 +74,7 @@ impl Clockable<SequencerEvents> for FrameSequencer {
         // Covert the tracked cycles to the APU step
         // Map the step to the correct set of clock events
         // Step 4 is the only thing different between step 4 and 5 mode (expect the extra step in 5 mode)
-        let events = helpers::cycles_to_step(self.cycles).map(|step| {
+        let events = helpers::cycles_to_step(self.cycles, self.region).map(|step| {
             match step {
                 Step::One => [Event::EnvelopAndLinear, Event::None, Event::None],
                 Step::Two => [Event::EnvelopAndLinear, Event::LengthAndSweep, Event::None],
@@ -88,16 +92,24 @@ impl Clockable<SequencerEvents> for FrameSequencer {
         .unwrap_or([Event::None, Event::None, Event::None]);
 
         // Advance cycles given the mode
-        self.cycles = match self.mode {
-            Mode::Step4 => (self.cycles + 1) % 14915,
-            Mode::Step5 => (self.cycles + 1) % 18641,
-        };
+        self.cycles = (self.cycles + 1) % helpers::sequence_length(self.mode, self.region);
 
         events
     }
 }
 
 impl FrameSequencer {
+    /// Select the step timing for the region. Dendy uses NTSC timing
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Level of the frame IRQ flag
+    pub fn frame_irq(&self) -> bool {
+        *self.frame_irq.borrow()
+    }
+
+    /// Read and clear the frame IRQ flag
     pub fn irq_status(&self) -> bool {
         let status = *self.frame_irq.borrow();
         *self.frame_irq.borrow_mut() = false;
@@ -106,16 +118,56 @@ impl FrameSequencer {
     }
 }
 
+impl Snapshot for FrameSequencer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.cycles);
+        state.write_bool(self.mode == Mode::Step5);
+        state.write_bool(self.irq_inhibit);
+        state.write_bool(*self.frame_irq.borrow());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.cycles = state.read_usize()?;
+        self.mode = if state.read_bool()? { Mode::Step5 } else { Mode::Step4 };
+        self.irq_inhibit = state.read_bool()?;
+        *self.frame_irq.borrow_mut() = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
 mod helpers {
-    use super::{Step, Mode, Event, SequencerEvents};
-    pub fn cycles_to_step(cycles: usize) -> Option<Step> {
-        match cycles {
-            3728 => Some(Step::One),
-            7456 => Some(Step::Two),
-            11185 => Some(Step::Three),
-            14914 => Some(Step::Four),
-            18640 => Some(Step::Five),
-            _ => None,
+    use super::{Step, Mode, Event, SequencerEvents, Region};
+    pub fn cycles_to_step(cycles: usize, region: Region) -> Option<Step> {
+        if region == Region::Pal {
+            match cycles {
+                4156 => Some(Step::One),
+                8313 => Some(Step::Two),
+                12469 => Some(Step::Three),
+                16626 => Some(Step::Four),
+                20782 => Some(Step::Five),
+                _ => None,
+            }
+        }
+        else {
+            match cycles {
+                3728 => Some(Step::One),
+                7456 => Some(Step::Two),
+                11185 => Some(Step::Three),
+                14914 => Some(Step::Four),
+                18640 => Some(Step::Five),
+                _ => None,
+            }
+        }
+    }
+
+    /// Number of APU cycles in the sequence
+    pub fn sequence_length(mode: Mode, region: Region) -> usize {
+        match (mode, region) {
+            (Mode::Step4, Region::Pal) => 16627,
+            (Mode::Step5, Region::Pal) => 20783,
+            (Mode::Step4, _) => 14915,
+            (Mode::Step5, _) => 18641,
         }
     }
 
@@ -193,6 +245,27 @@ mod tests {
         assert_eq!(irq_counter, 0);
     }
 
+    #[test]
+    fn pal_frame_irq() {
+        let mut frame_sequencer = FrameSequencer::default();
+        frame_sequencer.set_region(Region::Pal);
+
+        // Set Step 4 mode
+        frame_sequencer.load(0x00);
+
+        // The NTSC sequence length does not raise the IRQ
+        for _ in 0..14915 {
+            frame_sequencer.tick();
+        }
+        assert!(!frame_sequencer.frame_irq());
+
+        for _ in 14915..16627 {
+            frame_sequencer.tick();
+        }
+        assert!(frame_sequencer.frame_irq());
+        assert_eq!(frame_sequencer.cycles, 0);
+    }
+
     #[test]
     fn set_mode() {
         let mut frame_sequencer = FrameSequencer::default();
@@ -55,7 +55,8 @@ pub enum Instruction {
     CLD, CLI, CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP,
     JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL, ROR, RTI,
     RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
-    LAX, SAX, DCP, ISB, SLO, RLA, RRA, SRE, ANC, ALR, ARR, AXS, SHY, SHX
+    LAX, SAX, DCP, ISB, SLO, RLA, RRA, SRE, ANC, ALR, ARR, AXS, SHY, SHX,
+    SHA, TAS, LAS, XAA, LXA, JAM
 }
 
 impl fmt::Display for Instruction {
@@ -77,6 +78,9 @@ impl Instruction {
             | Instruction::ALR
             | Instruction::ARR
             | Instruction::AXS
+            | Instruction::LAS
+            | Instruction::XAA
+            | Instruction::LXA
             | Instruction::ORA
             | Instruction::ADC
             | Instruction::SBC
@@ -88,11 +92,13 @@ impl Instruction {
               Instruction::STA
             | Instruction::STX
             | Instruction::SAX
+            | Instruction::SHY
+            | Instruction::SHX
+            | Instruction::SHA
+            | Instruction::TAS
             | Instruction::STY => InstructionCategory::Write,
 
               Instruction::ASL
-            | Instruction::SHY
-            | Instruction::SHX
             | Instruction::LSR
             | Instruction::ROL
             | Instruction::ROR
@@ -149,6 +155,8 @@ pub fn cycle_count(instr: Instruction, mode: AddressingMode) -> usize {
                 Instruction::DEX | Instruction::DEY => 1,
                 Instruction::INX | Instruction::INY => 1,
                 Instruction::NOP => 1,
+                // The CPU halts after fetching the opcode
+                Instruction::JAM => 1,
 
                 _ => unreachable!("Matching implied instructions"),
             }
@@ -226,13 +234,14 @@ pub fn cycle_count(instr: Instruction, mode: AddressingMode) -> usize {
     }
 }
 
+/// Decode an opcode into an instruction and addressing mode. All 256 opcodes are decoded, including unofficial ones
 pub fn decode(opcode: u8) -> (Instruction, AddressingMode) {
     match opcode {
         // NOP
         0xEA | 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => (Instruction::NOP, AddressingMode::Implied),
-        0x04 | 0x44 | 0x64 | 0x82 | 0x89 | 0xC2 | 0xE2 => (Instruction::NOP, AddressingMode::Immediate),
+        0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => (Instruction::NOP, AddressingMode::Immediate),
         0x0C => (Instruction::NOP, AddressingMode::Absolute),
-        0x80 => (Instruction::NOP, AddressingMode::ZeroPage),
+        0x04 | 0x44 | 0x64 => (Instruction::NOP, AddressingMode::ZeroPage),
         0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => (Instruction::NOP, AddressingMode::ZeroPageX),
         0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => (Instruction::NOP, AddressingMode::AbsoluteX),
         // LDA
@@ -245,7 +254,6 @@ pub fn decode(opcode: u8) -> (Instruction, AddressingMode) {
         0xA1 => (Instruction::LDA, AddressingMode::IndexedIndirect),
         0xB1 => (Instruction::LDA, AddressingMode::IndirectIndexed),
         // LAX
-        0xAB => (Instruction::LAX, AddressingMode::Immediate),
         0xA7 => (Instruction::LAX, AddressingMode::ZeroPage),
         0xB7 => (Instruction::LAX, AddressingMode::ZeroPageY),
         0xAF => (Instruction::LAX, AddressingMode::Absolute),
@@ -491,6 +499,21 @@ pub fn decode(opcode: u8) -> (Instruction, AddressingMode) {
         0x9C => (Instruction::SHY, AddressingMode::AbsoluteX),
         // SHX
         0x9E => (Instruction::SHX, AddressingMode::AbsoluteY),
+        // SHA (AHX)
+        0x9F => (Instruction::SHA, AddressingMode::AbsoluteY),
+        0x93 => (Instruction::SHA, AddressingMode::IndirectIndexed),
+        // TAS
+        0x9B => (Instruction::TAS, AddressingMode::AbsoluteY),
+        // LAS
+        0xBB => (Instruction::LAS, AddressingMode::AbsoluteY),
+        // XAA (ANE)
+        0x8B => (Instruction::XAA, AddressingMode::Immediate),
+        // LXA
+        0xAB => (Instruction::LXA, AddressingMode::Immediate),
+        // JAM (KIL) - Halts the CPU
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+            (Instruction::JAM, AddressingMode::Implied)
+        },
         // STX
         0x86 => (Instruction::STX, AddressingMode::ZeroPage),
         0x96 => (Instruction::STX, AddressingMode::ZeroPageY),
@@ -513,10 +536,6 @@ pub fn decode(opcode: u8) -> (Instruction, AddressingMode) {
         0x98 => (Instruction::TYA, AddressingMode::Implied),
         // BRK - Followed by an unused byte
         0x00 => (Instruction::BRK, AddressingMode::Immediate),
-
-        _ => {
-            panic!("Invalid opcode: ${opcode}", opcode=format!("{:X}", opcode));
-        }
     }
 }
 
@@ -555,3 +574,15 @@ fn address(data: &[u8]) -> u16 {
 
     (hi << 8) | lo
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unofficial_nops() {
+        assert_eq!(decode(0x04), (Instruction::NOP, AddressingMode::ZeroPage));
+        assert_eq!(decode(0x80), (Instruction::NOP, AddressingMode::Immediate));
+        assert_eq!(decode(0x0C), (Instruction::NOP, AddressingMode::Absolute));
+    }
+}
@@ -12,6 +12,8 @@ use std::io::prelude::*;
 use std::fs::File;
 use std::error::Error;
 
+use crate::region::Region;
+
 pub const PRG_ROM_BANK_SIZE: usize = kb!(16);
 pub const CHR_ROM_BANK_SIZE: usize = kb!(8);
 
@@ -24,7 +26,8 @@ pub const CHR_ROM_BANK_SIZE: usize = kb!(8);
 pub enum ParseError {
     InvalidSize(usize),
     InvalidSig,
-    InvalidFormat
+    InvalidFormat,
+    Truncated(usize, usize),
 }
 
 impl fmt::Display for ParseError {
@@ -32,7 +35,9 @@ impl fmt::Display for ParseError {
         match *self {
             ParseError::InvalidSig     => write!(f, "Invalid signature at start of file. Expected `NES`. Not an NES ROM"),
             ParseError::InvalidSize(s) => write!(f, "Not enough data to parse header (Size: {})", s),
-            ParseError::InvalidFormat  => write!(f, "The detected header is not valid")
+            ParseError::InvalidFormat  => write!(f, "The detected header is not valid"),
+            ParseError::Truncated(expected, actual) =>
+                write!(f, "ROM data is truncated. Expected {} bytes, found {}", expected, actual),
         }
     }
 }
@@ -44,6 +49,10 @@ impl Error for ParseError {}
 pub enum CartridgeError {
     ReadFail(io::Error),
     InvalidRom(ParseError),
+    UnsupportedMapper(usize),
+    UnsupportedSubmapper(usize, usize),
+    PrgRomSize(usize, usize),
+    ChrRomSize(usize, usize),
 }
 
 impl fmt::Display for CartridgeError {
@@ -51,6 +60,13 @@ impl fmt::Display for CartridgeError {
         match *self {
             CartridgeError::ReadFail(ref e) => write!(f, "Failed to read ROM file: {}", e),
             CartridgeError::InvalidRom(ref e) => write!(f, "Invalid ROM file: {}", e),
+            CartridgeError::UnsupportedMapper(mapper) => write!(f, "Unsupported mapper: #{}", mapper),
+            CartridgeError::UnsupportedSubmapper(mapper, submapper) =>
+                write!(f, "Unsupported submapper {} for mapper #{}", submapper, mapper),
+            CartridgeError::PrgRomSize(expected, actual) =>
+                write!(f, "PRG ROM size does not match the header. Expected {} bytes, found {}", expected, actual),
+            CartridgeError::ChrRomSize(expected, actual) =>
+                write!(f, "CHR ROM size does not match the header. Expected {} bytes, found {}", expected, actual),
         }
     }
 }
@@ -60,6 +76,7 @@ impl Error for CartridgeError {
         match *self {
             CartridgeError::ReadFail(ref e) => Some(e),
             CartridgeError::InvalidRom(ref e) => Some(e),
+            _ => None,
         }
     }
 }
@@ -98,7 +115,7 @@ pub struct CartridgeInfo {
     pub vs_unisystem: bool,      // VS Unisystem
     pub playchoice10: bool,      // PlayChoice
     pub tv_system_pal: bool,     // NTSC if false, PAL if true
-    pub tv_system_ext: usize,    // Unofficial TV supper, 0 - NTSC, 1 - PAL, 2 - Dual Compat
+    pub tv_system_ext: usize,    // Unofficial TV supper, 0 - NTSC, 1 - PAL, 2 - Dual Compat (NES 2.0: 3 - Dendy)
     
     // below are NES 2.0 only
     pub submapper: usize,        // Submapper number
@@ -118,7 +135,7 @@ impl CartridgeInfo {
 impl fmt::Display for CartridgeInfo {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mirroring = if self.mirror_v { String::from("Vertical") } else { String::from("Horizontal") };
-        let tv_system = if self.tv_system_pal { String::from("PAL") } else { String::from("NTSC") };
+        let tv_system = Region::from_cartridge(self);
 
         write!(f,
         "
@@ -175,8 +192,11 @@ impl Cartridge {
         Cartridge::from_slice(rom.as_slice())
     }
 
+    /// Instantiate a Cartridge from a slice of bytes
+    ///
+    /// Returns an error if the header is invalid or the buffer is smaller than the size the header specifies
     pub fn from_slice(rom: &[u8]) -> Result<Cartridge, CartridgeError> {
-        CartridgeInfo::from(rom).map(|info| {
+        CartridgeInfo::from(rom).and_then(|info| {
             // Determine the number of bytes for PRG ROM and CHR ROM
             let prg_rom_size = info.prg_rom_banks * PRG_ROM_BANK_SIZE;
             let chr_rom_size = info.chr_rom_banks * CHR_ROM_BANK_SIZE;
@@ -186,12 +206,17 @@ impl Cartridge {
             let trainer_bytes = if info.trainer { 512 } else { 0 };
             let prg_rom_offset = header_bytes + trainer_bytes;
 
+            let rom_size = prg_rom_offset + prg_rom_size + chr_rom_size;
+            if rom.len() < rom_size {
+                return Err(CartridgeError::InvalidRom(ParseError::Truncated(rom_size, rom.len())));
+            }
+
             // Get a slice for the program ROM
             let prg_rom = rom[prg_rom_offset..prg_rom_offset+prg_rom_size].to_vec();
             // Get a slice for the character ROM
             let chr_rom = rom[(prg_rom_offset+prg_rom_size)..(prg_rom_offset+prg_rom_size+chr_rom_size)].to_vec();
 
-            Cartridge::from_parts(info, prg_rom, chr_rom, vec![])
+            Ok(Cartridge::from_parts(info, prg_rom, chr_rom, vec![]))
         })
     }
 
@@ -221,6 +246,22 @@ impl Cartridge {
         (self.info, self.prg_rom, self.chr_rom, self.bat_ram)
     }
 
+    /// Check the PRG and CHR ROM data is consistent with the header
+    pub fn verify(&self) -> Result<(), CartridgeError> {
+        let prg_rom_size = self.info.prg_rom_banks * PRG_ROM_BANK_SIZE;
+        let chr_rom_size = self.info.chr_rom_banks * CHR_ROM_BANK_SIZE;
+
+        if self.prg_rom.is_empty() || self.prg_rom.len() != prg_rom_size {
+            Err(CartridgeError::PrgRomSize(prg_rom_size, self.prg_rom.len()))
+        }
+        else if self.chr_rom.len() != chr_rom_size {
+            Err(CartridgeError::ChrRomSize(chr_rom_size, self.chr_rom.len()))
+        }
+        else {
+            Ok(())
+        }
+    }
+
     /// Insert battery RAM into the cartridge
     pub fn add_battery_ram(mut self, batt: Vec<u8>) -> Self {
         self.bat_ram = batt;
@@ -440,13 +481,9 @@ fn get_info_nes2(rom_header: &[u8], info: &mut CartridgeInfo) {
     info.batt_chr_ram = (rom_header[11] >> 4) as usize;
     info.chr_ram = (rom_header[11] & 0x0Fu8) as usize;
 
-    // TV system
-    info.tv_system_ext = if (rom_header[12] & 0x01) != 0 {
-        0
-    }
-    else {
-        1
-    }
+    // CPU/PPU timing: 0 - NTSC, 1 - PAL, 2 - Multi-region, 3 - Dendy
+    info.tv_system_ext = (rom_header[12] & 0x03u8) as usize;
+    info.tv_system_pal = info.tv_system_ext == 1;
 }
 
 /// Get the NES ROM format
@@ -616,6 +653,37 @@ mod tests {
         assert!(matches!(err, CartridgeError::InvalidRom(ParseError::InvalidSig)));
     }
 
+    #[test]
+    fn load_truncated() {
+        let header = init_header();
+        let prg_rom = [0u8; PRG_ROM_SIZE];
+
+        let rom = [&header[..], &prg_rom[..]].concat();
+        let rom_size = rom.len();
+
+        let err = Cartridge::from(rom).err().unwrap();
+
+        assert!(matches!(err, CartridgeError::InvalidRom(ParseError::Truncated(expected, actual))
+                         if expected == rom_size + CHR_ROM_SIZE && actual == rom_size));
+    }
+
+    #[test]
+    fn verify_sizes() {
+        let header = init_header();
+
+        let info = CartridgeInfo::from(&header[..]).unwrap();
+        let cart = Cartridge::from_parts(info, vec![0; PRG_ROM_SIZE], vec![0; CHR_ROM_SIZE], vec![]);
+        assert!(cart.verify().is_ok());
+
+        let info = CartridgeInfo::from(&header[..]).unwrap();
+        let cart = Cartridge::from_parts(info, vec![0; PRG_ROM_BANK_SIZE], vec![0; CHR_ROM_SIZE], vec![]);
+        assert!(matches!(cart.verify(), Err(CartridgeError::PrgRomSize(PRG_ROM_SIZE, PRG_ROM_BANK_SIZE))));
+
+        let info = CartridgeInfo::from(&header[..]).unwrap();
+        let cart = Cartridge::from_parts(info, vec![0; PRG_ROM_SIZE], vec![], vec![]);
+        assert!(matches!(cart.verify(), Err(CartridgeError::ChrRomSize(CHR_ROM_SIZE, 0))));
+    }
+
     #[test]
     fn loader_no_rom() {
         let err = CartridgeLoader::default().load().err().unwrap();
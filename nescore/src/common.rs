@@ -14,21 +14,28 @@ macro_rules! kb {
     };
 }
 
-/// Interrupt
-#[derive(Copy, Clone, PartialEq, Debug)]
-pub enum Interrupt {
-    Nmi,
-    Irq,
-}
-
 /// Access a memory mapped component
 pub trait IoAccess {
     #[allow(unused)]
     fn read_byte(&self, addr: u16) -> u8 { 0 }
     #[allow(unused)]
     fn write_byte(&mut self, addr: u16, data: u8) {}
+    /// Read without side effects, such as mappers observing the address
+    fn peek_byte(&self, addr: u16) -> u8 { self.read_byte(addr) }
+    /// Place an address on the bus without transferring any data
+    #[allow(unused)]
+    fn drive_address(&mut self, addr: u16) {}
+    /// Sample the level of the NMI line. The CPU triggers an NMI when the line becomes asserted
+    fn nmi_line(&self) -> bool { false }
+    /// Sample the level of the IRQ line
+    fn irq_line(&self) -> bool { false }
+    /// Take the page of a pending OAM DMA transfer
+    fn take_dma_request(&mut self) -> Option<u8> { None }
+    /// Address of a pending DMC sample fetch
+    fn dmc_dma_request(&self) -> Option<u16> { None }
+    /// Provide the sample byte fetched by DMC DMA
     #[allow(unused)]
-    fn raise_interrupt(&mut self, interrupt_type: Interrupt){}
+    fn dmc_dma_complete(&mut self, data: u8) {}
 }
 
 pub type IoAccessRef = Rc<RefCell<dyn IoAccess>>;
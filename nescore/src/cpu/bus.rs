@@ -7,6 +7,8 @@
 
 use crate::common::{IoAccess, IoAccessRef};
 use crate::mapper::Mapper;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::debug::DebuggerRef;
 
 const INTERNAL_RAM_SIZE: usize = 0x800;
 
@@ -16,6 +18,8 @@ pub struct CpuIoBus {
     apu: IoAccessRef,
     joy: IoAccessRef,
     mapper: Mapper,
+    debugger: Option<DebuggerRef>,
+    dma_page: Option<u8>,         // Page written to $4014, waiting for the CPU to start the transfer
 }
 
 fn mirror_address(addr: u16, base: u16, count: u16) -> u16 {
@@ -30,13 +34,19 @@ impl CpuIoBus {
             apu,
             joy,
             mapper,
+            debugger: None,
+            dma_page: None,
         }
     }
+
+    pub fn load_debugger(&mut self, debugger: DebuggerRef) {
+        self.debugger = Some(debugger);
+    }
 }
 
 impl IoAccess for CpuIoBus {
     fn read_byte(&self, addr: u16) -> u8 {
-        match addr {
+        let value = match addr {
             0x0000..=0x1FFF => self.ram[mirror_address(addr, 0x0000, INTERNAL_RAM_SIZE as u16) as usize],
             0x2000..=0x3FFF => self.ppu.borrow().read_byte(mirror_address(addr, 0x2000, 8)),
             0x4000..=0x4013 => self.apu.borrow().read_byte(addr),
@@ -44,10 +54,20 @@ impl IoAccess for CpuIoBus {
             0x4016 | 0x4017 => self.joy.borrow().read_byte(addr),
             0x4020..=0xFFFF => self.mapper.borrow().read(addr),
             _ => 0,
+        };
+
+        if let Some(ref debugger) = self.debugger {
+            debugger.borrow_mut().check_read(addr, value);
         }
+
+        value
     }
 
     fn write_byte(&mut self, addr: u16, data: u8) {
+        if let Some(ref debugger) = self.debugger {
+            debugger.borrow_mut().check_write(addr, data);
+        }
+
         match addr {
             0x0000..=0x1FFF => self.ram[mirror_address(addr, 0x0000, INTERNAL_RAM_SIZE as u16) as usize] = data,
             0x2000..=0x3FFF => {
@@ -55,15 +75,8 @@ impl IoAccess for CpuIoBus {
                 self.ppu.borrow_mut().write_byte(mirror_address(addr, 0x2000, 8), data);
             },
             0x4000..=0x4013 => self.apu.borrow_mut().write_byte(addr, data),
-            0x4014 => {
-                let base = (data as u16) << 8;
-                for i in 0..256 {
-                    // FIXME: This is kinda a hack to get the DMA transfer going. I think some refactoring the overall architecture
-                    // is necessary
-                    let cpu_byte = self.read_byte(base + i);
-                    self.ppu.borrow_mut().write_byte(0xFF00 | i, cpu_byte);
-                }
-            },
+            // OAM DMA. The CPU performs the transfer
+            0x4014 => self.dma_page = Some(data),
             0x4015 => self.apu.borrow_mut().write_byte(addr, data),
             0x4016 => {
                 self.joy.borrow_mut().write_byte(addr, data);
@@ -75,6 +88,36 @@ impl IoAccess for CpuIoBus {
             0x4020..=0xFFFF => self.mapper.borrow_mut().write(addr, data),
         }
     }
+
+    fn nmi_line(&self) -> bool {
+        self.ppu.borrow().nmi_line()
+    }
+
+    fn irq_line(&self) -> bool {
+        self.mapper.borrow().irq() || self.apu.borrow().irq_line()
+    }
+
+    fn take_dma_request(&mut self) -> Option<u8> {
+        self.dma_page.take()
+    }
+
+    fn dmc_dma_request(&self) -> Option<u16> {
+        self.apu.borrow().dmc_dma_request()
+    }
+
+    fn dmc_dma_complete(&mut self, data: u8) {
+        self.apu.borrow_mut().dmc_dma_complete(data);
+    }
+}
+
+impl Snapshot for CpuIoBus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.ram)
+    }
 }
 
 #[cfg(test)]
@@ -128,15 +171,39 @@ mod tests {
         assert_eq!(bus.read_byte(0x200F), 7);
     }
 
+    #[test]
+    fn mapper_irq_line() {
+        let mapper = Rc::new(RefCell::new(FakeMapper::default()));
+        let bus = init_bus_with_mapper(mapper.clone());
+
+        assert!(!bus.irq_line());
+
+        mapper.borrow_mut().irq = true;
+        assert!(bus.irq_line());
+    }
+
+    #[test]
+    fn oam_dma_request() {
+        let mut bus = init_bus();
+        assert_eq!(bus.take_dma_request(), None);
+
+        bus.write_byte(0x4014, 0x02);
+        assert_eq!(bus.take_dma_request(), Some(0x02));
+        assert_eq!(bus.take_dma_request(), None);
+    }
+
     //------------------------------------------------------------------------------------------------------------------
     // Helpers
     //------------------------------------------------------------------------------------------------------------------
 
     fn init_bus() -> CpuIoBus {
+        init_bus_with_mapper(Rc::new(RefCell::new(FakeMapper::default())))
+    }
+
+    fn init_bus_with_mapper(mapper: Rc<RefCell<FakeMapper>>) -> CpuIoBus {
         let ppu = Rc::new(RefCell::new(FakePpu::default()));
         let apu = Rc::new(RefCell::new(FakeApu::default()));
         let joy = Rc::new(RefCell::new(FakeJoy::default()));
-        let mapper = Rc::new(RefCell::new(FakeMapper::default()));
 
         CpuIoBus::new(ppu, apu, joy, mapper)
     }
@@ -182,6 +249,7 @@ mod tests {
     #[derive(Default)]
     struct FakeMapper {
         data: [u8; 10],
+        irq: bool,
     }
 
     impl MapperControl for FakeMapper {
@@ -200,6 +268,19 @@ mod tests {
         fn write_chr(&mut self, _addr: u16, _value: u8) {
 
         }
+
+        fn irq(&self) -> bool {
+            self.irq
+        }
     }
 
+    impl Snapshot for FakeMapper {
+        fn save_state(&self, state: &mut StateWriter) {
+            state.write_bytes(&self.data);
+        }
+
+        fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+            state.read_bytes(&mut self.data)
+        }
+    }
 }
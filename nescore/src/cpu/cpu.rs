@@ -5,52 +5,49 @@
 // @date Sep 18 2019
 //
 
-use crate::asm::{Instruction, AddressingMode, decode, cycle_count};
-use crate::common::{IoAccess, Clockable, Interrupt};
+use crate::asm::{Instruction, InstructionCategory, AddressingMode, decode};
+use crate::common::{IoAccess, Clockable};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::debug::DebuggerRef;
 use super::memorymap;
+use super::dma::{Dma, DmaCycle};
 
 use std::num::Wrapping;
 
 #[cfg(feature="events")]
 use std::sync::mpsc::Sender;
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-pub enum AddressingModeResult {
-    Byte(u8), Address(u16), Offset(u8), Implied
-}
-
-impl AddressingModeResult {
-    pub fn to_address(&self) -> Option<u16> {
-        match *self {
-            AddressingModeResult::Address(a) => Some(a),
-            _ => None,
-        }
-    }
-
-    pub fn to_byte<A2B>(&self, mut a2b: A2B) -> Option<u8> where A2B: FnMut(u16) -> u8 {
-        match *self {
-            AddressingModeResult::Byte(b) => Some(b),
-            AddressingModeResult::Address(a) => {
-                let b = a2b(a);
-                Some(b)
-            },
-            _ => None,
-        }
-    }
-
-    pub fn to_offset(&self) -> Option<u8> {
-        match *self {
-            AddressingModeResult::Offset(o) => Some(o),
-            _ => None,
-        }
-    }
-}
+/// Bits of the accumulator that survive the bus conflict in XAA and LXA. This varies between CPUs and temperature.
+/// With $FF, LXA behaves as LDA and TAX, which is what the test ROMs expect
+const UNSTABLE_MAGIC: u8 = 0xFF;
 
 #[derive(Copy, Clone)]
 pub enum State {
-    Reset,
+    /// Next cycle of the reset sequence
+    Reset(usize),
     Fetch,
+    /// Instruction, addressing mode, opcode and operand bytes, and the next cycle of the instruction
     Execute(Instruction, AddressingMode, [u8; 3], usize),
+    /// Next cycle of the NMI or IRQ sequence. The vector is selected when the status register is pushed
+    Interrupt(usize),
+    /// A JAM instruction halted the CPU. Only a reset recovers
+    Jammed,
+}
+
+/// How an instruction accesses the effective address
+#[derive(Copy, Clone, PartialEq)]
+enum Access {
+    Read,
+    Write,
+    Modify,
+}
+
+fn access_type(instr: Instruction) -> Access {
+    match instr.category() {
+        InstructionCategory::Write => Access::Write,
+        InstructionCategory::ReadModifyWrite => Access::Modify,
+        _ => Access::Read,
+    }
 }
 
 /// CPU Flags
@@ -88,6 +85,17 @@ pub mod events {
 
 const STACK_PAGE_OFFSET: u16 = 0x100;
 
+/// CPU register values
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub p: u8,
+}
+
 
 /// NES Central Processing Unit
 pub struct Cpu<Io: IoAccess> {
@@ -101,14 +109,33 @@ pub struct Cpu<Io: IoAccess> {
     bus: Option<Io>,
     state: State,                   // Internal CPU cycle state
 
-    interrupted: Option<Interrupt>, // Flag indicating the CPU was interrupt
+    // Instruction cycle state
+    opcode_addr: u16,               // Address of the current instruction
+    addr: u16,                      // Effective address, or pointer for indirect addressing
+    data: u8,                       // Operand value, or a byte of an address being fetched
+    crossed: bool,                  // Indexing crossed a page boundary
+
+    // Interrupt polling
+    nmi_line: bool,                 // Level of the NMI line on the previous cycle
+    nmi_pending: bool,              // An NMI edge was detected and the NMI has not been handled
+    irq_pending: bool,              // IRQ line asserted and not masked at the end of the last cycle
+    poll_nmi: bool,                 // Pending interrupts at the end of the second to last cycle
+    poll_irq: bool,
 
     debug: bool,                    // Debug mode
     is_holding: bool,               // CPU is in an infinite loop state
 
+    dma: Dma,                       // OAM and DMC DMA. The CPU is halted while a transfer is active
+    odd_cycle: bool,                // Current cycle is a put (write) cycle
+    resume_fetch: bool,             // DMA halted the CPU before it fetched the current instruction
+
+    debugger: Option<DebuggerRef>,  // Breakpoints
+
     // Event logging
     #[cfg(feature="events")]
     logger: Option<Sender<events::CpuEvent>>,
+    #[cfg(feature="events")]
+    event_regs: CpuRegisters,       // Registers before the current instruction was executed
 }
 
 impl<Io: IoAccess> Default for Cpu<Io> {
@@ -118,19 +145,37 @@ impl<Io: IoAccess> Default for Cpu<Io> {
             x: 0,
             y: 0,
             pc: 0,
-            sp: 0xFD,
+            // The reset sequence at power up leaves SP at $FD
+            sp: 0x00,
             p: 0x24,
 
             bus: None,
-            state: State::Reset,
+            state: State::Reset(1),
+
+            opcode_addr: 0,
+            addr: 0,
+            data: 0,
+            crossed: false,
 
-            interrupted: None,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_pending: false,
+            poll_nmi: false,
+            poll_irq: false,
 
             debug: false,
             is_holding: false,
 
+            dma: Dma::default(),
+            odd_cycle: false,
+            resume_fetch: false,
+
+            debugger: None,
+
             #[cfg(feature="events")]
             logger: None,
+            #[cfg(feature="events")]
+            event_regs: CpuRegisters::default(),
         }
     }
 }
@@ -140,13 +185,49 @@ impl<Io: IoAccess> Cpu<Io> {
         self.bus = Some(bus);
     }
 
+    pub fn load_debugger(&mut self, debugger: DebuggerRef) {
+        self.debugger = Some(debugger);
+    }
+
     /// Set the CPU's program counter
     pub fn set_pc(&mut self, pc: u16) {
+        // Skipping the reset sequence still leaves its effect on SP and the I flag
+        if let State::Reset(1) = self.state {
+            self.sp = self.sp.wrapping_sub(3);
+            self.set_flag_bit(Flags::InterruptDisable, true);
+        }
+
         self.pc = pc;
         // move to fetch state, as we no longer need to read the reset vector
         self.state = State::Fetch;
     }
 
+    /// Assert the RESET line. The reset sequence runs over the next 7 ticks and loads the PC from the reset vector
+    ///
+    /// The reset sequence performs three stack pushes with writes suppressed, so only SP changes. I is set
+    pub fn reset(&mut self) {
+        self.state = State::Reset(1);
+        self.dma = Dma::default();
+        self.resume_fetch = false;
+        self.is_holding = false;
+
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.poll_nmi = false;
+        self.poll_irq = false;
+    }
+
+    /// Return the CPU to its power up state. The bus must be reloaded
+    pub fn power_on(&mut self) {
+        *self = Cpu {
+            debug: self.debug,
+            debugger: self.debugger.take(),
+            #[cfg(feature="events")]
+            logger: self.logger.take(),
+            ..Cpu::default()
+        };
+    }
+
     pub fn set_debug(&mut self, debug: bool) {
         self.debug = debug;
     }
@@ -160,343 +241,735 @@ impl<Io: IoAccess> Cpu<Io> {
         self.pc
     }
 
+    pub fn registers(&self) -> CpuRegisters {
+        CpuRegisters {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            pc: self.pc,
+            sp: self.sp,
+            p: self.p,
+        }
+    }
+
+    /// Set register values. Setting the PC does not interrupt the current instruction
+    pub fn set_registers(&mut self, regs: CpuRegisters) {
+        self.a = regs.a;
+        self.x = regs.x;
+        self.y = regs.y;
+        self.pc = regs.pc;
+        self.sp = regs.sp;
+        self.p = regs.p;
+    }
+
     /// Determine if in an infinite loop state
     pub fn is_holding(&self) -> bool {
         self.is_holding
     }
 
+    /// Address of the JAM instruction that halted the CPU
+    pub fn jammed(&self) -> Option<u16> {
+        match self.state {
+            State::Jammed => Some(self.opcode_addr),
+            _ => None,
+        }
+    }
+
+    /// The previous instruction has completed and the next tick will fetch a new instruction (or service an interrupt)
+    pub fn at_instruction_boundary(&self) -> bool {
+        matches!(self.state, State::Fetch) && !self.dma.is_active() && !self.resume_fetch
+    }
+
     pub fn read_ram(&self, addr: u16) -> u8 {
         self.read_u8(addr)
     }
 
+    pub fn write_ram(&mut self, addr: u16, value: u8) {
+        self.write_u8(addr, value);
+    }
+
+    /// Perform one cycle of the active DMA transfers
+    fn run_dma_cycle(&mut self) {
+        match self.dma.cycle(!self.odd_cycle) {
+            DmaCycle::Idle => {},
+            DmaCycle::Read(addr) => {
+                let data = self.read_u8(addr);
+                self.dma.latch(data);
+            },
+            DmaCycle::Write(data) => self.write_u8(memorymap::PPU_OAM_DATA, data),
+            DmaCycle::DmcRead(addr) => {
+                let data = self.read_u8(addr);
+                if let Some(ref mut bus) = self.bus {
+                    bus.dmc_dma_complete(data);
+                }
+            },
+        }
+    }
+
+    /// Check execution breakpoints before the next instruction starts
+    fn begin_instruction(&mut self) {
+        if let (true, Some(ref debugger)) = (self.at_instruction_boundary(), &self.debugger) {
+            debugger.borrow_mut().begin_instruction(self.pc);
+        }
+    }
+
     /// Execute the current cycle given the internal state
     fn run_cycle(&mut self, state: State) -> State {
         match state {
-            State::Reset => {
-                // Read the PC address from the RESET vector
-                self.pc = self.read_u16(memorymap::RESET_VECTOR);
-                State::Fetch
+            State::Reset(1) => {
+                // The opcode is fetched and discarded
+                self.read_u8(self.pc);
+                State::Reset(2)
             },
-            State::Fetch => {
-                // Filter interrupt
-                let masked = self.get_flag_bit(Flags::InterruptDisable);
-                self.interrupted = self.interrupted.filter(
-                    |int_type| (*int_type == Interrupt::Irq && !masked) || *int_type == Interrupt::Nmi);
-
-                if let Some(int_type) = self.interrupted {
-                    self.interrupt(int_type);
+            State::Reset(cycle) => {
+                if self.interrupt_cycle(cycle, true) {
                     State::Fetch
                 }
                 else {
-                    let opcode = self.fetch();
-                    self.get_execute_state(opcode)
+                    State::Reset(cycle + 1)
                 }
             },
-            State::Execute(ref instr, ref mode, ref opcode_data, ref cycle) => {
+            State::Fetch => {
+                self.opcode_addr = self.pc;
+                self.crossed = false;
 
-                let total_cycles = cycle_count(*instr, *mode);
+                #[cfg(feature = "events")]
+                {
+                    self.event_regs = self.registers();
+                }
 
-                if *cycle < total_cycles-1 {
-                    return State::Execute(*instr, *mode, *opcode_data, *cycle + 1);
+                // Interrupts are polled at the end of the second to last cycle of the previous instruction
+                if self.poll_nmi || self.poll_irq {
+                    // The opcode is fetched and discarded
+                    self.read_u8(self.pc);
+                    State::Interrupt(2)
                 }
+                else {
+                    let opcode = self.fetch();
 
-                let operand_data = &opcode_data[1..];
-
-                if self.debug {
-                    #[cfg(feature = "events")]
-                    {
-                        let data = events::InstructionData {
-                            instr: *instr,
-                            mode: *mode,
-                            opcode_data: *opcode_data,
-                            addr: self.pc - (mode.operand_len() + 1) as u16,
-                            a: self.a,
-                            x: self.x,
-                            y: self.y,
-                            p: self.p,
-                            pc: self.pc,
-                            sp: self.sp,
-                        };
-
-                        if let Some(ref logger) = self.logger {
-                            match logger.send(events::CpuEvent::Instruction(data)) {
-                                Ok(_) => {},
-                                Err(_) => self.logger = None,
-                            }
-                        }
+                    match decode(opcode) {
+                        (Instruction::JAM, _) => State::Jammed,
+                        (instr, mode) => State::Execute(instr, mode, [opcode, 0, 0], 2),
                     }
                 }
-                
-                // Apply addressing mode
-                let addressing_result = match mode {
-                    AddressingMode::Immediate       => self.immediate(operand_data),
-                    AddressingMode::ZeroPage        => self.zeropage(operand_data),
-                    AddressingMode::ZeroPageX       => self.zeropage_x(operand_data),
-                    AddressingMode::ZeroPageY       => self.zeropage_y(operand_data),
-                    AddressingMode::Absolute        => self.absolute(operand_data),
-                    AddressingMode::AbsoluteX       => self.absolute_x(operand_data),
-                    AddressingMode::AbsoluteY       => self.absolute_y(operand_data),
-                    AddressingMode::IndexedIndirect => self.indexed_indirect(operand_data),
-                    AddressingMode::IndirectIndexed => self.indirect_indexed(operand_data),
-                    AddressingMode::Indirect        => self.indirect(operand_data),
-                    AddressingMode::Relative        => self.relative(operand_data),
-                    AddressingMode::Accumulator     => self.accumulator(),
-                    AddressingMode::Implied         => AddressingModeResult::Implied,
-                };
+            },
+            State::Execute(instr, mode, mut opcode_data, cycle) => {
+                if self.execute_cycle(instr, mode, &mut opcode_data, cycle) {
+                    self.end_instruction(instr, mode, opcode_data);
+                    State::Fetch
+                }
+                else {
+                    State::Execute(instr, mode, opcode_data, cycle + 1)
+                }
+            },
+            State::Interrupt(cycle) => {
+                if self.interrupt_cycle(cycle, false) {
+                    State::Fetch
+                }
+                else {
+                    State::Interrupt(cycle + 1)
+                }
+            },
+            State::Jammed => State::Jammed,
+        }
+    }
 
-                let read_mem = |addr: u16| -> u8 {
-                    self.read_u8(addr)
+    /// The current instruction has completed
+    #[allow(unused_variables)]
+    fn end_instruction(&mut self, instr: Instruction, mode: AddressingMode, opcode_data: [u8; 3]) {
+        // Is the PC pointing at the same instruction?
+        self.is_holding = self.opcode_addr == self.pc;
+
+        #[cfg(feature = "events")]
+        {
+            if self.debug {
+                let regs = self.event_regs;
+                let data = events::InstructionData {
+                    instr,
+                    mode,
+                    opcode_data,
+                    addr: self.opcode_addr,
+                    a: regs.a,
+                    x: regs.x,
+                    y: regs.y,
+                    p: regs.p,
+                    pc: self.opcode_addr.wrapping_add((mode.operand_len() + 1) as u16),
+                    sp: regs.sp,
                 };
 
+                if let Some(ref logger) = self.logger {
+                    if logger.send(events::CpuEvent::Instruction(data)).is_err() {
+                        self.logger = None;
+                    }
+                }
+            }
+        }
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Instruction Cycles
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Perform a single cycle of the current instruction. Cycle 1 is the opcode fetch. Returns true when the
+    /// instruction has completed
+    fn execute_cycle(&mut self, instr: Instruction, mode: AddressingMode, data: &mut [u8; 3], cycle: usize) -> bool {
+        match instr {
+            Instruction::BRK => return self.brk_cycle(data, cycle),
+            Instruction::RTI => return self.rti_cycle(cycle),
+            Instruction::RTS => return self.rts_cycle(cycle),
+            Instruction::JSR => return self.jsr_cycle(data, cycle),
+            Instruction::JMP => return self.jmp_cycle(mode, data, cycle),
+            Instruction::PHA | Instruction::PHP => return self.push_cycle(instr, cycle),
+            Instruction::PLA | Instruction::PLP => return self.pull_cycle(instr, cycle),
+            _ => {},
+        }
+
+        match mode {
+            AddressingMode::Implied => {
+                // Dummy read of the next instruction byte
+                self.read_u8(self.pc);
+                self.implied_op(instr);
+                true
+            },
+            AddressingMode::Accumulator => {
+                self.read_u8(self.pc);
+                self.a = self.modify_op(instr, self.a);
+                true
+            },
+            AddressingMode::Immediate => {
+                let m = self.fetch_operand(data, 1);
+                self.read_op(instr, m);
+                true
+            },
+            AddressingMode::Relative => self.branch_cycle(instr, data, cycle),
+            _ => {
+                let access = access_type(instr);
+                let first = self.first_access_cycle(mode, access);
+
+                if cycle < first {
+                    self.address_cycle(mode, data, cycle);
+                    false
+                }
+                else {
+                    self.access_cycle(instr, access, cycle - first)
+                }
+            },
+        }
+    }
+
+    /// Cycle on which memory at the effective address is first accessed
+    fn first_access_cycle(&self, mode: AddressingMode, access: Access) -> usize {
+        // Indexed reads skip the fix up cycle when the page is not crossed
+        let skip_fixup = (access == Access::Read && !self.crossed) as usize;
+
+        match mode {
+            AddressingMode::ZeroPage => 3,
+            AddressingMode::ZeroPageX | AddressingMode::ZeroPageY | AddressingMode::Absolute => 4,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => 5 - skip_fixup,
+            AddressingMode::IndexedIndirect => 6,
+            AddressingMode::IndirectIndexed => 6 - skip_fixup,
+            _ => unreachable!("No memory access for addressing mode {:?}", mode),
+        }
+    }
+
+    /// Calculate the effective address
+    fn address_cycle(&mut self, mode: AddressingMode, data: &mut [u8; 3], cycle: usize) {
+        match (mode, cycle) {
+            // Fetch the low byte of the address, or the zero page pointer
+            (_, 2) => {
+                self.addr = self.fetch_operand(data, 1) as u16;
+            },
+            // Zero page indexed. Read from the base address while the index is added
+            (AddressingMode::ZeroPageX, 3) | (AddressingMode::ZeroPageY, 3) => {
+                self.read_u8(self.addr);
+                let index = if mode == AddressingMode::ZeroPageX { self.x } else { self.y };
+                self.addr = (self.addr + index as u16) & 0xFF;
+            },
+            // Fetch the high byte of the address
+            (AddressingMode::Absolute, 3) => {
+                let hi = self.fetch_operand(data, 2) as u16;
+                self.addr |= hi << 8;
+            },
+            (AddressingMode::AbsoluteX, 3) | (AddressingMode::AbsoluteY, 3) => {
+                let hi = self.fetch_operand(data, 2);
+                let index = if mode == AddressingMode::AbsoluteX { self.x } else { self.y };
+                self.index_address(hi, index);
+            },
+            // Read from the address before the high byte is fixed
+            (AddressingMode::AbsoluteX, 4) | (AddressingMode::AbsoluteY, 4) | (AddressingMode::IndirectIndexed, 5) => {
+                self.read_u8(self.unfixed_address());
+            },
+            (AddressingMode::IndexedIndirect, 3) => {
+                self.read_u8(self.addr);
+                self.addr = (self.addr + self.x as u16) & 0xFF;
+            },
+            (AddressingMode::IndexedIndirect, 4) | (AddressingMode::IndirectIndexed, 3) => {
+                self.data = self.read_u8(self.addr);
+            },
+            (AddressingMode::IndexedIndirect, 5) => {
+                let hi = self.read_u8((self.addr + 1) & 0xFF) as u16;
+                self.addr = (hi << 8) | self.data as u16;
+            },
+            (AddressingMode::IndirectIndexed, 4) => {
+                let hi = self.read_u8((self.addr + 1) & 0xFF);
+                self.addr = self.data as u16;
+                self.index_address(hi, self.y);
+            },
+            _ => unreachable!("Invalid address cycle {} for {:?}", cycle, mode),
+        }
+    }
+
+    /// Add an index to the low byte of the address in `self.addr`, using `hi` as the base high byte
+    fn index_address(&mut self, hi: u8, index: u8) {
+        let base = ((hi as u16) << 8) | (self.addr & 0xFF);
+        self.addr = base.wrapping_add(index as u16);
+        self.crossed = (base & 0xFF00) != (self.addr & 0xFF00);
+        // Keep the base high byte for the read before the fix up
+        self.data = hi;
+    }
+
+    /// The effective address with the high byte from the base address
+    fn unfixed_address(&self) -> u16 {
+        ((self.data as u16) << 8) | (self.addr & 0xFF)
+    }
+
+    /// Read, write or read-modify-write the effective address
+    fn access_cycle(&mut self, instr: Instruction, access: Access, step: usize) -> bool {
+        match (access, step) {
+            (Access::Read, 0) => {
+                let m = self.read_u8(self.addr);
+                self.read_op(instr, m);
+                true
+            },
+            (Access::Write, 0) => {
+                let value = self.store_value(instr);
+                self.write_u8(self.addr, value);
+                true
+            },
+            (Access::Modify, 0) => {
+                self.data = self.read_u8(self.addr);
+                false
+            },
+            (Access::Modify, 1) => {
+                // The unmodified value is written back while the operation is performed
+                self.write_u8(self.addr, self.data);
+                self.data = self.modify_op(instr, self.data);
+                false
+            },
+            (Access::Modify, 2) => {
+                self.write_u8(self.addr, self.data);
+                true
+            },
+            _ => unreachable!("Invalid access cycle"),
+        }
+    }
+
+    fn branch_cycle(&mut self, instr: Instruction, data: &mut [u8; 3], cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                let offset = self.fetch_operand(data, 1);
+                self.addr = self.pc.wrapping_add(offset as i8 as u16);
+                !self.branch_taken(instr)
+            },
+            3 => {
+                self.read_u8(self.pc);
+                // The low byte of the PC is updated first
+                let crossed = (self.pc & 0xFF00) != (self.addr & 0xFF00);
+                self.pc = (self.pc & 0xFF00) | (self.addr & 0x00FF);
+
+                // A taken branch that does not cross a page does not poll interrupts on its last cycles. An IRQ that
+                // arrived during the operand fetch is delayed by an instruction
+                if !crossed && !self.poll_irq {
+                    self.irq_pending = false;
+                }
+
+                !crossed
+            },
+            _ => {
+                // Read from the wrong page and fix the high byte
+                self.read_u8(self.pc);
+                self.pc = self.addr;
+                true
+            },
+        }
+    }
+
+    fn jmp_cycle(&mut self, mode: AddressingMode, data: &mut [u8; 3], cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.addr = self.fetch_operand(data, 1) as u16;
+                false
+            },
+            3 => {
+                let hi = self.fetch_operand(data, 2) as u16;
+                self.addr |= hi << 8;
+
+                if mode == AddressingMode::Absolute {
+                    self.pc = self.addr;
+                }
+
+                mode == AddressingMode::Absolute
+            },
+            4 => {
+                self.data = self.read_u8(self.addr);
+                false
+            },
+            _ => {
+                // The high byte is always fetched from the same page as the low byte
+                let ptr = (self.addr & 0xFF00) | (self.addr.wrapping_add(1) & 0x00FF);
+                let hi = self.read_u8(ptr) as u16;
+                self.pc = (hi << 8) | self.data as u16;
+                true
+            },
+        }
+    }
+
+    fn jsr_cycle(&mut self, data: &mut [u8; 3], cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.data = self.fetch_operand(data, 1);
+                false
+            },
+            3 => {
+                self.read_u8(self.stack_address());
+                false
+            },
+            // The return address is the last byte of the JSR instruction
+            4 => {
+                self.push(high_byte!(self.pc) as u8);
+                false
+            },
+            5 => {
+                self.push(low_byte!(self.pc) as u8);
+                false
+            },
+            _ => {
+                let hi = self.read_u8(self.pc);
+                data[2] = hi;
+                self.pc = ((hi as u16) << 8) | self.data as u16;
+                true
+            },
+        }
+    }
+
+    fn rts_cycle(&mut self, cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.read_u8(self.pc);
+                false
+            },
+            3 => {
+                self.read_u8(self.stack_address());
+                false
+            },
+            4 => {
+                self.data = self.pull();
+                false
+            },
+            5 => {
+                let hi = self.pull() as u16;
+                self.pc = (hi << 8) | self.data as u16;
+                false
+            },
+            _ => {
+                self.read_u8(self.pc);
+                self.pc = self.pc.wrapping_add(1);
+                true
+            },
+        }
+    }
+
+    fn rti_cycle(&mut self, cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.read_u8(self.pc);
+                false
+            },
+            3 => {
+                self.read_u8(self.stack_address());
+                false
+            },
+            4 => {
+                self.plp();
+                false
+            },
+            5 => {
+                self.data = self.pull();
+                false
+            },
+            _ => {
+                let hi = self.pull() as u16;
+                self.pc = (hi << 8) | self.data as u16;
+                true
+            },
+        }
+    }
+
+    fn brk_cycle(&mut self, data: &mut [u8; 3], cycle: usize) -> bool {
+        match cycle {
+            // The byte following BRK is skipped
+            2 => {
+                self.fetch_operand(data, 1);
+                false
+            },
+            3 => {
+                self.push(high_byte!(self.pc) as u8);
+                false
+            },
+            4 => {
+                self.push(low_byte!(self.pc) as u8);
+                false
+            },
+            5 => {
+                self.addr = self.interrupt_vector();
+                // OR with $30 to set the B flag
+                self.push(self.p | bv!(4) | bv!(5));
+                self.set_flag_bit(Flags::InterruptDisable, true);
+                false
+            },
+            6 => {
+                self.data = self.read_u8(self.addr);
+                false
+            },
+            _ => {
+                let hi = self.read_u8(self.addr + 1) as u16;
+                self.pc = (hi << 8) | self.data as u16;
+                true
+            },
+        }
+    }
+
+    fn push_cycle(&mut self, instr: Instruction, cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.read_u8(self.pc);
+                false
+            },
+            _ => {
                 match instr {
-                    Instruction::NOP => {},
-                    Instruction::CLC => self.clc(),
-                    Instruction::CLD => self.cld(),
-                    Instruction::CLI => self.cli(),
-                    Instruction::CLV => self.clv(),
-                    Instruction::DEX => self.dex(),
-                    Instruction::DEY => self.dey(),
-                    Instruction::INX => self.inx(),
-                    Instruction::INY => self.iny(),
                     Instruction::PHA => self.pha(),
-                    Instruction::PHP => self.php(),
+                    _ => self.php(),
+                }
+                true
+            },
+        }
+    }
+
+    fn pull_cycle(&mut self, instr: Instruction, cycle: usize) -> bool {
+        match cycle {
+            2 => {
+                self.read_u8(self.pc);
+                false
+            },
+            3 => {
+                self.read_u8(self.stack_address());
+                false
+            },
+            _ => {
+                match instr {
                     Instruction::PLA => self.pla(),
-                    Instruction::PLP => self.plp(),
-                    Instruction::RTI => self.rti(),
-                    Instruction::RTS => self.rts(),
-                    Instruction::SEC => self.sec(),
-                    Instruction::SED => self.sed(),
-                    Instruction::SEI => self.sei(),
-                    Instruction::TAX => self.tax(),
-                    Instruction::TAY => self.tay(),
-                    Instruction::TSX => self.tsx(),
-                    Instruction::TXA => self.txa(),
-                    Instruction::TXS => self.txs(),
-                    Instruction::TYA => self.tya(),
-                    Instruction::BRK => self.brk(),
-                    Instruction::ALR => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.alr(byte.unwrap());
-                    },
-                    Instruction::ARR => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.arr(byte.unwrap());
-                    },
-                    Instruction::AXS => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.axs(byte.unwrap());
-                    },
-                    Instruction::LDA => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.lda(byte.unwrap())
-                    },
-                    Instruction::LAX => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.lax(byte.unwrap())
-                    },
-                    Instruction::JMP => {
-                        let addr = addressing_result.to_address();
-                        self.jmp(addr.unwrap())
-                    },
-                    Instruction::ADC => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.adc(byte.unwrap())
-                    },
-                    Instruction::AND => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.and(byte.unwrap())
-                    },
-                    Instruction::ANC => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.anc(byte.unwrap())
-                    }
-                    Instruction::BCC => {
-                        let offset = addressing_result.to_offset();
-                        self.bcc(offset.unwrap())
-                    },
-                    Instruction::BCS => {
-                        let offset = addressing_result.to_offset();
-                        self.bcs(offset.unwrap())
-                    },
-                    Instruction::BEQ => {
-                        let offset = addressing_result.to_offset();
-                        self.beq(offset.unwrap())
-                    },
-                    Instruction::BNE => {
-                        let offset = addressing_result.to_offset();
-                        self.bne(offset.unwrap())
-                    },
-                    Instruction::BMI => {
-                        let offset = addressing_result.to_offset();
-                        self.bmi(offset.unwrap())
-                    },
-                    Instruction::BPL => {
-                        let offset = addressing_result.to_offset();
-                        self.bpl(offset.unwrap())
-                    },
-                    Instruction::BVC => {
-                        let offset = addressing_result.to_offset();
-                        self.bvc(offset.unwrap())
-                    },
-                    Instruction::BVS => {
-                        let offset = addressing_result.to_offset();
-                        self.bvs(offset.unwrap())
-                    },
-                    Instruction::BIT => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.bit(byte.unwrap())
-                    },
-                    Instruction::CMP => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.cmp(byte.unwrap())
-                    },
-                    Instruction::CPX => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.cpx(byte.unwrap())
-                    },
-                    Instruction::CPY => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.cpy(byte.unwrap())
-                    },
-                    Instruction::EOR => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.eor(byte.unwrap())
-                    },
-                    Instruction::LDX => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.ldx(byte.unwrap())
-                    },
-                    Instruction::LDY => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.ldy(byte.unwrap())
-                    },
-                    Instruction::SBC => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.sbc(byte.unwrap())
-                    },
-                    Instruction::ORA => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        self.ora(byte.unwrap())
-                    },
-                    Instruction::JSR => {
-                        let addr = addressing_result.to_address();
-                        self.jsr(addr.unwrap())
-                    },
-                    Instruction::STA => {
-                        let v = self.sta();
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::STX => {
-                        let v = self.stx();
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::STY => {
-                        let v = self.sty();
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::SAX => {
-                        let v = self.sax();
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::SHY => {
-                        let byte = high_byte!(addressing_result.to_address().unwrap()) as u8;
-                        let v = self.shy(byte.wrapping_add(1));
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::SHX => {
-                        let byte = high_byte!(addressing_result.to_address().unwrap()) as u8;
-                        let v = self.shx(byte.wrapping_add(1));
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::ASL => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.asl(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::ROR => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.ror(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::ROL => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.rol(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::LSR => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.lsr(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::INC => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.inc(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::DEC => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.dec(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::DCP => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let v = self.dcp(byte.unwrap());
-                        self.write_result(addressing_result, v);
-                    },
-                    Instruction::ISB => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let m = self.isb(byte.unwrap());
-                        self.write_result(addressing_result, m);
-                    },
-                    Instruction::SLO => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let m = self.slo(byte.unwrap());
-                        self.write_result(addressing_result, m);
-                    },
-                    Instruction::RLA => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let m = self.rla(byte.unwrap());
-                        self.write_result(addressing_result, m);
-                    },
-                    Instruction::SRE => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let m = self.sre(byte.unwrap());
-                        self.write_result(addressing_result, m);
-                    },
-                    Instruction::RRA => {
-                        let byte = addressing_result.to_byte(read_mem);
-                        let m = self.rra(byte.unwrap());
-                        self.write_result(addressing_result, m);
-                    }
+                    _ => self.plp(),
                 }
+                true
+            },
+        }
+    }
 
-                State::Fetch
+    /// NMI, IRQ and reset sequence. Cycle 1 is the discarded opcode fetch
+    ///
+    /// Reset uses the same sequence with the stack writes suppressed
+    fn interrupt_cycle(&mut self, cycle: usize, reset: bool) -> bool {
+        match cycle {
+            2 => {
+                self.read_u8(self.pc);
+                false
+            },
+            3 => {
+                self.interrupt_push(high_byte!(self.pc) as u8, reset);
+                false
+            },
+            4 => {
+                self.interrupt_push(low_byte!(self.pc) as u8, reset);
+                false
+            },
+            5 => {
+                self.addr = if reset { memorymap::RESET_VECTOR } else { self.interrupt_vector() };
+                self.interrupt_push(self.p, reset);
+                self.set_flag_bit(Flags::InterruptDisable, true);
+                false
+            },
+            6 => {
+                self.data = self.read_u8(self.addr);
+                false
+            },
+            _ => {
+                let hi = self.read_u8(self.addr + 1) as u16;
+                self.pc = (hi << 8) | self.data as u16;
+                true
             },
         }
     }
 
+    /// Push onto the stack during the interrupt sequence. On reset the write becomes a read
+    fn interrupt_push(&mut self, data: u8, reset: bool) {
+        if reset {
+            self.read_u8(self.stack_address());
+            self.sp = self.sp.wrapping_sub(1);
+        }
+        else {
+            self.push(data);
+        }
+    }
+
+    /// Select the vector for BRK and the interrupt sequence. An NMI detected before the status register is pushed
+    /// hijacks the sequence
+    fn interrupt_vector(&mut self) -> u16 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            memorymap::NMI_VECTOR
+        }
+        else {
+            memorymap::IRQ_VECTOR
+        }
+    }
+
+    /// Sample the interrupt lines at the end of a cycle
+    fn poll_interrupts(&mut self) {
+        // The CPU checks the state from the end of the second to last cycle of an instruction
+        self.poll_nmi = self.nmi_pending;
+        self.poll_irq = self.irq_pending;
+
+        let (nmi, irq) = self.bus.as_ref().map_or((false, false), |bus| (bus.nmi_line(), bus.irq_line()));
+
+        // NMI is edge triggered and remains pending until handled
+        if nmi && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = nmi;
+
+        // IRQ is level triggered and masked by the I flag
+        self.irq_pending = irq && !self.get_flag_bit(Flags::InterruptDisable);
+    }
+
     //------------------------------------------------------------------------------------------------------------------
-    // Opcode Decoding
+    // Instruction Operations
     //------------------------------------------------------------------------------------------------------------------
 
-    /// Convert opcode into instruction and addressing mode and return an execute state
-    fn get_execute_state(&mut self, opcode: u8) -> State {
-        let (instr, mode) = decode(opcode);
+    /// Operation of an instruction that reads its operand
+    fn read_op(&mut self, instr: Instruction, m: u8) {
+        match instr {
+            Instruction::NOP => {},
+            Instruction::LDA => self.lda(m),
+            Instruction::LAX => self.lax(m),
+            Instruction::LDX => self.ldx(m),
+            Instruction::LDY => self.ldy(m),
+            Instruction::ADC => self.adc(m),
+            Instruction::SBC => self.sbc(m),
+            Instruction::AND => self.and(m),
+            Instruction::ANC => self.anc(m),
+            Instruction::ALR => self.alr(m),
+            Instruction::ARR => self.arr(m),
+            Instruction::AXS => self.axs(m),
+            Instruction::LAS => self.las(m),
+            Instruction::XAA => self.xaa(m),
+            Instruction::LXA => self.lxa(m),
+            Instruction::ORA => self.ora(m),
+            Instruction::EOR => self.eor(m),
+            Instruction::BIT => self.bit(m),
+            Instruction::CMP => self.cmp(m),
+            Instruction::CPX => self.cpx(m),
+            Instruction::CPY => self.cpy(m),
+            _ => unreachable!("{:?} does not read memory", instr),
+        }
+    }
 
-        let operand_data = self.fetch_operand_data(mode.operand_len());
-        let opcode_data: [u8; 3] = [opcode, operand_data[0], operand_data[1]];
+    /// Operation of a read-modify-write instruction. Returns the modified value
+    fn modify_op(&mut self, instr: Instruction, m: u8) -> u8 {
+        match instr {
+            Instruction::ASL => self.asl(m),
+            Instruction::LSR => self.lsr(m),
+            Instruction::ROL => self.rol(m),
+            Instruction::ROR => self.ror(m),
+            Instruction::INC => self.inc(m),
+            Instruction::DEC => self.dec(m),
+            Instruction::DCP => self.dcp(m),
+            Instruction::ISB => self.isb(m),
+            Instruction::SLO => self.slo(m),
+            Instruction::RLA => self.rla(m),
+            Instruction::SRE => self.sre(m),
+            Instruction::RRA => self.rra(m),
+            _ => unreachable!("{:?} does not modify memory", instr),
+        }
+    }
 
-        State::Execute(instr, mode, opcode_data, 0)
+    /// Value written by a store instruction
+    fn store_value(&mut self, instr: Instruction) -> u8 {
+        match instr {
+            Instruction::STA => self.sta(),
+            Instruction::STX => self.stx(),
+            Instruction::STY => self.sty(),
+            Instruction::SAX => self.sax(),
+            Instruction::SHY => self.unstable_store(self.y),
+            Instruction::SHX => self.unstable_store(self.x),
+            Instruction::SHA => self.unstable_store(self.a & self.x),
+            Instruction::TAS => {
+                self.sp = self.a & self.x;
+                self.unstable_store(self.sp)
+            },
+            _ => unreachable!("{:?} does not write memory", instr),
+        }
     }
 
-    fn fetch_operand_data(&mut self, num_bytes: usize) -> [u8; 2] {
-        let mut operand_data = [0u8; 2];
+    /// SHY, SHX, SHA and TAS store a value ANDed with the high byte of the base address plus one. When indexing crosses
+    /// a page, the stored value also replaces the high byte of the effective address
+    fn unstable_store(&mut self, value: u8) -> u8 {
+        // The base high byte is kept from indexing
+        let value = value & self.data.wrapping_add(1);
 
-        for item in operand_data.iter_mut().take(num_bytes) {
-            *item = self.read_next_u8();
+        if self.crossed {
+            self.addr = ((value as u16) << 8) | (self.addr & 0x00FF);
         }
 
-        operand_data
+        value
+    }
+
+    /// Operation of an instruction that only uses registers
+    fn implied_op(&mut self, instr: Instruction) {
+        match instr {
+            Instruction::NOP => {},
+            Instruction::CLC => self.clc(),
+            Instruction::CLD => self.cld(),
+            Instruction::CLI => self.cli(),
+            Instruction::CLV => self.clv(),
+            Instruction::SEC => self.sec(),
+            Instruction::SED => self.sed(),
+            Instruction::SEI => self.sei(),
+            Instruction::DEX => self.dex(),
+            Instruction::DEY => self.dey(),
+            Instruction::INX => self.inx(),
+            Instruction::INY => self.iny(),
+            Instruction::TAX => self.tax(),
+            Instruction::TAY => self.tay(),
+            Instruction::TSX => self.tsx(),
+            Instruction::TXA => self.txa(),
+            Instruction::TXS => self.txs(),
+            Instruction::TYA => self.tya(),
+            _ => unreachable!("{:?} is not an implied instruction", instr),
+        }
+    }
+
+    fn branch_taken(&self, instr: Instruction) -> bool {
+        match instr {
+            Instruction::BCC => !self.get_flag_bit(Flags::Carry),
+            Instruction::BCS => self.get_flag_bit(Flags::Carry),
+            Instruction::BEQ => self.get_flag_bit(Flags::Zero),
+            Instruction::BNE => !self.get_flag_bit(Flags::Zero),
+            Instruction::BMI => self.get_flag_bit(Flags::Negative),
+            Instruction::BPL => !self.get_flag_bit(Flags::Negative),
+            Instruction::BVC => !self.get_flag_bit(Flags::Overflow),
+            Instruction::BVS => self.get_flag_bit(Flags::Overflow),
+            _ => unreachable!("{:?} is not a branch", instr),
+        }
     }
 
     //------------------------------------------------------------------------------------------------------------------
@@ -520,6 +993,33 @@ impl<Io: IoAccess> Cpu<Io> {
         self.a & self.x
     }
 
+    /// LAS - M & SP -> A, X, SP
+    fn las(&mut self, m: u8) {
+        let r = m & self.sp;
+
+        self.a = r;
+        self.x = r;
+        self.sp = r;
+
+        self.update_flags(r);
+    }
+
+    /// XAA - (A | magic) & X & M -> A
+    fn xaa(&mut self, m: u8) {
+        self.a = (self.a | UNSTABLE_MAGIC) & self.x & m;
+        self.update_flags(self.a);
+    }
+
+    /// LXA - (A | magic) & M -> A, X
+    fn lxa(&mut self, m: u8) {
+        let r = (self.a | UNSTABLE_MAGIC) & m;
+
+        self.a = r;
+        self.x = r;
+
+        self.update_flags(r);
+    }
+
     fn dcp(&mut self, m: u8) -> u8 {
         let m = m.wrapping_sub(1);
         self.cmp(m);
@@ -527,11 +1027,6 @@ impl<Io: IoAccess> Cpu<Io> {
         m
     }
 
-    /// Jump
-    fn jmp(&mut self, addr: u16) {
-        self.pc = addr;
-    }
-
     /// ADC - Add with Carry
     fn adc(&mut self, m: u8) {
         // A,Z,C,N = A+M+C
@@ -598,57 +1093,26 @@ impl<Io: IoAccess> Cpu<Io> {
 
         self.a |= c << 7;
 
-        self.update_flags(self.a);
+        // Bit 7 of the AND result is shifted into bit 6 and copied to carry
+        self.update_flags_with_carry(self.a, b7 != 0);
         self.set_flag_bit(Flags::Overflow, v != 0);
     }
 
     /// AXS - A & X - M -> X
     fn axs(&mut self, m: u8) {
-        let r = self.a & self.x;
-        let r = r.wrapping_sub(m);
+        let ax = self.a & self.x;
+        let r = ax.wrapping_sub(m);
 
         self.x = r;
 
-        self.set_zero_flag(r);
+        // Flags are set as CMP, without borrow in
+        self.update_flags_with_carry(r, ax >= m);
     }
 
     fn sta(&mut self) -> u8 {
         self.a
     }
 
-    fn bcc(&mut self, offset: u8) {
-        self.branch(!self.get_flag_bit(Flags::Carry), offset);
-    }
-
-    fn bcs(&mut self, offset: u8) {
-        self.branch(self.get_flag_bit(Flags::Carry), offset);
-    }
-
-    fn beq(&mut self, offset: u8) {
-        self.branch(self.get_flag_bit(Flags::Zero), offset);
-    }
-
-    fn bne(&mut self, offset: u8) {
-        self.branch(!self.get_flag_bit(Flags::Zero), offset);
-    }
-
-    fn bmi(&mut self, offset: u8) {
-        self.branch(self.get_flag_bit(Flags::Negative), offset);
-    }
-
-    /// BPL - Branch if Positive
-    fn bpl(&mut self, offset: u8) {
-        self.branch(!self.get_flag_bit(Flags::Negative), offset);
-    }
-
-    fn bvc(&mut self, offset: u8) {
-        self.branch(!self.get_flag_bit(Flags::Overflow), offset);
-    }
-
-    fn bvs(&mut self, offset: u8) {
-        self.branch(self.get_flag_bit(Flags::Overflow), offset);
-    }
-
     /// BIT - Bit Test
     fn bit(&mut self, m: u8) {
         let r = self.a & m;
@@ -824,42 +1288,6 @@ impl<Io: IoAccess> Cpu<Io> {
         m
     }
 
-    fn rti(&mut self) {
-        self.plp();
-        self.pc = self.pull16();
-
-        self.set_flag_bit(Flags::InterruptDisable, false);
-    }
-
-    fn jsr(&mut self, addr: u16) {
-        //     JSR
-        //     #  address R/W description
-        //    --- ------- --- -------------------------------------------------
-        //     1    PC     R  fetch opcode, increment PC
-        //     2    PC     R  fetch low address byte, increment PC
-        //     3  $0100,S  R  internal operation (predecrement S?)
-        //     4  $0100,S  W  push PCH on stack, decrement S
-        //     5  $0100,S  W  push PCL on stack, decrement S
-        //     6    PC     R  copy low address byte to PCL, fetch high address
-        //                    byte to PCH
-        self.push16(self.pc-1);
-        self.pc = addr;
-    }
-
-    fn rts(&mut self) {
-        //  RTS
-        //  #  address R/W description
-        // --- ------- --- -----------------------------------------------
-        //  1    PC     R  fetch opcode, increment PC
-        //  2    PC     R  read next instruction byte (and throw it away)
-        //  3  $0100,S  R  increment S
-        //  4  $0100,S  R  pull PCL from stack, increment S
-        //  5  $0100,S  R  pull PCH from stack
-        //  6    PC     R  increment PC
-        self.pc = self.pull16();
-        self.pc = self.pc.wrapping_add(1);
-    }
-
     fn sbc(&mut self, m: u8) {
         let m = Wrapping(m as u16);
         let c = Wrapping(1u16) - Wrapping(self.get_carry() as u16);
@@ -906,14 +1334,6 @@ impl<Io: IoAccess> Cpu<Io> {
         self.set_flag_bit(Flags::InterruptDisable, true);
     }
 
-    fn shy(&self, m: u8) -> u8 {
-        self.y & m
-    }
-
-    fn shx(&self, m: u8) -> u8 {
-        self.x & m
-    }
-
     fn stx(&mut self) -> u8 {
         self.x
     }
@@ -956,121 +1376,6 @@ impl<Io: IoAccess> Cpu<Io> {
         self.set_zero_flag(self.a);
     }
 
-    fn brk(&mut self) {
-        // Docs for BRK say to add 2 to the PC when it goes on the stack.
-        // This is to skip the unused byte after the BRK
-        // The PC is already in the right spot
-        self.push16(self.pc);
-        // OR with $30 to set the B flag
-        self.push(self.p | bv!(4) | bv!(5));
-
-        self.pc = self.read_u16(memorymap::IRQ_VECTOR);
-
-        self.set_flag_bit(Flags::InterruptDisable, true);
-    }
-
-    //------------------------------------------------------------------------------------------------------------------
-    // Addressing Modes
-    //------------------------------------------------------------------------------------------------------------------
-
-    /// Immediate Addressing.
-    /// Put current PC value on the address bus
-    fn immediate(&self, data: &[u8]) -> AddressingModeResult {
-        AddressingModeResult::Byte(data[0])
-    }
-
-    fn accumulator(&self) -> AddressingModeResult {
-        AddressingModeResult::Byte(self.a)
-    }
-
-    /// Absolute Addressing.
-    /// Fetch the address to read from the next two bytes
-    fn absolute(&mut self, data: &[u8]) -> AddressingModeResult {
-        AddressingModeResult::Address(((data[1] as u16) << 8) | data[0] as u16)
-    }
-
-    /// Absolute Addressing Indexed by X
-    fn absolute_x(&mut self, data: &[u8]) -> AddressingModeResult {
-        self.absolute_i(data, self.x)
-    }
-
-    /// Absolute Addressing Indexed by Y
-    fn absolute_y(&mut self, data: &[u8]) -> AddressingModeResult {
-        self.absolute_i(data, self.y)
-    }
-
-    fn absolute_i(&mut self, data: &[u8], i: u8) -> AddressingModeResult {
-        let addr = ((data[1] as u16) << 8) | data[0] as u16;
-        let addr = (Wrapping(addr) + Wrapping(i as u16)).0;
-        AddressingModeResult::Address(addr)
-    }
-
-    /// Zero Page Addressing
-    /// Fetch the next byte and put it on the address bus
-    fn zeropage(&mut self, data: &[u8]) -> AddressingModeResult {
-        AddressingModeResult::Address(data[0] as u16)
-    }
-
-    /// Zero Page Index X Addressing.
-    fn zeropage_x(&mut self, data: &[u8]) -> AddressingModeResult {
-        self.zeropage_i(data, self.x)
-    }
-
-    /// Zero Page Index Y Addressing
-    fn zeropage_y(&mut self, data: &[u8]) -> AddressingModeResult {
-        self.zeropage_i(data, self.y)
-    }
-
-    fn zeropage_i(&mut self, data: &[u8], i: u8) -> AddressingModeResult {
-        AddressingModeResult::Address(((data[0] as u16) + i as u16) & 0xFF)
-    }
-
-    /// Indexed Indirect Addressing
-    fn indexed_indirect(&mut self, data: &[u8]) -> AddressingModeResult {
-        let ptr = ((data[0] as u16) + (self.x as u16)) & 0xFF;
-
-        let addr = self.indirect_read(ptr);
-
-        AddressingModeResult::Address(addr)
-    }
-
-    /// Indirect Indexed Addressing
-    fn indirect_indexed(&mut self, data: &[u8]) -> AddressingModeResult {
-        let ptr = data[0] as u16;
-
-        let addr = self.indirect_read(ptr);
-        let addr = addr.wrapping_add(self.y as u16);
-
-        AddressingModeResult::Address(addr)
-    }
-
-    /// Indirect
-    /// Only applicable to JMP instruction
-    fn indirect(&self, data: &[u8]) -> AddressingModeResult {
-        let ptr = ((data[1] as u16) << 8) | data[0] as u16;
-
-        let addr = self.indirect_read(ptr);
-
-        AddressingModeResult::Address(addr)
-    }
-
-    fn indirect_read(&self, ptr: u16) -> u16 {
-        // Note: The PCH will always be fetched from the same page
-        // as PCL, i.e. page boundary crossing is not handled.
-        let page = ptr & 0xFF00;
-        let addr_lo = ptr;
-        let addr_hi = page | ((ptr + 0x01) & 0x00FF);
-
-        let lo = self.read_u8(addr_lo) as u16;
-        let hi = self.read_u8(addr_hi) as u16;
-
-        (hi << 8) | lo
-    }
-
-    fn relative(&mut self, data: &[u8]) -> AddressingModeResult {
-        AddressingModeResult::Offset(data[0])
-    }
-
     //------------------------------------------------------------------------------------------------------------------
     // Flags Register
     //------------------------------------------------------------------------------------------------------------------
@@ -1121,17 +1426,6 @@ impl<Io: IoAccess> Cpu<Io> {
     // CPU Operations
     //------------------------------------------------------------------------------------------------------------------
 
-    /// Branch
-    fn branch(&mut self, cond_met: bool, offset: u8) {
-        if cond_met {
-            let offset = offset as i8;
-            let offset = offset as i16;
-            let base_addr = self.pc as i16;
-
-            self.pc = (Wrapping(base_addr) + Wrapping(offset)).0 as u16;
-        }
-    }
-
     /// Do and compare operation on the given arguments and set appropriate flags
     fn compare(&mut self, a: u8, m: u8) {
         let r = (Wrapping(a) - Wrapping(m)).0;
@@ -1159,6 +1453,11 @@ impl<Io: IoAccess> Cpu<Io> {
         new_a
     }
 
+    /// Current address of the stack pointer
+    fn stack_address(&self) -> u16 {
+        (self.sp as u16) + STACK_PAGE_OFFSET
+    }
+
     /// Push a value onto the stack
     fn push(&mut self, data: u8) {
         // The stack is always stored in page 1
@@ -1172,21 +1471,6 @@ impl<Io: IoAccess> Cpu<Io> {
         self.read_u8((self.sp as u16) + STACK_PAGE_OFFSET)
     }
 
-    fn push16(&mut self, data: u16) {
-        let hi = high_byte!(data) as u8;
-        let lo = low_byte!(data) as u8;
-
-        self.push(hi);
-        self.push(lo);
-    }
-
-    fn pull16(&mut self) -> u16 {
-        let lo = self.pull() as u16;
-        let hi = self.pull() as u16;
-
-        (hi << 8) | lo
-    }
-
     //------------------------------------------------------------------------------------------------------------------
     // Base CPU Read/Write Operations
     //------------------------------------------------------------------------------------------------------------------
@@ -1196,18 +1480,12 @@ impl<Io: IoAccess> Cpu<Io> {
         self.read_next_u8()
     }
 
-    fn write_result(&mut self, addressing_result: AddressingModeResult, value: u8) {
-        let mode = match self.state {
-            State::Execute(_, mode, _, _) => mode,
-            _ => panic!("Must be in execution state!"),
-        };
+    /// Fetch an operand byte of the current instruction and increment the program counter
+    fn fetch_operand(&mut self, opcode_data: &mut [u8; 3], idx: usize) -> u8 {
+        let byte = self.read_next_u8();
+        opcode_data[idx] = byte;
 
-        if mode == AddressingMode::Accumulator {
-            self.a = value;
-        }
-        else {
-            self.write_u8(addressing_result.to_address().unwrap(), value);
-        }
+        byte
     }
 
     fn read_next_u8(&mut self) -> u8 {
@@ -1217,13 +1495,6 @@ impl<Io: IoAccess> Cpu<Io> {
         byte
     }
 
-    fn read_u16(&mut self, addr: u16) -> u16 {
-        let lo = self.read_u8(addr) as u16;
-        let hi = self.read_u8(addr + 1) as u16;
-
-        (hi << 8) | lo
-    }
-
     fn read_u8(&self, addr: u16) -> u8 {
         if let Some(ref bus) = self.bus {
             bus.read_byte(addr)
@@ -1239,40 +1510,145 @@ impl<Io: IoAccess> Cpu<Io> {
         }
     }
 
-    fn interrupt(&mut self, int_type: Interrupt) {
-        self.interrupted = None;
+}
+
+impl<Io: IoAccess> Clockable for Cpu<Io> {
+    /// Execute one CPU cycle
+    fn tick(&mut self) {
+        // The DMC requests a fetch whenever its sample buffer is empty
+        if let Some(addr) = self.bus.as_ref().and_then(|bus| bus.dmc_dma_request()) {
+            // The instruction at a boundary has already been announced. Do not report it again once the CPU resumes
+            self.resume_fetch |= self.at_instruction_boundary();
+            self.dma.start_dmc(addr);
+        }
+
+        // The CPU is halted while DMA is in progress
+        if self.dma.is_active() {
+            self.run_dma_cycle();
+            self.poll_interrupts();
+            self.odd_cycle = !self.odd_cycle;
 
-        self.push16(self.pc);
-        self.push(self.p);
+            if !self.dma.is_active() {
+                self.begin_instruction();
+            }
 
-        // Load the vector for the specified interrupt
-        self.pc = match int_type {
-            Interrupt::Nmi => self.read_u16(memorymap::NMI_VECTOR),
-            Interrupt::Irq => self.read_u16(memorymap::IRQ_VECTOR),
-        };
+            return;
+        }
 
-        self.set_flag_bit(Flags::InterruptDisable, true);
+        // Implement one cycle of the CPU using a state machine
+        // Execute the cycle based on the current CPU state and return the next CPU state
+        self.state = self.run_cycle(self.state);
+        self.resume_fetch = false;
+
+        self.poll_interrupts();
+
+        // A write to $4014 halts the CPU on the following cycle
+        if let Some(page) = self.bus.as_mut().and_then(|bus| bus.take_dma_request()) {
+            self.dma.start_oam(page);
+        }
+
+        self.odd_cycle = !self.odd_cycle;
+
+        self.begin_instruction();
     }
 }
 
-impl<Io: IoAccess> IoAccess for Cpu<Io> {
-    fn raise_interrupt(&mut self, interrupt_type: Interrupt) {
-        if self.interrupted.is_none() {
-            self.interrupted = Some(interrupt_type);
+impl<Io: IoAccess + Snapshot> Snapshot for Cpu<Io> {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.a);
+        state.write_u8(self.x);
+        state.write_u8(self.y);
+        state.write_u16(self.pc);
+        state.write_u8(self.sp);
+        state.write_u8(self.p);
+
+        // The instruction and addressing mode are decoded from the opcode when the state is loaded
+        match self.state {
+            State::Reset(cycle) => {
+                state.write_u8(0);
+                state.write_usize(cycle);
+            },
+            State::Fetch => state.write_u8(1),
+            State::Execute(_, _, opcode_data, cycle) => {
+                state.write_u8(2);
+                state.write_bytes(&opcode_data);
+                state.write_usize(cycle);
+            },
+            State::Interrupt(cycle) => {
+                state.write_u8(3);
+                state.write_usize(cycle);
+            },
+            State::Jammed => state.write_u8(4),
+        }
+
+        state.write_u16(self.opcode_addr);
+        state.write_u16(self.addr);
+        state.write_u8(self.data);
+        state.write_bool(self.crossed);
+
+        state.write_bool(self.nmi_line);
+        state.write_bool(self.nmi_pending);
+        state.write_bool(self.irq_pending);
+        state.write_bool(self.poll_nmi);
+        state.write_bool(self.poll_irq);
+
+        state.write_bool(self.is_holding);
+
+        self.dma.save_state(state);
+        state.write_bool(self.odd_cycle);
+        state.write_bool(self.resume_fetch);
+
+        if let Some(ref bus) = self.bus {
+            bus.save_state(state);
         }
     }
-}
 
-impl<Io: IoAccess> Clockable for Cpu<Io> {
-    /// Execute one CPU cycle
-    fn tick(&mut self) {
-        // Get the current PC
-        let prev_pc = self.pc;
-        // Implement one cycle of the CPU using a state machine
-        // Execute the cycle based on the current CPU state and return the next CPU state
-        self.state = self.run_cycle(self.state);
-        // Is the PC pointing at the same location?
-        self.is_holding = prev_pc == self.pc;
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.a = state.read_u8()?;
+        self.x = state.read_u8()?;
+        self.y = state.read_u8()?;
+        self.pc = state.read_u16()?;
+        self.sp = state.read_u8()?;
+        self.p = state.read_u8()?;
+
+        self.state = match state.read_u8()? {
+            0 => State::Reset(state.read_usize()?),
+            1 => State::Fetch,
+            2 => {
+                let mut opcode_data = [0u8; 3];
+                state.read_bytes(&mut opcode_data)?;
+                let cycle = state.read_usize()?;
+
+                let (instr, mode) = decode(opcode_data[0]);
+                State::Execute(instr, mode, opcode_data, cycle)
+            },
+            3 => State::Interrupt(state.read_usize()?),
+            4 => State::Jammed,
+            _ => return Err(StateError::InvalidData),
+        };
+
+        self.opcode_addr = state.read_u16()?;
+        self.addr = state.read_u16()?;
+        self.data = state.read_u8()?;
+        self.crossed = state.read_bool()?;
+
+        self.nmi_line = state.read_bool()?;
+        self.nmi_pending = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+        self.poll_nmi = state.read_bool()?;
+        self.poll_irq = state.read_bool()?;
+
+        self.is_holding = state.read_bool()?;
+
+        self.dma.load_state(state)?;
+        self.odd_cycle = state.read_bool()?;
+        self.resume_fetch = state.read_bool()?;
+
+        if let Some(ref mut bus) = self.bus {
+            bus.load_state(state)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -1285,12 +1661,42 @@ mod tests {
     use super::*;
     use helper::*;
 
+    #[test]
+    fn save_state_round_trip() {
+        // LDA #$DE, STA $00, INX, JMP $4020
+        let prg = vec![0xA9, 0xDE, 0x85, 0x00, 0xE8, 0x4C, 0x20, 0x40];
+
+        // Stop in the middle of the STA instruction
+        let mut cpu = init_cpu(prg.clone());
+        run_cpu(&mut cpu, 3);
+
+        let mut writer = StateWriter::default();
+        cpu.save_state(&mut writer);
+        let data = writer.into_inner();
+
+        let mut restored = init_cpu(prg);
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+
+        for _ in 0..20 {
+            cpu.tick();
+            restored.tick();
+
+            assert_eq!(restored.pc, cpu.pc);
+            assert_eq!(restored.a, cpu.a);
+            assert_eq!(restored.x, cpu.x);
+            assert_eq!(restored.p, cpu.p);
+        }
+
+        assert_eq!(restored.read_ram(0x0000), 0xDE);
+    }
+
     #[test]
     fn pc_after_reset() {
         let mut cpu = init_cpu(vec![]);
         cpu.pc = 0x0001;
 
-        simple_test_base(&mut cpu, 0);
+        cpu.reset();
+        run_ticks(&mut cpu, 7);
 
         assert_eq!(cpu.pc, 0x4020);
     }
@@ -1370,6 +1776,25 @@ mod tests {
         assert_eq!(cpu.a, 0xDE);
     }
 
+    #[test]
+    fn lda_absolute_x_page_cross() {
+        let prg = vec![
+            0xBD, 0xFF, 0x40, // LDA $40FF, X
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.x = 0x01;
+        cpu.write_u8(0x4100, 0xDE);
+
+        // Four cycles without the page crossing, one more to fix the high byte of the address
+        simple_test_base(&mut cpu, 4);
+        assert!(!cpu.at_instruction_boundary());
+
+        cpu.tick();
+        assert!(cpu.at_instruction_boundary());
+        assert_eq!(cpu.a, 0xDE);
+    }
+
     #[test]
     fn lda_absolute_y() {
         let prg = vec![
@@ -1489,6 +1914,178 @@ mod tests {
         assert_eq!(cpu.read_u8(0x02), 0x00);
     }
 
+    #[test]
+    fn las() {
+        let prg = vec![
+            0xBB, 0x00, 0x02, // LAS $0200, Y
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.sp = 0xF0;
+        cpu.write_u8(0x0200, 0x9F);
+
+        simple_test_base(&mut cpu, 4);
+
+        assert_eq!(cpu.a, 0x90);
+        assert_eq!(cpu.x, 0x90);
+        assert_eq!(cpu.sp, 0x90);
+        assert!(cpu.get_flag_bit(Flags::Negative));
+    }
+
+    #[test]
+    fn tas() {
+        let prg = vec![
+            0x9B, 0x00, 0x02, // TAS $0200, Y
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.a = 0xF7;
+        cpu.x = 0x7F;
+
+        simple_test_base(&mut cpu, 5);
+
+        // SP = A & X, stored value is SP & (H + 1)
+        assert_eq!(cpu.sp, 0x77);
+        assert_eq!(cpu.read_u8(0x0200), 0x03);
+    }
+
+    #[test]
+    fn sha_page_cross() {
+        let prg = vec![
+            0x9F, 0xFF, 0x02, // SHA $02FF, Y
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.a = 0xFF;
+        cpu.x = 0xF1;
+        cpu.y = 0x01;
+
+        simple_test_base(&mut cpu, 5);
+
+        // The value replaces the high byte of the effective address
+        assert_eq!(cpu.bus.as_ref().unwrap().writes, vec![(0x0100, 0x01)]);
+    }
+
+    #[test]
+    fn shy_no_page_cross() {
+        let prg = vec![
+            0x9C, 0x00, 0x02, // SHY $0200, X
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.y = 0xFF;
+        cpu.x = 0x01;
+
+        simple_test_base(&mut cpu, 5);
+
+        assert_eq!(cpu.bus.as_ref().unwrap().writes, vec![(0x0201, 0x03)]);
+    }
+
+    #[test]
+    fn lxa() {
+        let prg = vec![
+            0xAB, 0x5A, // LXA #$5A
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.a = 0x0F;
+
+        simple_test_base(&mut cpu, 2);
+
+        assert_eq!(cpu.a, 0x5A);
+        assert_eq!(cpu.x, 0x5A);
+    }
+
+    #[test]
+    fn xaa() {
+        let prg = vec![
+            0x8B, 0x5A, // XAA #$5A
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.x = 0x0F;
+
+        simple_test_base(&mut cpu, 2);
+
+        assert_eq!(cpu.a, 0x0A);
+    }
+
+    #[test]
+    fn axs_flags() {
+        let prg = vec![
+            0xCB, 0x10, // AXS #$10
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.a = 0x0F;
+        cpu.x = 0xFF;
+
+        simple_test_base(&mut cpu, 2);
+
+        assert_eq!(cpu.x, 0xFF);
+        assert!(!cpu.get_flag_bit(Flags::Carry));
+        assert!(cpu.get_flag_bit(Flags::Negative));
+    }
+
+    #[test]
+    fn arr_carry() {
+        let prg = vec![
+            0x6B, 0xC0, // ARR #$C0
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.a = 0xFF;
+        cpu.p = 0x00;
+
+        simple_test_base(&mut cpu, 2);
+
+        assert_eq!(cpu.a, 0x60);
+        assert!(cpu.get_flag_bit(Flags::Carry));
+        assert!(!cpu.get_flag_bit(Flags::Overflow));
+    }
+
+    #[test]
+    fn jam() {
+        let prg = vec![
+            0x02, // JAM
+        ];
+
+        let mut cpu = init_cpu(prg);
+
+        simple_test_base(&mut cpu, 10);
+
+        assert_eq!(cpu.jammed(), Some(0x4020));
+        assert!(!cpu.at_instruction_boundary());
+    }
+
+    #[test]
+    fn reset() {
+        let prg = vec![
+            0xA9, 0x55, // LDA #$55
+            0x02,       // JAM
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.p = 0x00;
+
+        simple_test_base(&mut cpu, 10);
+        assert_eq!(cpu.jammed(), Some(0x4022));
+
+        cpu.reset();
+
+        // The reset sequence takes 7 cycles
+        run_ticks(&mut cpu, 6);
+        assert!(!cpu.at_instruction_boundary());
+        cpu.tick();
+        assert!(cpu.at_instruction_boundary());
+
+        assert_eq!(cpu.jammed(), None);
+        assert_eq!(cpu.pc, 0x4020);
+        assert_eq!(cpu.sp, 0xFA);
+        assert_eq!(cpu.a, 0x55);
+        assert!(cpu.get_flag_bit(Flags::InterruptDisable));
+    }
+
     #[test]
     fn jmp_absolute() {
         let prg = vec![
@@ -1762,7 +2359,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_set!(cpu.p, Flags::Carry as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1776,7 +2374,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_clear!(cpu.p, Flags::Carry as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1804,7 +2403,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_clear!(cpu.p, Flags::Zero as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1860,7 +2460,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_set!(cpu.p, Flags::Zero as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1874,7 +2475,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_clear!(cpu.p, Flags::Negative as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1916,7 +2518,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_set!(cpu.p, Flags::Negative as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1944,7 +2547,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_set!(cpu.p, Flags::Overflow as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -1958,7 +2562,8 @@ mod tests {
         let mut cpu = init_cpu(prg);
         mask_clear!(cpu.p, Flags::Overflow as u8);
 
-        simple_test_base(&mut cpu, 3);
+        // Branches that are not taken complete in two cycles
+        simple_test_base(&mut cpu, 2);
 
         assert_eq!(cpu.pc, 0x4022);
     }
@@ -2109,6 +2714,22 @@ mod tests {
         assert_eq!(cpu.read_u8(0x02), 0x00);
     }
 
+    #[test]
+    fn inc_mem_double_write() {
+        let prg = vec![
+            0xEE, 0x00, 0x02, // INC $0200
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.write_u8(0x0200, 0x41);
+        cpu.bus.as_mut().unwrap().writes.clear();
+
+        simple_test_base(&mut cpu, 6);
+
+        // Read-modify-write instructions write back the unmodified value before the result
+        assert_eq!(cpu.bus.as_ref().unwrap().writes, vec![(0x0200, 0x41), (0x0200, 0x42)]);
+    }
+
     #[test]
     fn dex() {
         let prg = vec![
@@ -2621,7 +3242,7 @@ mod tests {
             0x4C, 0x20, 0x40, // JMP $4020; Infinite loop
         ];
 
-        let cpu = simple_test(prg, 2);
+        let cpu = simple_test(prg, 3);
 
         assert_eq!(cpu.is_holding(), true);
     }
@@ -2634,12 +3255,12 @@ mod tests {
 
         let mut cpu = init_cpu(prg);
         cpu.set_flag_bit(Flags::InterruptDisable, false);
-        cpu.raise_interrupt(Interrupt::Irq);
+        cpu.bus.as_mut().unwrap().irq = true;
 
-        // One tick to reset
-        cpu.tick();
-        // Another tick to interrupt
-        cpu.tick();
+        // The IRQ is polled during the NOP and the interrupt sequence takes 7 cycles
+        for _ in 0..9 {
+            cpu.tick();
+        }
         // Verify the IRQ interrupt vector has loaded
         assert_eq!(cpu.pc, 0x4030);
     }
@@ -2652,16 +3273,155 @@ mod tests {
 
         let mut cpu = init_cpu(prg);
         cpu.set_flag_bit(Flags::InterruptDisable, true);
-        cpu.raise_interrupt(Interrupt::Irq);
+        cpu.bus.as_mut().unwrap().irq = true;
 
-        // One tick to reset
+        // Another two ticks to execute the NOP; should be masked
         cpu.tick();
-        // Another tick to interrupt; should be masked
         cpu.tick();
         // Verify the IRQ vector was not loaded
         assert_eq!(cpu.pc, 0x4021);
     }
 
+    #[test]
+    fn irq_line_sampled() {
+        let prg = vec![
+            0xEA // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.set_flag_bit(Flags::InterruptDisable, false);
+
+        // Assert the IRQ line on the bus
+        cpu.bus.as_mut().unwrap().irq = true;
+        for _ in 0..9 {
+            cpu.tick();
+        }
+
+        // Verify the IRQ interrupt vector has loaded
+        assert_eq!(cpu.pc, 0x4030);
+    }
+
+    #[test]
+    fn nmi_edge_triggered() {
+        let prg = vec![
+            0xEA, // NOP
+            0xEA, // NOP
+            0xEA, // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+
+        // NMI is taken after the first NOP
+        cpu.bus.as_mut().unwrap().nmi = true;
+        run_ticks(&mut cpu, 9);
+
+        assert_eq!(cpu.pc, 0x4020);
+        assert_eq!(cpu.sp, 0xFA);
+
+        // The line is still asserted, but there is no new edge
+        run_ticks(&mut cpu, 6);
+
+        assert_eq!(cpu.pc, 0x4023);
+        assert_eq!(cpu.sp, 0xFA);
+    }
+
+    #[test]
+    fn nmi_hijacks_brk() {
+        let prg = vec![
+            0x00, // BRK
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.p = 0x00;
+
+        // The first three cycles of BRK
+        run_ticks(&mut cpu, 3);
+        // NMI edge before the status register is pushed
+        cpu.bus.as_mut().unwrap().nmi = true;
+        run_ticks(&mut cpu, 4);
+
+        // BRK completes using the NMI vector, with the B flag pushed
+        assert_eq!(cpu.pc, 0x4020);
+        assert_eq!(cpu.read_u8(0x01FB), 0x30);
+    }
+
+    #[test]
+    fn nmi_hijacks_irq() {
+        let prg = vec![
+            0xEA, // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.set_flag_bit(Flags::InterruptDisable, false);
+        cpu.bus.as_mut().unwrap().irq = true;
+
+        // The NOP and the first three cycles of the IRQ sequence
+        run_ticks(&mut cpu, 5);
+        cpu.bus.as_mut().unwrap().nmi = true;
+        run_ticks(&mut cpu, 4);
+
+        assert_eq!(cpu.pc, 0x4020);
+    }
+
+    #[test]
+    fn cli_latency() {
+        let prg = vec![
+            0x58, // CLI
+            0xEA, // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.set_flag_bit(Flags::InterruptDisable, true);
+        cpu.bus.as_mut().unwrap().irq = true;
+
+        // CLI, NOP and the IRQ sequence
+        run_ticks(&mut cpu, 11);
+
+        // The IRQ is delayed until the instruction after CLI has executed
+        assert_eq!(cpu.pc, 0x4030);
+        assert_eq!(cpu.read_u8(0x01FC), 0x22);
+    }
+
+    #[test]
+    fn irq_after_sei() {
+        let prg = vec![
+            0x78, // SEI
+            0xEA, // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.set_flag_bit(Flags::InterruptDisable, false);
+        cpu.bus.as_mut().unwrap().irq = true;
+
+        // SEI and the IRQ sequence
+        run_ticks(&mut cpu, 9);
+
+        // The IRQ was polled before SEI set the I flag
+        assert_eq!(cpu.pc, 0x4030);
+        assert_eq!(cpu.read_u8(0x01FC), 0x21);
+    }
+
+    #[test]
+    fn branch_delays_irq() {
+        let prg = vec![
+            0x90, 0x00, // BCC +0
+            0xEA,       // NOP
+        ];
+
+        let mut cpu = init_cpu(prg);
+        cpu.p = 0x00;
+
+        // Fetch the branch opcode
+        run_ticks(&mut cpu, 1);
+        // IRQ arrives during the operand fetch of a taken branch that does not cross a page
+        cpu.bus.as_mut().unwrap().irq = true;
+        run_ticks(&mut cpu, 2 + 2 + 7);
+
+        // The IRQ is taken after the NOP
+        assert_eq!(cpu.pc, 0x4030);
+        assert_eq!(cpu.read_u8(0x01FC), 0x23);
+    }
+
     #[test]
     fn b_flag() {
         // From nestest starting at $C822
@@ -2683,13 +3443,19 @@ mod tests {
         use super::*;
 
         pub struct FakeBus {
-            memmap: Vec<u8>, // ROM
+            memmap: Vec<u8>,            // ROM
+            pub nmi: bool,              // NMI line
+            pub irq: bool,              // IRQ line
+            pub writes: Vec<(u16, u8)>, // Log of bus writes
         }
 
         impl Default for FakeBus {
             fn default() -> Self {
                 FakeBus {
                     memmap: vec![],
+                    nmi: false,
+                    irq: false,
+                    writes: vec![],
                 }
             }
         }
@@ -2716,6 +3482,9 @@ mod tests {
 
                 FakeBus {
                     memmap: rom,
+                    nmi: false,
+                    irq: false,
+                    writes: vec![],
                 }
             }
         }
@@ -2727,6 +3496,25 @@ mod tests {
 
             fn write_byte(&mut self, addr: u16, data: u8) {
                 self.memmap[addr as usize] = data;
+                self.writes.push((addr, data));
+            }
+
+            fn nmi_line(&self) -> bool {
+                self.nmi
+            }
+
+            fn irq_line(&self) -> bool {
+                self.irq
+            }
+        }
+
+        impl Snapshot for FakeBus {
+            fn save_state(&self, state: &mut StateWriter) {
+                state.write_bytes(&self.memmap);
+            }
+
+            fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+                state.read_bytes(&mut self.memmap)
             }
         }
 
@@ -2748,13 +3536,20 @@ mod tests {
             let mut cpu: Cpu<FakeBus> = Cpu::default();
             cpu.set_debug(true);
             cpu.load_bus(bus);
+
+            // Run the reset sequence
+            run_ticks(&mut cpu, 7);
+
             cpu
         }
 
-        pub fn run_cpu(cpu: &mut Cpu<FakeBus>, ticks: usize) {
-            // Tick CPU once to exit Reset state
-            cpu.tick();
+        pub fn run_ticks(cpu: &mut Cpu<FakeBus>, ticks: usize) {
+            for _ in 0..ticks {
+                cpu.tick();
+            }
+        }
 
+        pub fn run_cpu(cpu: &mut Cpu<FakeBus>, ticks: usize) {
             // Tick CPU the expect number of times
             for _ in 0..ticks {
                 cpu.tick();
@@ -0,0 +1,311 @@
+//
+// cpu/dma.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 05 2021
+//
+
+use crate::state::{StateWriter, StateReader, StateError};
+
+/// Bus operation performed by the DMA unit for a single CPU cycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DmaCycle {
+    /// Halt, dummy or alignment cycle. No data is transferred
+    Idle,
+    /// Read a byte from CPU memory for OAM DMA
+    Read(u16),
+    /// Write the previously read byte to OAMDATA ($2004)
+    Write(u8),
+    /// Fetch a sample byte for the DMC
+    DmcRead(u16),
+}
+
+/// DMA units that halt the CPU
+///
+/// A DMC fetch takes priority over OAM DMA on get cycles. The OAM transfer continues through the DMC halt and dummy
+/// cycles, then loses the get cycle used by the DMC and needs an extra cycle to realign
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Dma {
+    oam: Option<OamDma>,
+    dmc: Option<DmcDma>,
+}
+
+impl Dma {
+    pub fn start_oam(&mut self, page: u8) {
+        self.oam = Some(OamDma::new(page));
+    }
+
+    /// Schedule a DMC sample fetch. Ignored if a fetch is already scheduled
+    pub fn start_dmc(&mut self, addr: u16) {
+        if self.dmc.is_none() {
+            self.dmc = Some(DmcDma::new(addr));
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.oam.is_some() || self.dmc.is_some()
+    }
+
+    /// Determine the bus operation for the current cycle. `get` is true on even (read) CPU cycles
+    pub fn cycle(&mut self, get: bool) -> DmaCycle {
+        if let Some(ref mut dmc) = self.dmc {
+            if dmc.ready() && get {
+                let addr = dmc.addr;
+                self.dmc = None;
+
+                return DmaCycle::DmcRead(addr);
+            }
+        }
+
+        match self.oam {
+            Some(ref mut oam) => {
+                let op = oam.cycle(get);
+                if oam.is_done() {
+                    self.oam = None;
+                }
+
+                op
+            },
+            None => DmaCycle::Idle,
+        }
+    }
+
+    /// Provide the byte read during a `DmaCycle::Read`
+    pub fn latch(&mut self, data: u8) {
+        if let Some(ref mut oam) = self.oam {
+            oam.latch(data);
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.oam.is_some());
+        if let Some(ref oam) = self.oam {
+            oam.save_state(state);
+        }
+
+        state.write_bool(self.dmc.is_some());
+        if let Some(ref dmc) = self.dmc {
+            state.write_u16(dmc.addr);
+            state.write_u8(dmc.stage);
+        }
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.oam = if state.read_bool()? { Some(OamDma::load_state(state)?) } else { None };
+
+        self.dmc = if state.read_bool()? {
+            let addr = state.read_u16()?;
+            let stage = state.read_u8()?;
+            if stage > DmcDma::READY {
+                return Err(StateError::InvalidData);
+            }
+
+            Some(DmcDma { addr, stage })
+        }
+        else {
+            None
+        };
+
+        Ok(())
+    }
+}
+
+/// DMC sample fetch. The CPU is halted for a halt cycle, a dummy cycle and an optional alignment cycle before the
+/// sample is read on a get cycle
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DmcDma {
+    addr: u16,
+    stage: u8, // Number of halt and dummy cycles completed
+}
+
+impl DmcDma {
+    const READY: u8 = 2;
+
+    fn new(addr: u16) -> Self {
+        DmcDma {
+            addr,
+            stage: 0,
+        }
+    }
+
+    /// Advance through the halt and dummy cycles. Returns true once the sample can be read
+    fn ready(&mut self) -> bool {
+        if self.stage < DmcDma::READY {
+            self.stage += 1;
+            false
+        }
+        else {
+            true
+        }
+    }
+}
+
+/// Transfer of a page of CPU memory into PPU OAM
+///
+/// The CPU is halted for one cycle and an optional alignment cycle, so the reads land on get cycles. This is
+/// followed by 256 alternating read and write cycles, for a total of 513 or 514 cycles
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct OamDma {
+    page: u8,
+    count: u16,       // Number of bytes written to OAM
+    halted: bool,     // The halt cycle has completed
+    data: Option<u8>, // Byte read and waiting to be written
+}
+
+impl OamDma {
+    fn new(page: u8) -> Self {
+        OamDma {
+            page,
+            count: 0,
+            halted: false,
+            data: None,
+        }
+    }
+
+    fn cycle(&mut self, get: bool) -> DmaCycle {
+        if !self.halted {
+            self.halted = true;
+            return DmaCycle::Idle;
+        }
+
+        match self.data.take() {
+            Some(data) => {
+                self.count += 1;
+                DmaCycle::Write(data)
+            },
+            None if get => DmaCycle::Read(((self.page as u16) << 8) | self.count),
+            None => DmaCycle::Idle,
+        }
+    }
+
+    /// Provide the byte read during a `DmaCycle::Read`
+    fn latch(&mut self, data: u8) {
+        self.data = Some(data);
+    }
+
+    fn is_done(&self) -> bool {
+        self.count == 256
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.page);
+        state.write_u16(self.count);
+        state.write_bool(self.halted);
+        state.write_bool(self.data.is_some());
+        state.write_u8(self.data.unwrap_or(0));
+    }
+
+    fn load_state(state: &mut StateReader) -> Result<Self, StateError> {
+        let page = state.read_u8()?;
+        let count = state.read_u16()?;
+        let halted = state.read_bool()?;
+        let has_data = state.read_bool()?;
+        let data = state.read_u8()?;
+
+        if count > 256 {
+            return Err(StateError::InvalidData);
+        }
+
+        Ok(OamDma {
+            page,
+            count,
+            halted,
+            data: if has_data { Some(data) } else { None },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_aligned() {
+        // Halt cycle lands on a put cycle, so the first read is on the next get cycle
+        assert_eq!(count_cycles(false), 513);
+    }
+
+    #[test]
+    fn transfer_unaligned() {
+        // Halt cycle lands on a get cycle, an extra alignment cycle is needed
+        assert_eq!(count_cycles(true), 514);
+    }
+
+    #[test]
+    fn dmc_fetch() {
+        // Halt and dummy cycle, then the read on a get cycle
+        let mut dma = Dma::default();
+        dma.start_dmc(0xC000);
+
+        assert_eq!(dma.cycle(false), DmaCycle::Idle);
+        assert_eq!(dma.cycle(true), DmaCycle::Idle);
+        assert_eq!(dma.cycle(false), DmaCycle::Idle);
+        assert_eq!(dma.cycle(true), DmaCycle::DmcRead(0xC000));
+        assert!(!dma.is_active());
+    }
+
+    #[test]
+    fn dmc_fetch_during_oam_dma() {
+        let mut dma = Dma::default();
+        dma.start_oam(0x02);
+
+        let mut get = true;
+        let mut cycles = 0;
+
+        while dma.is_active() {
+            if cycles == 100 {
+                dma.start_dmc(0xC000);
+            }
+
+            if let DmaCycle::Read(addr) = dma.cycle(get) {
+                dma.latch(addr as u8);
+            }
+
+            cycles += 1;
+            get = !get;
+        }
+
+        // The DMC steals a get cycle and the OAM transfer realigns
+        assert_eq!(cycles, 514 + 2);
+    }
+
+    #[test]
+    fn transfer_order() {
+        let mut dma = OamDma::new(0x02);
+        let mut get = false;
+        let mut ops = vec![];
+
+        while !dma.is_done() {
+            let op = dma.cycle(get);
+            if let DmaCycle::Read(addr) = op {
+                dma.latch(addr as u8);
+            }
+            ops.push(op);
+            get = !get;
+        }
+
+        assert_eq!(&ops[..5], &[
+            DmaCycle::Idle,
+            DmaCycle::Read(0x0200),
+            DmaCycle::Write(0x00),
+            DmaCycle::Read(0x0201),
+            DmaCycle::Write(0x01),
+        ]);
+        assert_eq!(ops.last(), Some(&DmaCycle::Write(0xFF)));
+    }
+
+    fn count_cycles(mut get: bool) -> usize {
+        let mut dma = OamDma::new(0x00);
+        let mut cycles = 0;
+
+        while !dma.is_done() {
+            if let DmaCycle::Read(_) = dma.cycle(get) {
+                dma.latch(0);
+            }
+            cycles += 1;
+            get = !get;
+        }
+
+        cycles
+    }
+}
@@ -8,3 +8,6 @@
 pub const NMI_VECTOR: u16 = 0xFFFA;
 pub const RESET_VECTOR: u16 = 0xFFFC;
 pub const IRQ_VECTOR: u16 = 0xFFFE;
+
+/// OAM DMA transfers are written through OAMDATA
+pub const PPU_OAM_DATA: u16 = 0x2004;
@@ -7,11 +7,12 @@
 
 // Modules
 mod cpu;
+mod dma;
 pub mod bus;
 pub mod memorymap;
 
 // Public re-exports
-pub use cpu::Cpu;
+pub use cpu::{Cpu, CpuRegisters};
 
 #[cfg(feature="events")]
 pub use cpu::events;
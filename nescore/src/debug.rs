@@ -0,0 +1,200 @@
+//
+// debug.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 22 2021
+//
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// A condition that halts the emulator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    /// Halt before the CPU executes the instruction at the address
+    Execute(u16),
+    /// Halt when the CPU reads from an address in the inclusive range
+    Read(u16, u16),
+    /// Halt when the CPU writes to an address in the inclusive range
+    Write(u16, u16),
+    /// Halt when the PPU writes to a VRAM address in the inclusive range
+    VramWrite(u16, u16),
+}
+
+fn in_range(addr: u16, start: u16, end: u16) -> bool {
+    start <= addr && addr <= end
+}
+
+/// Details of the breakpoint that halted the emulator
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BreakpointHit {
+    /// Identifier returned when the breakpoint was added
+    pub id: usize,
+    pub breakpoint: Breakpoint,
+    /// Address of the instruction that caused the hit
+    pub pc: u16,
+    /// Address that was accessed
+    pub addr: u16,
+    /// Value read or written. `None` for execution breakpoints
+    pub value: Option<u8>,
+}
+
+pub type DebuggerRef = Rc<RefCell<Debugger>>;
+
+/// Breakpoints shared by the CPU, CPU bus and PPU
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    pc: u16,                    // Address of the instruction being executed
+    hit: Option<BreakpointHit>, // Hit waiting to be handled
+    muted: bool,                // Ignore accesses. Used when inspecting memory
+}
+
+impl Debugger {
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.breakpoints.push((id, breakpoint));
+
+        id
+    }
+
+    /// Remove a breakpoint. Returns false if there is no breakpoint with the given id
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+
+        self.breakpoints.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Take the pending hit, if any
+    pub fn take_hit(&mut self) -> Option<BreakpointHit> {
+        self.hit.take()
+    }
+
+    /// The CPU is about to execute the instruction at `pc`
+    pub fn begin_instruction(&mut self, pc: u16) {
+        self.pc = pc;
+        self.check(pc, None, |bp| matches!(bp, Breakpoint::Execute(addr) if addr == pc));
+    }
+
+    pub fn check_read(&mut self, addr: u16, value: u8) {
+        self.check(addr, Some(value), |bp| matches!(bp, Breakpoint::Read(s, e) if in_range(addr, s, e)));
+    }
+
+    pub fn check_write(&mut self, addr: u16, value: u8) {
+        self.check(addr, Some(value), |bp| matches!(bp, Breakpoint::Write(s, e) if in_range(addr, s, e)));
+    }
+
+    pub fn check_vram_write(&mut self, addr: u16, value: u8) {
+        self.check(addr, Some(value), |bp| matches!(bp, Breakpoint::VramWrite(s, e) if in_range(addr, s, e)));
+    }
+
+    fn check<F: Fn(Breakpoint) -> bool>(&mut self, addr: u16, value: Option<u8>, matches: F) {
+        // The first hit is kept until it is handled
+        if self.muted || self.hit.is_some() {
+            return;
+        }
+
+        if let Some((id, breakpoint)) = self.breakpoints.iter().find(|(_, bp)| matches(*bp)) {
+            self.hit = Some(BreakpointHit {
+                id: *id,
+                breakpoint: *breakpoint,
+                pc: self.pc,
+                addr,
+                value,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_remove() {
+        let mut debugger = Debugger::default();
+
+        let a = debugger.add(Breakpoint::Execute(0x8000));
+        let b = debugger.add(Breakpoint::Read(0x0000, 0x07FF));
+        assert_ne!(a, b);
+
+        assert!(debugger.remove(a));
+        assert!(!debugger.remove(a));
+        assert_eq!(debugger.breakpoints(), &[(b, Breakpoint::Read(0x0000, 0x07FF))]);
+    }
+
+    #[test]
+    fn execute_hit() {
+        let mut debugger = Debugger::default();
+        let id = debugger.add(Breakpoint::Execute(0x8000));
+
+        debugger.begin_instruction(0x7FFF);
+        assert_eq!(debugger.take_hit(), None);
+
+        debugger.begin_instruction(0x8000);
+        let hit = debugger.take_hit().unwrap();
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.pc, 0x8000);
+        assert_eq!(hit.value, None);
+
+        assert_eq!(debugger.take_hit(), None);
+    }
+
+    #[test]
+    fn watchpoint_range() {
+        let mut debugger = Debugger::default();
+        debugger.add(Breakpoint::Write(0x2000, 0x2007));
+        debugger.begin_instruction(0xC000);
+
+        debugger.check_read(0x2002, 0x80);
+        debugger.check_write(0x2008, 0x00);
+        assert_eq!(debugger.take_hit(), None);
+
+        debugger.check_write(0x2007, 0x42);
+        let hit = debugger.take_hit().unwrap();
+        assert_eq!(hit.pc, 0xC000);
+        assert_eq!(hit.addr, 0x2007);
+        assert_eq!(hit.value, Some(0x42));
+    }
+
+    #[test]
+    fn first_hit_is_kept() {
+        let mut debugger = Debugger::default();
+        debugger.add(Breakpoint::Read(0x0000, 0xFFFF));
+
+        debugger.check_read(0x0010, 0x01);
+        debugger.check_read(0x0020, 0x02);
+
+        assert_eq!(debugger.take_hit().unwrap().addr, 0x0010);
+    }
+
+    #[test]
+    fn muted() {
+        let mut debugger = Debugger::default();
+        debugger.add(Breakpoint::VramWrite(0x2000, 0x23FF));
+
+        debugger.set_muted(true);
+        debugger.check_vram_write(0x2000, 0x01);
+        assert_eq!(debugger.take_hit(), None);
+
+        debugger.set_muted(false);
+        debugger.check_vram_write(0x2000, 0x01);
+        assert!(debugger.take_hit().is_some());
+    }
+}
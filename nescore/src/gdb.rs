@@ -0,0 +1,589 @@
+//
+// gdb.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 29 2021
+//
+
+//! GDB remote serial protocol server
+//!
+//! Allows a remote debugger to attach to the emulator over TCP
+//!
+//! ```no_run
+//! # use nescore::{Nes, Cartridge};
+//! # use nescore::gdb::GdbServer;
+//! # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+//! let mut nes = Nes::from(cart);
+//!
+//! let server = GdbServer::bind("127.0.0.1:1234").unwrap();
+//! // Blocks until the debugger detaches
+//! server.serve(&mut nes).unwrap();
+//! ```
+//!
+//! Registers are numbered: 0 A, 1 X, 2 Y, 3 P, 4 SP, 5 PC. The layout is also described by the target description
+//! sent to the debugger.
+//!
+//! Reads from the PPU and APU registers have side effects and return zero instead.
+
+use crate::{Nes, Breakpoint, BreakpointHit};
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, SocketAddr};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nescore.m6502.core">
+    <reg name="a" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>"#;
+
+// Stop signals
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+// Interrupt request sent by the debugger while the target is running
+const INTERRUPT: u8 = 0x03;
+
+/// GDB remote serial protocol server
+pub struct GdbServer {
+    listener: TcpListener,
+}
+
+impl GdbServer {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(GdbServer {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Wait for a debugger to connect and handle its requests until it detaches
+    ///
+    /// The emulator is halted while the debugger is attached, and only runs when the debugger continues or steps
+    pub fn serve(&self, nes: &mut Nes) -> io::Result<()> {
+        let (stream, _) = self.listener.accept()?;
+        stream.set_nodelay(true)?;
+
+        Session::new(nes, stream).run()
+    }
+}
+
+/// Data received from the debugger
+enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+/// A connected debugger
+struct Session<'a> {
+    nes: &'a mut Nes,
+    stream: TcpStream,
+    no_ack: bool,                                // Acknowledgments are disabled
+    breakpoints: HashMap<(u8, u16, u16), Vec<usize>>, // Breakpoint ids for each (type, address, length)
+}
+
+impl<'a> Session<'a> {
+    fn new(nes: &'a mut Nes, stream: TcpStream) -> Self {
+        Session {
+            nes,
+            stream,
+            no_ack: false,
+            breakpoints: HashMap::new(),
+        }
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        let result = self.handle_packets();
+
+        // Breakpoints do not outlive the session
+        for id in self.breakpoints.values().flatten() {
+            self.nes.remove_breakpoint(*id);
+        }
+
+        result
+    }
+
+    fn handle_packets(&mut self) -> io::Result<()> {
+        while let Some(incoming) = self.receive()? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => packet,
+                // Already halted
+                Incoming::Interrupt => continue,
+            };
+
+            match packet.as_bytes().first() {
+                Some(b'D') => {
+                    self.send("OK")?;
+                    break;
+                },
+                Some(b'k') => break,
+                _ => {
+                    let response = self.handle(&packet)?;
+                    self.send(&response)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a packet and return the response. An empty response indicates the packet is not supported
+    fn handle(&mut self, packet: &str) -> io::Result<String> {
+        if packet.is_empty() || !packet.is_char_boundary(1) {
+            return Ok(String::new());
+        }
+
+        let (cmd, args) = packet.split_at(1);
+
+        let response = match cmd {
+            "?" => stop_reply(SIGTRAP, None),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(args),
+            "p" => self.read_register(args),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "s" => {
+                if !self.resume_address(args) {
+                    return Ok(error());
+                }
+
+                self.nes.step_instruction();
+                stop_reply(SIGTRAP, None)
+            },
+            "c" => {
+                if !self.resume_address(args) {
+                    return Ok(error());
+                }
+
+                self.resume()?
+            },
+            "Z" => self.insert_breakpoint(args),
+            "z" => self.remove_breakpoint(args),
+            "H" => "OK".to_string(),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+
+        Ok(response)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+        }
+        else if packet == "QStartNoAckMode" {
+            self.no_ack = true;
+            "OK".to_string()
+        }
+        else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            read_chunk(TARGET_XML, args)
+        }
+        else if packet == "qAttached" {
+            "1".to_string()
+        }
+        else if packet == "qC" {
+            "QC1".to_string()
+        }
+        else if packet == "qfThreadInfo" {
+            "m1".to_string()
+        }
+        else if packet == "qsThreadInfo" {
+            "l".to_string()
+        }
+        else {
+            String::new()
+        }
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Registers
+    //------------------------------------------------------------------------------------------------------------------
+
+    fn read_registers(&self) -> String {
+        let regs = self.nes.get_cpu_registers();
+        let [lo, hi] = regs.pc.to_le_bytes();
+
+        encode_hex(&[regs.a, regs.x, regs.y, regs.p, regs.sp, lo, hi])
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        match decode_hex(args) {
+            Some(bytes) if bytes.len() == 7 => {
+                let mut regs = self.nes.get_cpu_registers();
+                regs.a = bytes[0];
+                regs.x = bytes[1];
+                regs.y = bytes[2];
+                regs.p = bytes[3];
+                regs.sp = bytes[4];
+                regs.pc = u16::from_le_bytes([bytes[5], bytes[6]]);
+
+                self.nes.set_cpu_registers(regs);
+
+                "OK".to_string()
+            },
+            _ => error(),
+        }
+    }
+
+    fn read_register(&self, args: &str) -> String {
+        let regs = self.nes.get_cpu_registers();
+
+        match usize::from_str_radix(args, 16) {
+            Ok(0) => encode_hex(&[regs.a]),
+            Ok(1) => encode_hex(&[regs.x]),
+            Ok(2) => encode_hex(&[regs.y]),
+            Ok(3) => encode_hex(&[regs.p]),
+            Ok(4) => encode_hex(&[regs.sp]),
+            Ok(5) => encode_hex(&regs.pc.to_le_bytes()),
+            _ => error(),
+        }
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let (reg, value) = match args.split_once('=') {
+            Some((reg, value)) => (usize::from_str_radix(reg, 16).ok(), decode_hex(value)),
+            None => (None, None),
+        };
+
+        let mut regs = self.nes.get_cpu_registers();
+
+        match (reg, value.as_deref()) {
+            (Some(0), Some(&[value])) => regs.a = value,
+            (Some(1), Some(&[value])) => regs.x = value,
+            (Some(2), Some(&[value])) => regs.y = value,
+            (Some(3), Some(&[value])) => regs.p = value,
+            (Some(4), Some(&[value])) => regs.sp = value,
+            (Some(5), Some(&[lo, hi])) => regs.pc = u16::from_le_bytes([lo, hi]),
+            _ => return error(),
+        }
+
+        self.nes.set_cpu_registers(regs);
+
+        "OK".to_string()
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Memory
+    //------------------------------------------------------------------------------------------------------------------
+
+    fn read_memory(&self, args: &str) -> String {
+        match parse_addr_len(args) {
+            Some((addr, len)) => {
+                let bytes: Vec<u8> = (0..len).map(|i| {
+                    let addr = addr.wrapping_add(i);
+                    if is_io(addr) { 0 } else { self.nes.read_cpu_ram(addr) }
+                }).collect();
+
+                encode_hex(&bytes)
+            },
+            None => error(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_addr_len(range)?, decode_hex(data)?)));
+
+        match parsed {
+            Some(((addr, len), data)) if data.len() == len as usize => {
+                // Writing to I/O registers has side effects
+                if (0..len).any(|i| is_io(addr.wrapping_add(i))) {
+                    return error();
+                }
+
+                for (i, value) in data.into_iter().enumerate() {
+                    self.nes.write_cpu_ram(addr.wrapping_add(i as u16), value);
+                }
+
+                "OK".to_string()
+            },
+            _ => error(),
+        }
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Execution
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// `c` and `s` packets can give the address to resume at. Returns false if the address is invalid
+    fn resume_address(&mut self, args: &str) -> bool {
+        if args.is_empty() {
+            return true;
+        }
+
+        match u16::from_str_radix(args, 16) {
+            Ok(addr) => {
+                let mut regs = self.nes.get_cpu_registers();
+                regs.pc = addr;
+                self.nes.set_cpu_registers(regs);
+
+                true
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Watchpoint kind and address reported for a breakpoint hit
+    fn watchpoint(&self, hit: &BreakpointHit) -> Option<(&'static str, u16)> {
+        // Access watchpoints are inserted as a read and a write breakpoint
+        let ty = self.breakpoints.iter().find(|(_, ids)| ids.contains(&hit.id)).map(|((ty, _, _), _)| *ty);
+
+        match (ty, hit.breakpoint) {
+            (Some(4), _) => Some(("awatch", hit.addr)),
+            (_, Breakpoint::Write(..)) => Some(("watch", hit.addr)),
+            (_, Breakpoint::Read(..)) => Some(("rwatch", hit.addr)),
+            _ => None,
+        }
+    }
+
+    /// Run until a breakpoint is hit or the debugger interrupts
+    fn resume(&mut self) -> io::Result<String> {
+        self.stream.set_nonblocking(true)?;
+
+        let signal = loop {
+            self.nes.emulate_frame();
+
+            if let Some(hit) = self.nes.breakpoint_hit() {
+                break stop_reply(SIGTRAP, self.watchpoint(&hit));
+            }
+
+            // The CPU is halted by a JAM instruction
+            if self.nes.jammed().is_some() {
+                break stop_reply(SIGILL, None);
+            }
+
+            let mut byte = [0u8; 1];
+            match self.stream.read(&mut byte) {
+                Ok(0) => break String::new(),
+                Ok(_) if byte[0] == INTERRUPT => break stop_reply(SIGINT, None),
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => {
+                    self.stream.set_nonblocking(false)?;
+                    return Err(e);
+                },
+            }
+        };
+
+        self.stream.set_nonblocking(false)?;
+
+        Ok(signal)
+    }
+
+    /// Z packets: type,addr,kind
+    fn insert_breakpoint(&mut self, args: &str) -> String {
+        let (ty, addr, len) = match parse_breakpoint(args) {
+            Some(bp) => bp,
+            None => return error(),
+        };
+
+        let end = addr.saturating_add(len.max(1) - 1);
+
+        let breakpoints = match ty {
+            // Software and hardware breakpoints
+            0 | 1 => vec![Breakpoint::Execute(addr)],
+            // Write, read and access watchpoints
+            2 => vec![Breakpoint::Write(addr, end)],
+            3 => vec![Breakpoint::Read(addr, end)],
+            4 => vec![Breakpoint::Read(addr, end), Breakpoint::Write(addr, end)],
+            _ => return String::new(),
+        };
+
+        let ids: Vec<usize> = breakpoints.into_iter().map(|bp| self.nes.add_breakpoint(bp)).collect();
+        self.breakpoints.entry((ty, addr, len)).or_default().extend(ids);
+
+        "OK".to_string()
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> String {
+        let key = match parse_breakpoint(args) {
+            Some(key) => key,
+            None => return error(),
+        };
+
+        for id in self.breakpoints.remove(&key).unwrap_or_default() {
+            self.nes.remove_breakpoint(id);
+        }
+
+        "OK".to_string()
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Packets
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Receive the next packet. Returns `None` when the debugger disconnects
+    fn receive(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte()? {
+                Some(b'$') => {},
+                Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+                // Acknowledgments
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut checksum = [0u8; 2];
+            self.stream.read_exact(&mut checksum)?;
+
+            let valid = std::str::from_utf8(&checksum).ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                .is_some_and(|checksum| checksum == helpers::checksum(&data));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+
+            if valid {
+                return Ok(Some(Incoming::Packet(String::from_utf8_lossy(&data).into_owned())));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        self.stream.write_all(helpers::frame(data).as_bytes())
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8; 1];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+/// Stop reply, including the kind and address for watchpoints
+fn stop_reply(signal: u8, watch: Option<(&str, u16)>) -> String {
+    match watch {
+        Some((kind, addr)) => format!("T{:02x}{}:{:04x};", signal, kind, addr),
+        None => format!("S{:02x}", signal),
+    }
+}
+
+/// Respond to qXfer reads of `offset,length`
+fn read_chunk(data: &str, args: &str) -> String {
+    let (offset, len) = match args.split_once(',') {
+        Some((offset, len)) => (usize::from_str_radix(offset, 16), usize::from_str_radix(len, 16)),
+        None => return error(),
+    };
+
+    match (offset, len) {
+        (Ok(offset), Ok(len)) => {
+            let start = offset.min(data.len());
+            let end = (start + len).min(data.len());
+            let prefix = if end == data.len() { "l" } else { "m" };
+
+            format!("{}{}", prefix, &data[start..end])
+        },
+        _ => error(),
+    }
+}
+
+fn parse_addr_len(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, u16::from_str_radix(len, 16).ok()?))
+}
+
+fn parse_breakpoint(args: &str) -> Option<(u8, u16, u16)> {
+    let mut parts = args.split(',');
+
+    let ty = parts.next()?.parse().ok()?;
+    let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let len = u16::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((ty, addr, len))
+}
+
+/// PPU, APU and IO registers
+fn is_io(addr: u16) -> bool {
+    (0x2000..=0x401F).contains(&addr)
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+mod helpers {
+    //! Packets are sent as `$data#checksum`, where the checksum is the modulo 256 sum of the data
+
+    pub fn checksum(data: &[u8]) -> u8 {
+        data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+    }
+
+    pub fn frame(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum(data.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_packet() {
+        assert_eq!(helpers::frame("OK"), "$OK#9a");
+        assert_eq!(helpers::frame(""), "$#00");
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(encode_hex(&[0x00, 0xAB, 0x12]), "00ab12");
+        assert_eq!(decode_hex("00ab12"), Some(vec![0x00, 0xAB, 0x12]));
+        assert_eq!(decode_hex("0"), None);
+        assert_eq!(decode_hex("zz"), None);
+    }
+
+    #[test]
+    fn parse_breakpoint_packet() {
+        assert_eq!(parse_breakpoint("0,c000,1"), Some((0, 0xC000, 1)));
+        assert_eq!(parse_breakpoint("2,0010,2"), Some((2, 0x0010, 2)));
+        assert_eq!(parse_breakpoint("0,c000"), None);
+    }
+
+    #[test]
+    fn xfer_chunks() {
+        assert_eq!(read_chunk("abcdef", "0,4"), "mabcd");
+        assert_eq!(read_chunk("abcdef", "4,4"), "lef");
+        assert_eq!(read_chunk("abcdef", "6,4"), "l");
+    }
+
+    #[test]
+    fn watchpoint_stop_reply() {
+        assert_eq!(stop_reply(SIGTRAP, Some(("watch", 0x0010))), "T05watch:0010;");
+        assert_eq!(stop_reply(SIGTRAP, Some(("awatch", 0x0200))), "T05awatch:0200;");
+        assert_eq!(stop_reply(SIGINT, None), "S02");
+    }
+}
@@ -0,0 +1,135 @@
+//
+// hooks.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jul 24 2021
+//
+
+use crate::ppu::PpuRegisters;
+use crate::region::Region;
+
+/// PPU position a hook is run at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PpuHook {
+    /// A dot on a specific scanline
+    Position(usize, usize),
+    /// A dot on every scanline
+    Dot(usize),
+    /// Start of vblank (scanline 241, dot 1 on NTSC)
+    VBlank,
+    /// Start of the pre-render scanline
+    PreRender,
+}
+
+impl PpuHook {
+    fn matches(&self, scanline: usize, dot: usize, region: Region) -> bool {
+        match *self {
+            PpuHook::Position(s, d) => scanline == s && dot == d,
+            PpuHook::Dot(d) => dot == d,
+            PpuHook::VBlank => scanline == region.vblank_scanline() && dot == 1,
+            PpuHook::PreRender => scanline == region.scanlines() - 1 && dot == 0,
+        }
+    }
+}
+
+/// PPU state passed to a hook, after the dot has been run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PpuHookEvent {
+    /// Identifier returned when the hook was added
+    pub id: usize,
+    pub scanline: usize,
+    pub dot: usize,
+    /// Scroll (v, t, x), PPUCTRL and PPUMASK values
+    pub registers: PpuRegisters,
+}
+
+type HookFn = Box<dyn FnMut(&PpuHookEvent)>;
+
+/// Callbacks run at PPU positions
+#[derive(Default)]
+pub struct PpuHooks {
+    hooks: Vec<(usize, PpuHook, HookFn)>,
+    next_id: usize,
+}
+
+impl PpuHooks {
+    pub fn add(&mut self, hook: PpuHook, callback: HookFn) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.hooks.push((id, hook, callback));
+
+        id
+    }
+
+    /// Remove a hook. Returns false if there is no hook with the given id
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.hooks.len();
+        self.hooks.retain(|(i, _, _)| *i != id);
+
+        self.hooks.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Run the hooks for the dot. Registers are only read if a hook matches
+    pub fn run<F: Fn() -> PpuRegisters>(&mut self, scanline: usize, dot: usize, region: Region, registers: F) {
+        for (id, hook, callback) in self.hooks.iter_mut() {
+            if hook.matches(scanline, dot, region) {
+                let event = PpuHookEvent {
+                    id: *id,
+                    scanline,
+                    dot,
+                    registers: registers(),
+                };
+
+                callback(&event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[test]
+    fn hook_positions() {
+        assert!(PpuHook::Position(10, 256).matches(10, 256, Region::Ntsc));
+        assert!(!PpuHook::Position(10, 256).matches(11, 256, Region::Ntsc));
+        assert!(PpuHook::Dot(5).matches(200, 5, Region::Ntsc));
+        assert!(PpuHook::VBlank.matches(241, 1, Region::Ntsc));
+        assert!(PpuHook::VBlank.matches(291, 1, Region::Dendy));
+        assert!(PpuHook::PreRender.matches(261, 0, Region::Ntsc));
+        assert!(PpuHook::PreRender.matches(311, 0, Region::Pal));
+        assert!(!PpuHook::PreRender.matches(261, 0, Region::Pal));
+    }
+
+    #[test]
+    fn add_and_remove() {
+        let count = Rc::new(RefCell::new(0));
+
+        let mut hooks = PpuHooks::default();
+        let c = count.clone();
+        let id = hooks.add(PpuHook::Dot(0), Box::new(move |_| *c.borrow_mut() += 1));
+
+        hooks.run(0, 0, Region::Ntsc, PpuRegisters::default);
+        hooks.run(0, 1, Region::Ntsc, PpuRegisters::default);
+        assert_eq!(*count.borrow(), 1);
+
+        assert!(hooks.remove(id));
+        assert!(!hooks.remove(id));
+
+        hooks.run(0, 0, Region::Ntsc, PpuRegisters::default);
+        assert_eq!(*count.borrow(), 1);
+    }
+}
@@ -6,6 +6,7 @@
 //
 
 use crate::common::IoAccess;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 use std::cell::RefCell;
 
@@ -86,6 +87,28 @@ impl IoAccess for Joy {
     }
 }
 
+impl Snapshot for Joy {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.strobe);
+        state.write_bytes(&self.ctrls_states);
+
+        for shift in self.ctrls_shifts.iter() {
+            state.write_u8(*shift.borrow());
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = state.read_bool()?;
+        state.read_bytes(&mut self.ctrls_states)?;
+
+        for shift in self.ctrls_shifts.iter() {
+            *shift.borrow_mut() = state.read_u8()?;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -14,21 +14,34 @@ mod ppu;
 mod apu;
 mod mapper;
 mod joy;
+mod state;
+mod rewind;
+mod debug;
+mod region;
+mod hooks;
 
 #[cfg(feature = "events")]
 pub mod log;
 pub mod cart;
 pub mod asm;
 pub mod utils;
+pub mod gdb;
+pub mod trace;
 
 // Public re-exports
-pub use nes::Nes;
+pub use nes::{Nes, Budget, StopReason};
 pub use cart::{Cartridge, CartridgeLoader};
 pub use joy::{Controller, Button};
+pub use state::StateError;
+pub use debug::{Breakpoint, BreakpointHit};
+pub use cpu::CpuRegisters;
+pub use ppu::{PpuRegisters, Palette, BuiltinPalette, NtscPaletteParams, PaletteError};
+pub use region::Region;
+pub use hooks::{PpuHook, PpuHookEvent};
 
 /// NES system specifications and associated types
 pub mod specs {
-    pub use super::ppu::{DISPLAY_WIDTH, DISPLAY_HEIGHT};
+    pub use super::ppu::{DISPLAY_WIDTH, DISPLAY_HEIGHT, IndexedPixel};
     pub use super::nes::PixelFormat;
 
     pub use super::apu::{Sample, APU_OUTPUT_RATE};
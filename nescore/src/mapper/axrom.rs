@@ -9,23 +9,26 @@ use super::{MapperControl, Mirroring};
 use crate::cart::Cartridge;
 
 use super::mem::Memory;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 pub struct Axrom {
     prg_rom: Memory,
     chr_ram: [u8; kb!(8)],
     bank_select: usize,
     single_screen_select: bool,
+    bus_conflicts: bool, // Register writes are ANDed with the ROM byte at the address
 }
 
 impl From<Cartridge> for Axrom {
     fn from(cart: Cartridge) -> Self {
-        let (_, prg_rom, _, _) = cart.into_parts();
+        let (info, prg_rom, _, _) = cart.into_parts();
 
         Axrom {
             prg_rom: Memory::new(prg_rom, kb!(32)),
             chr_ram: [0; kb!(8)],
             bank_select: 0,
             single_screen_select: false,
+            bus_conflicts: info.submapper == 2,
         }
     }
 }
@@ -42,6 +45,8 @@ impl MapperControl for Axrom {
 
     fn write(&mut self, addr: u16, data: u8) {
         if let 0x8000..=0xFFFF = addr {
+            let data = if self.bus_conflicts { data & self.read(addr) } else { data };
+
             let bank = data & 0x07;
             self.bank_select = bank as usize;
 
@@ -65,4 +70,55 @@ impl MapperControl for Axrom {
             None
         }
     }
+
+    fn power_on(&mut self) {
+        self.bank_select = 0;
+        self.single_screen_select = false;
+    }
+}
+
+impl Snapshot for Axrom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.chr_ram);
+        state.write_usize(self.bank_select);
+        state.write_bool(self.single_screen_select);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.chr_ram)?;
+        let bank = state.read_usize()?;
+        if bank >= self.prg_rom.num_banks() {
+            return Err(StateError::InvalidData);
+        }
+        self.bank_select = bank;
+        self.single_screen_select = state.read_bool()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bus_conflicts() {
+        let mut prg = vec![0; kb!(64)];
+        // ROM byte at $8000 in bank 0
+        prg[0] = 0x01;
+        prg[kb!(32)] = 0xDE;
+
+        let mut axrom = Axrom {
+            prg_rom: Memory::new(prg, kb!(32)),
+            chr_ram: [0; kb!(8)],
+            bank_select: 0,
+            single_screen_select: false,
+            bus_conflicts: true,
+        };
+
+        // Bank 3 and single screen AND 1 selects bank 1
+        axrom.write(0x8000, 0x13);
+        assert_eq!(axrom.read(0x8000), 0xDE);
+        assert!(axrom.mirroring().is_none());
+    }
 }
@@ -7,6 +7,7 @@
 
 use super::{MapperControl, Mirroring};
 use crate::cart::Cartridge;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 const NAMETABLE_RAM_SIZE: usize = kb!(4);
 
@@ -21,6 +22,9 @@ pub struct MapperBase<Mapper: MapperControl> {
     palette_ram: [u8; 32],
     mirror_v: bool,
     four_screen: bool,
+
+    // Last state of PPU A12
+    a12: bool,
 }
 
 impl<Mapper: MapperControl + From<Cartridge>> From<Cartridge> for MapperBase<Mapper> {
@@ -36,6 +40,8 @@ impl<Mapper: MapperControl + From<Cartridge>> From<Cartridge> for MapperBase<Map
             palette_ram: [0; 32],
             mirror_v,
             four_screen,
+
+            a12: false,
         }
     }
 }
@@ -81,6 +87,66 @@ impl<Mapper: MapperControl> MapperControl for MapperBase<Mapper> {
     fn get_battery_ram(&self) -> Vec<u8> {
         self.mapper.get_battery_ram()
     }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Reset
+    //------------------------------------------------------------------------------------------------------------------
+    fn reset(&mut self) {
+        self.mapper.reset()
+    }
+
+    fn power_on(&mut self) {
+        self.nametable_buffer = [0; NAMETABLE_RAM_SIZE];
+        self.palette_ram = [0; 32];
+        self.a12 = false;
+
+        self.mapper.power_on()
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Timing and IRQ
+    //------------------------------------------------------------------------------------------------------------------
+    fn ppu_address(&mut self, addr: u16) {
+        let addr = addr & 0x3FFF;
+
+        let a12 = mask_is_set!(addr, 0x1000);
+        if a12 != self.a12 {
+            self.a12 = a12;
+            self.mapper.ppu_a12_edge(a12);
+        }
+
+        if let 0x2000..=0x3EFF = addr {
+            self.mapper.ppu_nametable_fetch(0x2000 | (addr & 0x0FFF));
+        }
+
+        self.mapper.ppu_address(addr)
+    }
+
+    fn cpu_tick(&mut self) {
+        self.mapper.cpu_tick()
+    }
+
+    fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+}
+
+impl<Mapper: MapperControl> Snapshot for MapperBase<Mapper> {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.nametable_buffer);
+        state.write_bytes(&self.palette_ram);
+        state.write_bool(self.a12);
+
+        self.mapper.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.nametable_buffer)?;
+        state.read_bytes(&mut self.palette_ram)?;
+        self.a12 = state.read_bool()?;
+
+        self.mapper.load_state(state)
+    }
 }
 
 impl<Mapper: MapperControl> MapperBase<Mapper> {
@@ -223,8 +289,62 @@ mod tests {
         assert_eq!(mapper.read_chr(0x3F0C), 0x01);
     }
 
+    #[test]
+    fn a12_edges() {
+        let mut mapper = init_mapper();
+
+        mapper.ppu_address(0x0000);
+        mapper.ppu_address(0x1000);
+        mapper.ppu_address(0x1008);
+        mapper.ppu_address(0x2000);
+
+        assert_eq!(mapper.mapper.a12_edges, vec![true, false]);
+    }
+
+    #[test]
+    fn nametable_fetches() {
+        let mut mapper = init_mapper();
+
+        mapper.ppu_address(0x0000);
+        mapper.ppu_address(0x2001);
+        mapper.ppu_address(0x23C0);
+        mapper.ppu_address(0x3400);
+        mapper.ppu_address(0x3F00);
+
+        assert_eq!(mapper.mapper.nametable_fetches, vec![0x2001, 0x23C0, 0x2400]);
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut mapper = init_mapper();
+
+        mapper.write_chr(0x2000, 0xDE);
+        mapper.write_chr(0x2C00, 0xAD);
+        mapper.write_chr(0x3F01, 0x21);
+        mapper.write(0x6000, 0xBE);
+        mapper.ppu_address(0x1000);
+
+        let mut writer = StateWriter::default();
+        mapper.save_state(&mut writer);
+        let data = writer.into_inner();
+
+        let mut restored = init_mapper();
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+
+        assert_eq!(restored.read_chr(0x2000), 0xDE);
+        assert_eq!(restored.read_chr(0x2C00), 0xAD);
+        assert_eq!(restored.read_chr(0x3F01), 0x21);
+        assert_eq!(restored.read(0x6000), 0xBE);
+
+        // A12 is already high, so no edge is reported
+        restored.ppu_address(0x1000);
+        assert!(restored.mapper.a12_edges.is_empty());
+    }
+
     struct FakeMapper {
         ram: [u8; kb!(32)],
+        a12_edges: Vec<bool>,
+        nametable_fetches: Vec<u16>,
     }
 
     #[allow(unused)]
@@ -237,12 +357,30 @@ mod tests {
             self.ram[addr as usize] = data;
         }
         fn write_chr(&mut self, addr: u16, value: u8) {}
+        fn ppu_a12_edge(&mut self, rising: bool) {
+            self.a12_edges.push(rising);
+        }
+        fn ppu_nametable_fetch(&mut self, addr: u16) {
+            self.nametable_fetches.push(addr);
+        }
+    }
+
+    impl Snapshot for FakeMapper {
+        fn save_state(&self, state: &mut StateWriter) {
+            state.write_bytes(&self.ram);
+        }
+
+        fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+            state.read_bytes(&mut self.ram)
+        }
     }
 
     impl From<Cartridge> for FakeMapper {
         fn from(_: Cartridge) -> Self {
             FakeMapper{
                 ram: [0; kb!(32)],
+                a12_edges: vec![],
+                nametable_fetches: vec![],
             }
         }
     }
@@ -9,6 +9,7 @@ use super::MapperControl;
 use crate::cart::{Cartridge, PRG_ROM_BANK_SIZE, CHR_ROM_BANK_SIZE};
 
 use super::mem::Memory;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 ///
 /// CNROM
@@ -24,6 +25,7 @@ pub struct Cnrom {
     chr_rom: Memory,
     chr_rom_bank: usize,
     prg_rom_banks: usize,
+    bus_conflicts: bool,  // Register writes are ANDed with the ROM byte at the address
 }
 
 impl From<Cartridge> for Cnrom {
@@ -35,6 +37,7 @@ impl From<Cartridge> for Cnrom {
             chr_rom: Memory::new(chr_rom, CHR_ROM_BANK_SIZE),
             chr_rom_bank: 0,
             prg_rom_banks: info.prg_rom_banks,
+            bus_conflicts: info.submapper == 2,
         }
     }
 }
@@ -59,6 +62,7 @@ impl MapperControl for Cnrom {
 
     fn write(&mut self, addr: u16, data: u8) {
         if let 0x8000..=0xFFFF = addr {
+            let data = if self.bus_conflicts { data & self.read(addr) } else { data };
             self.chr_rom_bank = (data & 0x03) as usize;
         }
     }
@@ -75,6 +79,26 @@ impl MapperControl for Cnrom {
     fn write_chr(&mut self, _addr: u16, _value: u8) {
         // No CHR RAM
     }
+
+    fn power_on(&mut self) {
+        self.chr_rom_bank = 0;
+    }
+}
+
+impl Snapshot for Cnrom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.chr_rom_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let bank = state.read_usize()?;
+        if bank >= self.chr_rom.num_banks() {
+            return Err(StateError::InvalidData);
+        }
+        self.chr_rom_bank = bank;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -134,12 +158,30 @@ mod tests {
         assert_eq!(cnrom.read_chr(0x1FFF), 0xEF);
     }
 
+    #[test]
+    fn bus_conflicts() {
+        let mut prg = vec![0; PRG_ROM_BANK_SIZE * 2];
+        let mut chr = vec![0; CHR_ROM_BANK_SIZE * 4];
+
+        // ROM byte at $8000
+        prg[0] = 0x01;
+        chr[CHR_ROM_BANK_SIZE] = 0xDE;
+
+        let mut cnrom = init_cnrom(prg, chr, 2);
+        cnrom.bus_conflicts = true;
+
+        // Bank 3 AND 1 selects bank 1
+        cnrom.write(0x8000, 0x03);
+        assert_eq!(cnrom.read_chr(0x0000), 0xDE);
+    }
+
     fn init_cnrom(prg_rom: Vec<u8>, chr_rom: Vec<u8>, num_prg_banks: usize) -> Cnrom {
         Cnrom {
             prg_rom: Memory::new(prg_rom, PRG_ROM_BANK_SIZE),
             chr_rom: Memory::new(chr_rom, CHR_ROM_BANK_SIZE),
             chr_rom_bank: 0,
             prg_rom_banks: num_prg_banks,
+            bus_conflicts: false,
         }
     }
 }
@@ -12,12 +12,14 @@ use super::mmc1::Mmc1;
 use super::unrom::Unrom;
 use super::cnrom::Cnrom;
 use super::axrom::Axrom;
+use super::mmc3::Mmc3;
 
 // use std::boxed::Box;
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use crate::cart::Cartridge;
+use crate::cart::{Cartridge, CartridgeError};
+use crate::state::Snapshot;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Mirroring {
@@ -27,7 +29,8 @@ pub enum Mirroring {
     Horizontal,
 }
 
-pub trait MapperControl {
+/// Mapper state (registers, RAM) is saved with the rest of the system through `Snapshot`
+pub trait MapperControl: Snapshot {
     fn read(&self, addr: u16) -> u8;
     fn write(&mut self, addr: u16, data: u8);
 
@@ -39,20 +42,69 @@ pub trait MapperControl {
     fn get_battery_ram(&self) -> Vec<u8> {
         (0x6000..0x8000).map(|addr| self.read(addr)).collect()
     }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Reset
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// The console was reset. Most mappers do not see the RESET line and keep their registers
+    fn reset(&mut self) {}
+
+    /// Return registers to their power up state. ROM and battery backed RAM are kept
+    fn power_on(&mut self) {}
+
+    //------------------------------------------------------------------------------------------------------------------
+    // PPU bus notifications
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Observe an address placed on the PPU bus
+    fn ppu_address(&mut self, _addr: u16) {}
+
+    /// PPU A12 has changed state. `rising` is true when A12 goes from low to high
+    fn ppu_a12_edge(&mut self, _rising: bool) {}
+
+    /// The PPU has fetched from nametable memory ($2000-$2FFF)
+    fn ppu_nametable_fetch(&mut self, _addr: u16) {}
+
+    //------------------------------------------------------------------------------------------------------------------
+    // CPU timing and IRQ
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Clock the mapper with the CPU (M2)
+    fn cpu_tick(&mut self) {}
+
+    /// Level of the mapper's IRQ line. The CPU samples this every cycle.
+    /// A mapper asserts the line by returning true and holds it until the IRQ is acknowledged (usually by a register write)
+    fn irq(&self) -> bool { false }
 }
 
 pub type Mapper = Rc<RefCell<dyn MapperControl>>;
 
 /// Create mapper instance from cartridge
-pub fn from_cartridge(cart: Cartridge) -> Mapper {
-    match cart.info.mapper {
-        0 => create_mapper::<Nrom>(cart),
-        1 => create_mapper::<Mmc1>(cart),
-        2 => create_mapper::<Unrom>(cart),
-        3 => create_mapper::<Cnrom>(cart),
-        7 => create_mapper::<Axrom>(cart),
-        _ => panic!("Invalid or unimplemented mapper: #{mapper}", mapper=cart.info.mapper),
+pub fn from_cartridge(cart: Cartridge) -> Result<Mapper, CartridgeError> {
+    let mapper = cart.info.mapper;
+    let submapper = cart.info.submapper;
+
+    // Submappers supported for each mapper and its constructor. Submapper 0 is the default behaviour
+    let (submappers, create): (&[usize], fn(Cartridge) -> Mapper) = match mapper {
+        0 => (&[0], create_mapper::<Nrom>),
+        1 => (&[0], create_mapper::<Mmc1>),
+        2 => (&[0, 1, 2], create_mapper::<Unrom>), // Submapper 2 has bus conflicts
+        3 => (&[0, 1, 2], create_mapper::<Cnrom>), // Submapper 2 has bus conflicts
+        4 => (&[0], create_mapper::<Mmc3>),
+        7 => (&[0, 1, 2], create_mapper::<Axrom>), // Submapper 2 has bus conflicts
+        _ => return Err(CartridgeError::UnsupportedMapper(mapper)),
+    };
+
+    if !submappers.contains(&submapper) {
+        return Err(CartridgeError::UnsupportedSubmapper(mapper, submapper));
     }
+
+    cart.verify()?;
+
+    let mapper = create(cart);
+
+    Ok(mapper)
 }
 
 /// Instantiate a mapper from a Cartridge
@@ -5,6 +5,8 @@
 // @date Dec 27 2019
 //
 
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+
 /// Representation of a memory block in the mapper
 pub struct Memory {
     mem: Vec<u8>,
@@ -43,6 +45,11 @@ impl Memory {
         self.read(self.num_banks - 1, index)
     }
 
+    /// Number of banks for the current bank size
+    pub fn num_banks(&self) -> usize {
+        self.num_banks
+    }
+
     pub fn set_bank_size(&mut self, new_size: usize) {
         self.bank_size = new_size;
         self.num_banks = self.mem.len() / self.bank_size;
@@ -53,6 +60,24 @@ impl Memory {
     }
 }
 
+/// Save the bank layout and contents of the memory block
+impl Snapshot for Memory {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_usize(self.bank_size);
+        state.write_bytes(&self.mem);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let bank_size = state.read_usize()?;
+        if bank_size == 0 || bank_size > self.mem.len() {
+            return Err(StateError::InvalidData);
+        }
+
+        self.set_bank_size(bank_size);
+        state.read_bytes(&mut self.mem)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -9,6 +9,7 @@ use super::{MapperControl, Mirroring};
 use crate::cart::{Cartridge, PRG_ROM_BANK_SIZE, CHR_ROM_BANK_SIZE};
 
 use super::mem::Memory;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 const PRG_RAM_SIZE: usize = 0x2000;
 const SHIFT_REGISTER_INIT_VALUE: u8 = 0x10;
@@ -147,6 +148,45 @@ impl Mmc1 {
         self.chr_bank1_selection = (value & 0x1F) as usize;
     }
 
+    /// Reconstruct the value of the control register
+    fn control(&self) -> u8 {
+        let mirroring = match self.mirroring {
+            Mirroring::OneScreenLower => 0,
+            Mirroring::OneScreenUpper => 1,
+            Mirroring::Vertical       => 2,
+            Mirroring::Horizontal     => 3,
+        };
+
+        let prg_rom_bank_mode = match self.prg_rom_bank_mode {
+            PrgRomBankMode::Switch32K  => 0,
+            PrgRomBankMode::SwitchC000 => 2,
+            PrgRomBankMode::Switch8000 => 3,
+        };
+
+        let chr_bank_mode = match self.chr_bank_mode {
+            ChrBankMode::Switch8K => 0,
+            ChrBankMode::Switch4K => 1,
+        };
+
+        mirroring | (prg_rom_bank_mode << 2) | (chr_bank_mode << 4)
+    }
+
+    /// Check the selected banks exist for the current bank modes
+    fn banks_in_range(&self) -> bool {
+        let prg_bank = match self.prg_rom_bank_mode {
+            PrgRomBankMode::Switch32K => self.prg_bank_selection >> 1,
+            PrgRomBankMode::Switch8000 | PrgRomBankMode::SwitchC000 => self.prg_bank_selection,
+        };
+
+        let chr_in_range = match self.chr_bank_mode {
+            ChrBankMode::Switch8K => (self.chr_bank0_selection >> 1) < self.chr_data.num_banks(),
+            ChrBankMode::Switch4K => self.chr_bank0_selection < self.chr_data.num_banks()
+                                     && self.chr_bank1_selection < self.chr_data.num_banks(),
+        };
+
+        prg_bank < self.prg_rom.num_banks() && chr_in_range
+    }
+
     fn write_prg_bank(&mut self, value: u8) {
         self.prg_bank_selection = (value & 0x0F) as usize;
     }
@@ -281,6 +321,51 @@ impl MapperControl for Mmc1 {
     fn mirroring(&self) -> Option<Mirroring> {
         Some(self.mirroring)
     }
+
+    fn power_on(&mut self) {
+        self.shift_register = SHIFT_REGISTER_INIT_VALUE;
+
+        self.mirroring = Mirroring::OneScreenLower;
+        self.prg_rom_bank_mode = PrgRomBankMode::Switch8000;
+        self.chr_bank_mode = ChrBankMode::Switch4K;
+
+        self.prg_bank_selection = 0;
+        self.chr_bank0_selection = 0;
+        self.chr_bank1_selection = 0;
+    }
+}
+
+impl Snapshot for Mmc1 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        self.chr_data.save_state(state);
+
+        state.write_u8(self.shift_register);
+        state.write_u8(self.control());
+
+        state.write_usize(self.prg_bank_selection);
+        state.write_usize(self.chr_bank0_selection);
+        state.write_usize(self.chr_bank1_selection);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        self.chr_data.load_state(state)?;
+
+        self.shift_register = state.read_u8()?;
+        // Restores the bank modes and bank sizes
+        self.write_control(state.read_u8()?);
+
+        self.prg_bank_selection = state.read_usize()?;
+        self.chr_bank0_selection = state.read_usize()?;
+        self.chr_bank1_selection = state.read_usize()?;
+
+        if !self.banks_in_range() {
+            return Err(StateError::InvalidData);
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,550 @@
+//
+// mapper/mmc3.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 02 2021
+//
+
+use super::{MapperControl, Mirroring};
+use crate::cart::Cartridge;
+
+use super::mem::Memory;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+
+const PRG_RAM_SIZE: usize = kb!(8);
+const PRG_BANK_SIZE: usize = kb!(8);
+const CHR_BANK_SIZE: usize = kb!(1);
+
+/// Number of M2 cycles PPU A12 must be low before a rising edge clocks the IRQ counter
+const A12_FILTER_CYCLES: usize = 4;
+
+/// PRG ROM bank mode
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PrgRomBankMode {
+    Swap8000, // $8000-$9FFF swappable, $C000-$DFFF fixed to the second last bank
+    SwapC000, // $C000-$DFFF swappable, $8000-$9FFF fixed to the second last bank
+}
+
+///
+/// MMC3 / TxROM
+///
+/// * CPU $6000-$7FFF: 8 KB PRG RAM bank
+/// * CPU $8000-$9FFF: 8 KB switchable PRG ROM bank (or fixed to the second last bank)
+/// * CPU $A000-$BFFF: 8 KB switchable PRG ROM bank
+/// * CPU $C000-$DFFF: 8 KB PRG ROM bank, fixed to the second last bank (or switchable)
+/// * CPU $E000-$FFFF: 8 KB PRG ROM bank, fixed to the last bank
+/// * PPU $0000-$1FFF: Two 2 KB switchable CHR banks and four 1 KB switchable CHR banks
+///
+/// https://wiki.nesdev.com/w/index.php/MMC3
+///
+pub struct Mmc3 {
+    prg_rom: Memory,
+    prg_ram: [u8; PRG_RAM_SIZE],
+    chr_data: Memory,
+
+    bank_registers: [usize; 8], // R0 - R7
+    bank_select: usize,         // Register updated by the next bank data write
+    prg_rom_bank_mode: PrgRomBankMode,
+    chr_inversion: bool,        // Swap the 2K and 1K CHR bank regions
+
+    mirroring: Mirroring,
+    four_screen: bool,
+
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+
+    a12: bool,                  // State of PPU A12
+    a12_low_cycles: usize,      // Number of M2 cycles A12 has been low for
+}
+
+impl From<Cartridge> for Mmc3 {
+    fn from(cart: Cartridge) -> Self {
+        let four_screen = cart.info.four_screen_mode;
+        let mirroring = if cart.info.mirror_v { Mirroring::Vertical } else { Mirroring::Horizontal };
+
+        let (_, prg_rom, chr_rom, sav_ram) = cart.into_parts();
+
+        // If no CHR ROM is provided, use 8Kb of CHR RAM
+        let chr_data = if chr_rom.is_empty() {
+            vec![0x00u8; kb!(8)]
+        }
+        else {
+            chr_rom
+        };
+
+        // Copy sav ram to prg ram
+        let mut prg_ram = [0u8; PRG_RAM_SIZE];
+        for (i, b) in prg_ram.iter_mut().enumerate() {
+            *b = if i < sav_ram.len() {
+                sav_ram[i]
+            }
+            else {
+                0
+            };
+        }
+
+        Mmc3 {
+            prg_rom: Memory::new(prg_rom, PRG_BANK_SIZE),
+            prg_ram,
+            chr_data: Memory::new(chr_data, CHR_BANK_SIZE),
+
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            bank_select: 0,
+            prg_rom_bank_mode: PrgRomBankMode::Swap8000,
+            chr_inversion: false,
+
+            mirroring,
+            four_screen,
+
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+
+            a12: false,
+            a12_low_cycles: 0,
+        }
+    }
+}
+
+impl Mmc3 {
+    fn write_registers(&mut self, addr: u16, value: u8) {
+        let even = addr & 0x01 == 0;
+
+        match (addr, even) {
+            // Bank select
+            (0x8000..=0x9FFF, true) => {
+                self.bank_select = (value & 0x07) as usize;
+                self.prg_rom_bank_mode = if bit_is_set!(value, 6) { PrgRomBankMode::SwapC000 } else { PrgRomBankMode::Swap8000 };
+                self.chr_inversion = bit_is_set!(value, 7);
+            },
+            // Bank data
+            (0x8000..=0x9FFF, false) => {
+                let value = match self.bank_select {
+                    // 2K CHR banks ignore the low bit
+                    0 | 1 => value & 0xFE,
+                    // PRG banks ignore the top two bits
+                    6 | 7 => value & 0x3F,
+                    _ => value,
+                };
+
+                self.bank_registers[self.bank_select] = value as usize;
+            },
+            // Mirroring
+            (0xA000..=0xBFFF, true) => {
+                self.mirroring = if bit_is_set!(value, 0) { Mirroring::Horizontal } else { Mirroring::Vertical };
+            },
+            // PRG RAM protect
+            (0xA000..=0xBFFF, false) => {
+                self.prg_ram_enabled = bit_is_set!(value, 7);
+                self.prg_ram_write_protect = bit_is_set!(value, 6);
+            },
+            // IRQ latch
+            (0xC000..=0xDFFF, true) => {
+                self.irq_latch = value;
+            },
+            // IRQ reload
+            (0xC000..=0xDFFF, false) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            },
+            // IRQ disable and acknowledge
+            (0xE000..=0xFFFF, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            },
+            // IRQ enable
+            (0xE000..=0xFFFF, false) => {
+                self.irq_enabled = true;
+            },
+            _ => {},
+        }
+    }
+
+    /// Clock the scanline counter. Called on a filtered rising edge of PPU A12
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        }
+        else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+
+    /// Map a CPU address in $8000-$FFFF to a PRG ROM bank
+    fn prg_bank(&self, addr: u16) -> usize {
+        let second_last = self.prg_rom.num_banks() - 2;
+
+        let bank = match (addr, self.prg_rom_bank_mode) {
+            (0x8000..=0x9FFF, PrgRomBankMode::Swap8000) => self.bank_registers[6],
+            (0x8000..=0x9FFF, PrgRomBankMode::SwapC000) => second_last,
+            (0xA000..=0xBFFF, _)                        => self.bank_registers[7],
+            (0xC000..=0xDFFF, PrgRomBankMode::Swap8000) => second_last,
+            (0xC000..=0xDFFF, PrgRomBankMode::SwapC000) => self.bank_registers[6],
+            (0xE000..=0xFFFF, _)                        => self.prg_rom.num_banks() - 1,
+            _ => panic!("Invalid address for MMC3 PRG ROM: ${:04X}", addr),
+        };
+
+        bank % self.prg_rom.num_banks()
+    }
+
+    /// Map a PPU address in $0000-$1FFF to a 1K CHR bank
+    fn chr_bank(&self, addr: u16) -> usize {
+        // CHR A12 inversion swaps the 2K and 1K regions
+        let addr = if self.chr_inversion { addr ^ 0x1000 } else { addr };
+
+        let bank = match addr {
+            0x0000..=0x03FF => self.bank_registers[0],
+            0x0400..=0x07FF => self.bank_registers[0] + 1,
+            0x0800..=0x0BFF => self.bank_registers[1],
+            0x0C00..=0x0FFF => self.bank_registers[1] + 1,
+            0x1000..=0x13FF => self.bank_registers[2],
+            0x1400..=0x17FF => self.bank_registers[3],
+            0x1800..=0x1BFF => self.bank_registers[4],
+            0x1C00..=0x1FFF => self.bank_registers[5],
+            _ => panic!("Invalid address for MMC3 CHR: ${:04X}", addr),
+        };
+
+        bank % self.chr_data.num_banks()
+    }
+}
+
+impl MapperControl for Mmc3 {
+    fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                self.prg_ram[(addr - 0x6000) as usize]
+            },
+            0x8000..=0xFFFF => {
+                self.prg_rom.read(self.prg_bank(addr), (addr as usize) % PRG_BANK_SIZE)
+            },
+            // Open bus
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram_write_protect => {
+                self.prg_ram[(addr - 0x6000) as usize] = value;
+            },
+            0x8000..=0xFFFF => {
+                self.write_registers(addr, value);
+            },
+            _ => {},
+        }
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr_data.read(self.chr_bank(addr), (addr as usize) % CHR_BANK_SIZE)
+    }
+
+    fn write_chr(&mut self, addr: u16, value: u8) {
+        self.chr_data.write(self.chr_bank(addr), (addr as usize) % CHR_BANK_SIZE, value);
+    }
+
+    fn mirroring(&self) -> Option<Mirroring> {
+        if self.four_screen {
+            None
+        }
+        else {
+            Some(self.mirroring)
+        }
+    }
+
+    fn get_battery_ram(&self) -> Vec<u8> {
+        self.prg_ram.to_vec()
+    }
+
+    fn power_on(&mut self) {
+        // Mirroring is left as is. Its power up state is not defined
+        self.bank_registers = [0, 2, 4, 5, 6, 7, 0, 1];
+        self.bank_select = 0;
+        self.prg_rom_bank_mode = PrgRomBankMode::Swap8000;
+        self.chr_inversion = false;
+
+        self.prg_ram_enabled = true;
+        self.prg_ram_write_protect = false;
+
+        self.irq_latch = 0;
+        self.irq_counter = 0;
+        self.irq_reload = false;
+        self.irq_enabled = false;
+        self.irq_pending = false;
+
+        self.a12 = false;
+        self.a12_low_cycles = 0;
+    }
+
+    fn ppu_a12_edge(&mut self, rising: bool) {
+        if rising {
+            // Filter out A12 toggles that occur within the fetches for a single tile
+            if self.a12_low_cycles >= A12_FILTER_CYCLES {
+                self.clock_irq_counter();
+            }
+        }
+        else {
+            self.a12_low_cycles = 0;
+        }
+
+        self.a12 = rising;
+    }
+
+    fn cpu_tick(&mut self) {
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+impl Snapshot for Mmc3 {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        self.chr_data.save_state(state);
+
+        for bank in self.bank_registers.iter() {
+            state.write_usize(*bank);
+        }
+        state.write_usize(self.bank_select);
+        state.write_bool(self.prg_rom_bank_mode == PrgRomBankMode::SwapC000);
+        state.write_bool(self.chr_inversion);
+
+        state.write_bool(matches!(self.mirroring, Mirroring::Horizontal));
+
+        state.write_bool(self.prg_ram_enabled);
+        state.write_bool(self.prg_ram_write_protect);
+
+        state.write_u8(self.irq_latch);
+        state.write_u8(self.irq_counter);
+        state.write_bool(self.irq_reload);
+        state.write_bool(self.irq_enabled);
+        state.write_bool(self.irq_pending);
+
+        state.write_bool(self.a12);
+        state.write_usize(self.a12_low_cycles);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        self.chr_data.load_state(state)?;
+
+        for bank in self.bank_registers.iter_mut() {
+            *bank = state.read_usize()?;
+        }
+        self.bank_select = state.read_usize()?;
+        if self.bank_select >= self.bank_registers.len() {
+            return Err(StateError::InvalidData);
+        }
+        self.prg_rom_bank_mode = if state.read_bool()? { PrgRomBankMode::SwapC000 } else { PrgRomBankMode::Swap8000 };
+        self.chr_inversion = state.read_bool()?;
+
+        self.mirroring = if state.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+
+        self.prg_ram_enabled = state.read_bool()?;
+        self.prg_ram_write_protect = state.read_bool()?;
+
+        self.irq_latch = state.read_u8()?;
+        self.irq_counter = state.read_u8()?;
+        self.irq_reload = state.read_bool()?;
+        self.irq_enabled = state.read_bool()?;
+        self.irq_pending = state.read_bool()?;
+
+        self.a12 = state.read_bool()?;
+        self.a12_low_cycles = state.read_usize()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prg_bank_mode_0() {
+        let mut mmc3 = init_mmc3(4, 1);
+
+        // R6 = bank 1, R7 = bank 2
+        mmc3.write(0x8000, 0x06);
+        mmc3.write(0x8001, 0x01);
+        mmc3.write(0x8000, 0x07);
+        mmc3.write(0x8001, 0x02);
+
+        assert_eq!(mmc3.read(0x8000), 1);
+        assert_eq!(mmc3.read(0xA000), 2);
+        assert_eq!(mmc3.read(0xC000), 6);
+        assert_eq!(mmc3.read(0xE000), 7);
+    }
+
+    #[test]
+    fn prg_bank_mode_1() {
+        let mut mmc3 = init_mmc3(4, 1);
+
+        // R6 = bank 1, PRG mode 1
+        mmc3.write(0x8000, 0x46);
+        mmc3.write(0x8001, 0x01);
+
+        assert_eq!(mmc3.read(0x8000), 6);
+        assert_eq!(mmc3.read(0xC000), 1);
+        assert_eq!(mmc3.read(0xE000), 7);
+    }
+
+    #[test]
+    fn chr_banks() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        // R0 = 2K bank at $0000, R2 = 1K bank at $1000
+        mmc3.write(0x8000, 0x00);
+        mmc3.write(0x8001, 0x03);
+        mmc3.write(0x8000, 0x02);
+        mmc3.write(0x8001, 0x05);
+
+        // Low bit of R0 is ignored
+        assert_eq!(mmc3.read_chr(0x0000), 2);
+        assert_eq!(mmc3.read_chr(0x0400), 3);
+        assert_eq!(mmc3.read_chr(0x1000), 5);
+    }
+
+    #[test]
+    fn chr_inversion() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        mmc3.write(0x8000, 0x80);
+        mmc3.write(0x8001, 0x04);
+        mmc3.write(0x8000, 0x82);
+        mmc3.write(0x8001, 0x01);
+
+        assert_eq!(mmc3.read_chr(0x1000), 4);
+        assert_eq!(mmc3.read_chr(0x1400), 5);
+        assert_eq!(mmc3.read_chr(0x0000), 1);
+    }
+
+    #[test]
+    fn mirroring() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        mmc3.write(0xA000, 0x00);
+        assert!(matches!(mmc3.mirroring(), Some(Mirroring::Vertical)));
+
+        mmc3.write(0xA000, 0x01);
+        assert!(matches!(mmc3.mirroring(), Some(Mirroring::Horizontal)));
+    }
+
+    #[test]
+    fn prg_ram_protect() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        mmc3.write(0x6000, 0xDE);
+        assert_eq!(mmc3.read(0x6000), 0xDE);
+
+        // Enabled, write protected
+        mmc3.write(0xA001, 0xC0);
+        mmc3.write(0x6000, 0xAD);
+        assert_eq!(mmc3.read(0x6000), 0xDE);
+
+        // Disabled
+        mmc3.write(0xA001, 0x00);
+        assert_eq!(mmc3.read(0x6000), 0x00);
+    }
+
+    #[test]
+    fn irq_after_latch_scanlines() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        mmc3.write(0xC000, 2);
+        mmc3.write(0xC001, 0);
+        mmc3.write(0xE001, 0);
+
+        // First clock reloads the counter
+        clock_a12(&mut mmc3);
+        assert!(!mmc3.irq());
+        clock_a12(&mut mmc3);
+        assert!(!mmc3.irq());
+        clock_a12(&mut mmc3);
+        assert!(mmc3.irq());
+
+        // Acknowledge
+        mmc3.write(0xE000, 0);
+        assert!(!mmc3.irq());
+    }
+
+    #[test]
+    fn a12_filter() {
+        let mut mmc3 = init_mmc3(2, 1);
+
+        mmc3.write(0xC000, 0);
+        mmc3.write(0xE001, 0);
+
+        // A12 toggles faster than the filter allows
+        mmc3.ppu_a12_edge(false);
+        mmc3.cpu_tick();
+        mmc3.ppu_a12_edge(true);
+
+        assert!(!mmc3.irq());
+
+        clock_a12(&mut mmc3);
+        assert!(mmc3.irq());
+    }
+
+    fn clock_a12(mmc3: &mut Mmc3) {
+        mmc3.ppu_a12_edge(false);
+        for _ in 0..A12_FILTER_CYCLES {
+            mmc3.cpu_tick();
+        }
+        mmc3.ppu_a12_edge(true);
+    }
+
+    /// Create an MMC3 where every PRG and CHR bank contains its bank number
+    fn init_mmc3(num_prg_banks: u8, num_chr_banks: u8) -> Mmc3 {
+        let header = init_header(num_prg_banks, num_chr_banks);
+
+        let prg_rom = (0..(num_prg_banks as usize * 2))
+                        .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+                        .collect::<Vec<u8>>();
+        let chr_rom = (0..(num_chr_banks as usize * 8))
+                        .flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE])
+                        .collect::<Vec<u8>>();
+
+        let rom = [&header[..], &prg_rom[..], &chr_rom[..]].concat();
+
+        let cart = Cartridge::from(rom).unwrap();
+        Mmc3::from(cart)
+    }
+
+    fn init_header(num_prg_banks: u8, num_chr_banks: u8) -> [u8; 16] {
+        [
+            0x4E, 0x45, 0x53, 0x1A, // NES<EOF>
+            num_prg_banks,          // PRG ROM
+            num_chr_banks,          // CHR ROM
+            0x40,                   // Flag 6
+            0x00,                   // Flag 7
+            0x00,                   // Flag 8
+            0x00,                   // Flag 9
+            0x00,                   // Flag 10
+            0x00,                   // Flag 11
+            0x00,                   // Flag 12
+            0x00,                   // Flag 13
+            0x00,                   // Flag 14
+            0x00,                   // Flag 15
+        ]
+    }
+}
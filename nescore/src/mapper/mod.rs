@@ -14,6 +14,7 @@ mod mmc1;
 mod unrom;
 mod cnrom;
 mod axrom;
+mod mmc3;
 
 // Public re-exports
 pub use mapper::{Mapper, Mirroring, MapperControl, from_cartridge};
@@ -10,6 +10,7 @@ use super::MapperControl;
 use crate::cart::{Cartridge, PRG_ROM_BANK_SIZE};
 
 use super::mem::Memory;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 const PRG_RAM_SIZE: usize = kb!(8);
 const CHR_DATA_SIZE: usize = kb!(8);
@@ -86,6 +87,18 @@ impl MapperControl for Nrom {
     }
 }
 
+impl Snapshot for Nrom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.prg_ram);
+        state.write_bytes(&self.chr_data);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.prg_ram)?;
+        state.read_bytes(&mut self.chr_data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
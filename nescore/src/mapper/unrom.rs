@@ -32,6 +32,7 @@
 use super::MapperControl;
 use super::mem::Memory;
 use crate::cart::{Cartridge, PRG_ROM_BANK_SIZE};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 const CHR_RAM_SIZE: usize = kb!(8);
 
@@ -40,18 +41,20 @@ pub struct Unrom {
     prg_rom: Memory,
     chr_ram: [u8; CHR_RAM_SIZE],
     rom_bank_selection: usize, // Select ROM bank
+    bus_conflicts: bool,       // Register writes are ANDed with the ROM byte at the address
 }
 
 
 impl From<Cartridge> for Unrom {
     fn from(cart: Cartridge) -> Self {
         // Extract info and ROM data, VROM is unused
-        let (_, prg_rom, _, _) = cart.into_parts();
+        let (info, prg_rom, _, _) = cart.into_parts();
 
         Unrom{
             prg_rom: Memory::new(prg_rom, PRG_ROM_BANK_SIZE),
             chr_ram: [0; CHR_RAM_SIZE],
             rom_bank_selection: 0,
+            bus_conflicts: info.submapper == 2,
         }
     }
 }
@@ -71,6 +74,7 @@ impl MapperControl for Unrom {
 
     fn write(&mut self, addr: u16, data: u8) {
         if let 0x8000..=0xFFFF = addr {
+            let data = if self.bus_conflicts { data & self.read(addr) } else { data };
             self.rom_bank_selection = (data & 0x0F) as usize;
         }
     }
@@ -82,6 +86,29 @@ impl MapperControl for Unrom {
     fn write_chr(&mut self, addr: u16, value: u8) {
         self.chr_ram[addr as usize] = value;
     }
+
+    fn power_on(&mut self) {
+        self.rom_bank_selection = 0;
+    }
+}
+
+impl Snapshot for Unrom {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.chr_ram);
+        state.write_usize(self.rom_bank_selection);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.chr_ram)?;
+
+        let bank = state.read_usize()?;
+        if bank >= self.prg_rom.num_banks() {
+            return Err(StateError::InvalidData);
+        }
+        self.rom_bank_selection = bank;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -100,6 +127,22 @@ mod tests {
         assert_eq!(unrom.read(0x8000), 0xDE);
     }
 
+    #[test]
+    fn bus_conflicts() {
+        let mut data = vec![0; 40];
+        data[10] = 0xDE;
+        data[30] = 0xAD;
+        // ROM byte at $8001 in bank 0
+        data[1] = 0x01;
+
+        let mut unrom = init_unrom(data, 10);
+        unrom.bus_conflicts = true;
+
+        // Bank 3 AND 1 selects bank 1
+        unrom.write(0x8001, 3);
+        assert_eq!(unrom.read(0x8000), 0xDE);
+    }
+
     #[test]
     fn irq() {
         let mut prg = vec![0; PRG_ROM_BANK_SIZE * 2];
@@ -120,6 +163,7 @@ mod tests {
             prg_rom: Memory::new(data, bank_size),
             chr_ram: [0; CHR_RAM_SIZE],
             rom_bank_selection: 0,
+            bus_conflicts: false,
         }
     }
 }
@@ -4,15 +4,21 @@
 // @author Natesh Narain <nnaraindev@gmail.com>
 // @date Sep 17 2020
 //
-use crate::cart::Cartridge;
-use crate::cpu::{Cpu, bus::CpuIoBus};
-use crate::ppu::{Ppu, bus::PpuIoBus};
+use crate::cart::{Cartridge, CartridgeError};
+use crate::cpu::{Cpu, CpuRegisters, bus::CpuIoBus};
+use crate::ppu::{Ppu, PpuRegisters, Palette, bus::PpuIoBus};
 use crate::apu::{Apu, bus::ApuIoBus};
 use crate::joy::Joy;
 use crate::mapper::Mapper;
 use crate::common::Clockable;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError, STATE_MAGIC, STATE_VERSION};
+use crate::rewind::RewindBuffer;
+use crate::debug::{DebuggerRef, Breakpoint, BreakpointHit};
+use crate::trace::{TraceLogger, TraceState};
+use crate::region::Region;
+use crate::hooks::{PpuHooks, PpuHook, PpuHookEvent};
 
-use crate::ppu::Pixel;
+use crate::ppu::{Pixel, IndexedPixel};
 use crate::apu::Sample;
 use crate::joy::{Controller, Button};
 
@@ -36,6 +42,8 @@ pub enum PixelFormat {
     RGBA8,
     GBRA8,
     BGRA8,
+    /// 9-bit palette color and emphasis bits (see `specs::IndexedPixel`) as a little endian u16. The palette is not applied
+    Indexed,
 }
 
 impl PixelFormat {
@@ -45,8 +53,46 @@ impl PixelFormat {
             PixelFormat::RGBA8 => 4,
             PixelFormat::GBRA8 => 4,
             PixelFormat::BGRA8 => 4,
+            PixelFormat::Indexed => 2,
         }
     }
+
+    /// Convert an RGB color into the format. The first `num_bytes` bytes are used
+    ///
+    /// Panics for `PixelFormat::Indexed`, which can't be produced from RGB
+    pub(crate) fn encode(&self, pixel: Pixel) -> [u8; 4] {
+        match *self {
+            PixelFormat::RGB8 =>  [pixel.0, pixel.1, pixel.2, 0],
+            PixelFormat::RGBA8 => [pixel.0, pixel.1, pixel.2, 255],
+            PixelFormat::GBRA8 => [pixel.1, pixel.2, pixel.0, 255],
+            PixelFormat::BGRA8 => [pixel.2, pixel.1, pixel.0, 255],
+            PixelFormat::Indexed => panic!("RGB colors can't be converted to indexed pixels"),
+        }
+    }
+}
+
+/// Limit on how long the emulator is run for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    /// Number of CPU cycles
+    Cycles(u64),
+    /// Number of frames (PPU cycles per frame)
+    Frames(u64),
+}
+
+/// Reason the emulator stopped running
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// The CPU is about to execute the instruction at the target address
+    Address(u16),
+    /// The budget was used up before reaching the stop condition
+    BudgetExhausted,
+    /// Nothing is run without a cartridge
+    NoCartridge,
+    /// A breakpoint was hit
+    Breakpoint(BreakpointHit),
+    /// The CPU was halted by the JAM instruction at the given address
+    Jammed(u16),
 }
 
 /// Sequencer event
@@ -57,26 +103,80 @@ enum Event {
 type SequencerEvents = [Event; 3];
 
 /// Component frame sequencer
-#[derive(Default)]
+///
+/// Each tick is a PPU cycle. The CPU is clocked when enough master clock cycles have elapsed, and the APU is clocked
+/// every other CPU cycle
 struct FrameSequencer {
-    counter: u32,
+    master: u32,      // Master clock cycles since the last CPU cycle
+    apu_cycle: bool,  // The next CPU cycle also clocks the APU
+    cpu_divider: u32,
+    ppu_divider: u32,
+}
+
+impl Default for FrameSequencer {
+    fn default() -> Self {
+        FrameSequencer::new(Region::default())
+    }
 }
 
 impl Clockable<SequencerEvents> for FrameSequencer {
     fn tick(&mut self) -> SequencerEvents {
-        let events = match self.counter {
-            0 => [Event::PPU, Event::CPU, Event::APU],
-            1 => [Event::PPU, Event::None, Event::None],
-            2 => [Event::PPU, Event::None, Event::None],
-            3 => [Event::PPU, Event::CPU, Event::None],
-            4 => [Event::PPU, Event::None, Event::None],
-            5 => [Event::PPU, Event::None, Event::None],
-            _ => panic!("Invalid clock for sequencer"),
-        };
+        let cpu_cycle = self.cpu_cycle();
+
+        self.master += self.ppu_divider;
+
+        if cpu_cycle {
+            self.master -= self.cpu_divider;
+
+            let apu_cycle = self.apu_cycle;
+            self.apu_cycle = !self.apu_cycle;
+
+            if apu_cycle {
+                [Event::PPU, Event::CPU, Event::APU]
+            }
+            else {
+                [Event::PPU, Event::CPU, Event::None]
+            }
+        }
+        else {
+            [Event::PPU, Event::None, Event::None]
+        }
+    }
+}
+
+impl FrameSequencer {
+    fn new(region: Region) -> Self {
+        // The CPU is clocked on the first PPU cycle
+        FrameSequencer {
+            master: region.cpu_divider() - region.ppu_divider(),
+            apu_cycle: true,
+            cpu_divider: region.cpu_divider(),
+            ppu_divider: region.ppu_divider(),
+        }
+    }
+
+    /// Check if the next tick will clock the CPU
+    fn cpu_cycle(&self) -> bool {
+        self.master + self.ppu_divider >= self.cpu_divider
+    }
+}
+
+impl Snapshot for FrameSequencer {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u32(self.master);
+        state.write_bool(self.apu_cycle);
+    }
 
-        self.counter = (self.counter + 1) % 6;
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        let master = state.read_u32()?;
+        if master >= self.cpu_divider {
+            return Err(StateError::InvalidData);
+        }
+
+        self.master = master;
+        self.apu_cycle = state.read_bool()?;
 
-        events
+        Ok(())
     }
 }
 
@@ -89,6 +189,21 @@ pub struct Nes {
     mapper: Option<Mapper>,          // Cartridge Mapper
 
     sequencer: FrameSequencer,       // Used to clock components in the right order
+    cpu_cycles: u64,                 // Number of CPU cycles run
+
+    cart_region: Region,             // Region from the cartridge header
+    forced_region: Option<Region>,   // Region to use instead of the one in the cartridge header
+    palette: Option<Palette>,        // Palette to use instead of the region's default
+
+    rewind: Option<RewindBuffer>,    // Snapshots for rewinding
+    frame: u64,                      // Number of frames emulated
+    frame_progress: usize,           // PPU cycles run in the current frame
+
+    debugger: DebuggerRef,           // Breakpoints and watchpoints
+    hit: Option<BreakpointHit>,      // Last breakpoint hit
+
+    tracer: Option<TraceLogger>,     // Instruction trace log
+    hooks: PpuHooks,                 // Callbacks at PPU positions
 
     framebuffer: Vec<u8>,
     pixel_format: PixelFormat,       // Pixel format
@@ -107,6 +222,21 @@ impl Default for Nes {
             mapper: None,
 
             sequencer: FrameSequencer::default(),
+            cpu_cycles: 0,
+
+            cart_region: Region::default(),
+            forced_region: None,
+            palette: None,
+
+            rewind: None,
+            frame: 0,
+            frame_progress: 0,
+
+            debugger: Rc::default(),
+            hit: None,
+
+            tracer: None,
+            hooks: PpuHooks::default(),
 
             framebuffer,
             pixel_format,
@@ -125,29 +255,147 @@ impl Nes {
     }
 
     /// Directly set the CPU entry point
+    ///
+    /// The reset sequence runs first, with the PPU starting from scanline 0 as it does in `nestest.log`.
+    /// Without a cartridge the reset sequence is skipped
     /// ```
     /// # use nescore::Nes;
     /// let nes = Nes::default().entry(0xC000);
     /// ```
-    pub fn entry(self, entry_addr: u16) -> Self {
+    pub fn entry(mut self, entry_addr: u16) -> Self {
+        if self.mapper.is_some() {
+            self.ppu.borrow_mut().set_position(0, 0);
+
+            while !self.cpu.borrow().at_instruction_boundary() {
+                self.clock();
+                self.frame_progress += 1;
+            }
+        }
+
         self.cpu.borrow_mut().set_pc(entry_addr);
         self
     }
 
     /// Builder function to allow inserting the cartridge
+    ///
+    /// Panics if the cartridge is not supported. See `Nes::try_with_cart`
     pub fn with_cart(mut self, cart: Cartridge) -> Self {
         self.insert(cart);
         self
     }
 
+    /// Builder function to insert a cartridge, returning an error if the cartridge is not supported
+    ///
+    /// This is the fallible version of `Nes::with_cart` and `Nes::from`
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// match Nes::default().try_with_cart(cart) {
+    ///     Ok(nes) => {},
+    ///     Err(e) => println!("{}", e),
+    /// }
+    /// ```
+    pub fn try_with_cart(mut self, cart: Cartridge) -> Result<Self, CartridgeError> {
+        self.try_insert(cart)?;
+        Ok(self)
+    }
+
+    /// Builder function to select the console region
+    ///
+    /// By default the region is detected from the cartridge header
+    /// ```
+    /// # use nescore::{Nes, Region};
+    /// let nes = Nes::default().region(Region::Pal);
+    /// ```
+    pub fn region(mut self, region: Region) -> Self {
+        self.set_region(Some(region));
+        self
+    }
+
+    /// Select the console region, or detect it from the cartridge header with `None`
+    ///
+    /// Changing the region restarts the current frame
+    pub fn set_region(&mut self, region: Option<Region>) {
+        self.forced_region = region;
+        self.apply_region();
+    }
+
+    /// Builder function to select the palette used to render the display
+    ///
+    /// By default the palette of the region's PPU is used
+    /// ```
+    /// # use nescore::{Nes, Palette, BuiltinPalette};
+    /// let nes = Nes::default().palette(Palette::from(BuiltinPalette::Ppu2C03));
+    /// ```
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.set_palette(Some(palette));
+        self
+    }
+
+    /// Select the palette used to render the display, or the region's default with `None`
+    pub fn set_palette(&mut self, palette: Option<Palette>) {
+        self.palette = palette;
+
+        let palette = self.palette.clone().unwrap_or_else(|| Palette::for_region(self.get_region()));
+        self.ppu.borrow_mut().set_palette(palette);
+    }
+
+    /// Builder function to limit rendering to 8 sprites per scanline (the default)
+    ///
+    /// Removing the limit draws every sprite on a scanline, reducing flicker. Games still see the hardware behaviour,
+    /// including the sprite overflow flag
+    /// ```
+    /// # use nescore::Nes;
+    /// let nes = Nes::default().sprite_limit(false);
+    /// ```
+    pub fn sprite_limit(mut self, enabled: bool) -> Self {
+        self.set_sprite_limit(enabled);
+        self
+    }
+
+    /// Limit rendering to 8 sprites per scanline
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.ppu.borrow_mut().set_sprite_limit(enabled);
+    }
+
     /// Set color output format
     pub fn pixel_format(mut self, pixel_format: PixelFormat) -> Self {
+        self.set_pixel_format(pixel_format);
+        self
+    }
+
+    /// Set color output format. The framebuffer is cleared
+    pub fn set_pixel_format(&mut self, pixel_format: PixelFormat) {
         self.pixel_format = pixel_format;
         self.framebuffer = vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT * pixel_format.num_bytes()];
+    }
+
+    /// Builder function to enable rewinding
+    ///
+    /// A snapshot is taken every `interval` frames, keeping up to `capacity` snapshots
+    /// ```
+    /// # use nescore::Nes;
+    /// // Keep 10 seconds of history, taking a snapshot every other frame
+    /// let nes = Nes::default().rewind_buffer(300, 2);
+    /// ```
+    pub fn rewind_buffer(mut self, capacity: usize, interval: usize) -> Self {
+        self.rewind = Some(RewindBuffer::new(capacity, interval));
+        self
+    }
 
+    /// Builder function to log each instruction executed. See `nescore::trace`
+    pub fn trace_logger(mut self, logger: TraceLogger) -> Self {
+        self.set_trace_logger(Some(logger));
         self
     }
 
+    /// Start or stop (with `None`) logging instructions
+    ///
+    /// Logging stops if writing to the logger fails
+    pub fn set_trace_logger(&mut self, logger: Option<TraceLogger>) {
+        self.tracer = logger;
+    }
+
     /// Builder function to set debug mode
     /// ```
     /// # use nescore::Nes;
@@ -168,29 +416,35 @@ impl Nes {
     ///
     /// * `videobuffer` - A RGB8 frame buffer
     /// * `audiobuffer` - Raw APU output (This must be down sampled to host playback rate)
+    ///
+    /// If a breakpoint is hit the frame is halted early. See `Nes::breakpoint_hit`. The next call resumes the frame
     pub fn emulate_frame(&mut self) -> (&[u8], SampleBuffer) {
-        // let mut framebuffer = [0x00u8; FRAME_BUFFER_SIZE];
-        let mut framebuffer_idx = 0usize;
-
         let mut samplebuffer: Vec<Sample> = Vec::new();
 
+        self.hit = None;
+
         if self.mapper.is_some() {
-            for _ in 0..crate::ppu::CYCLES_PER_FRAME {
+            if self.frame_progress == 0 {
+                self.capture_rewind();
+            }
+
+            loop {
                 // Clock the CPU, PPU and APU
-                let (pixel, sample) = self.clock_components();
-
-                if let Some(pixel) = pixel {
-                    let bytes = self.format_color_output(pixel);
-                    // TODO: This produces a clippy warning. However, `i` is not necessarily used to index the entirety
-                    // of `bytes`. There's probably a better way to do this...
-                    for i in 0..self.pixel_format.num_bytes() {
-                        self.framebuffer[framebuffer_idx] = bytes[i];
-                        framebuffer_idx = (framebuffer_idx + 1) % self.framebuffer.len();
-                    }
+                if let Some(sample) = self.clock() {
+                    samplebuffer.push(sample);
                 }
 
-                if let Some(sample) = sample {
-                    samplebuffer.push(sample);
+                self.frame_progress += 1;
+
+                // The frame ends when the PPU returns to the pre-render scanline. Odd frames may be one dot shorter
+                if self.at_frame_start() {
+                    self.frame_progress = 0;
+                    self.frame += 1;
+                    break;
+                }
+
+                if self.hit.is_some() {
+                    break;
                 }
             }
         }
@@ -198,15 +452,198 @@ impl Nes {
         (&self.framebuffer, samplebuffer)
     }
 
-    /// Covert a PPU RGB pixel into different color formats
+    /// The current framebuffer
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Stepping
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Run until the current CPU instruction has completed. Returns the number of CPU cycles run
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// let mut nes = Nes::from(cart);
+    /// nes.step_instruction();
+    /// println!("PC: ${:04X}", nes.get_program_counter());
+    /// ```
+    pub fn step_instruction(&mut self) -> usize {
+        let mut cycles = 0;
+
+        if self.mapper.is_some() {
+            loop {
+                self.step_cycle();
+                cycles += 1;
+
+                let cpu = self.cpu.borrow();
+                if cpu.at_instruction_boundary() || cpu.jammed().is_some() {
+                    break;
+                }
+            }
+        }
+
+        cycles
+    }
+
+    /// Run until the CPU has been clocked once
+    pub fn step_cycle(&mut self) {
+        if self.mapper.is_some() {
+            loop {
+                let cpu_cycle = self.sequencer.cpu_cycle();
+                self.clock();
+
+                if cpu_cycle {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Run until the PPU moves to the next scanline
+    pub fn step_scanline(&mut self) {
+        if self.mapper.is_some() {
+            let (scanline, _) = self.ppu.borrow().position();
+            while self.ppu.borrow().position().0 == scanline {
+                self.clock();
+            }
+        }
+    }
+
+    /// Run for the specified number of CPU cycles
+    pub fn run_for_cycles(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.step_cycle();
+        }
+    }
+
+    /// Run until the CPU is about to execute the instruction at **addr**, or the budget is exhausted
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge, Budget, StopReason};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// let mut nes = Nes::from(cart);
+    /// match nes.run_until_bounded(0xC66E, Budget::Frames(60)) {
+    ///     StopReason::Address(addr) => println!("Reached ${:04X}", addr),
+    ///     reason => println!("Stopped: {:?}", reason),
+    /// }
+    /// ```
+    pub fn run_until_bounded(&mut self, addr: u16, budget: Budget) -> StopReason {
+        if self.mapper.is_none() {
+            return StopReason::NoCartridge;
+        }
+
+        self.hit = None;
+
+        let mut cycles = 0u64;
+        let mut dots = 0u64;
+
+        loop {
+            if self.reached(addr) {
+                return StopReason::Address(addr);
+            }
+
+            if let Some(jam_addr) = self.jammed() {
+                return StopReason::Jammed(jam_addr);
+            }
+
+            let exhausted = match budget {
+                Budget::Cycles(n) => cycles >= n,
+                Budget::Frames(n) => dots >= n * self.get_region().ppu_cycles_per_frame() as u64,
+            };
+
+            if exhausted {
+                return StopReason::BudgetExhausted;
+            }
+
+            if self.sequencer.cpu_cycle() {
+                cycles += 1;
+            }
+
+            self.clock();
+            dots += 1;
+
+            if let Some(hit) = self.hit {
+                return StopReason::Breakpoint(hit);
+            }
+        }
+    }
+
+    /// Check if the CPU is about to execute the instruction at **addr**
+    fn reached(&self, addr: u16) -> bool {
+        let cpu = self.cpu.borrow();
+        cpu.at_instruction_boundary() && cpu.get_pc() == addr
+    }
+
+    /// Clock the system by a single PPU cycle and write any pixel produced to the framebuffer
+    fn clock(&mut self) -> Option<Sample> {
+        let cpu_cycle = self.sequencer.cpu_cycle();
+
+        if cpu_cycle && self.tracer.is_some() && self.cpu.borrow().at_instruction_boundary() {
+            self.trace();
+        }
+
+        let (scanline, dot) = self.ppu.borrow().position();
+        let (pixel, sample) = self.clock_components();
+
+        if cpu_cycle {
+            self.cpu_cycles += 1;
+        }
+
+        if let Some(pixel) = pixel {
+            let bytes = self.format_color_output(pixel);
+            let num_bytes = self.pixel_format.num_bytes();
+            let idx = ((scanline * DISPLAY_WIDTH) + dot) * num_bytes;
+
+            self.framebuffer[idx..idx + num_bytes].copy_from_slice(&bytes[..num_bytes]);
+        }
+
+        if let Some(hit) = self.debugger.borrow_mut().take_hit() {
+            self.hit = Some(hit);
+        }
+
+        sample
+    }
+
+    /// Check if the PPU is at the start of the pre-render scanline
+    fn at_frame_start(&self) -> bool {
+        self.ppu.borrow().position() == (self.get_region().scanlines() - 1, 0)
+    }
+
+    /// Log the instruction the CPU is about to execute
+    fn trace(&mut self) {
+        if let Some(mut tracer) = self.tracer.take() {
+            let regs = self.get_cpu_registers();
+            let (scanline, dot) = self.get_ppu_position();
+
+            let state = TraceState {
+                pc: regs.pc,
+                a: regs.a,
+                x: regs.x,
+                y: regs.y,
+                p: regs.p,
+                sp: regs.sp,
+                scanline,
+                dot,
+                frame: self.frame,
+                cycles: self.cpu_cycles,
+            };
+
+            if tracer.trace(self, &state).is_ok() {
+                self.tracer = Some(tracer);
+            }
+        }
+    }
+
+    /// Covert a PPU pixel into different color formats
     /// return 4 bytes with the color data and a bool that indicates if the last byte is used
-    fn format_color_output(&self, pixel: Pixel) -> [u8; 4] {
-        match self.pixel_format {
-            PixelFormat::RGB8 =>  [pixel.0, pixel.1, pixel.2, 0],
-            PixelFormat::RGBA8 => [pixel.0, pixel.1, pixel.2, 255],
-            PixelFormat::GBRA8 => [pixel.1, pixel.2, pixel.0, 255],
-            PixelFormat::BGRA8 => [pixel.2, pixel.1, pixel.0, 255],
+    fn format_color_output(&self, pixel: IndexedPixel) -> [u8; 4] {
+        if let PixelFormat::Indexed = self.pixel_format {
+            let [lo, hi] = pixel.to_le_bytes();
+            return [lo, hi, 0, 0];
         }
+
+        self.pixel_format.encode(self.ppu.borrow().color(pixel))
     }
 
     /// Run the NES emulator until it fills an audio buffer to the specified size
@@ -219,7 +656,7 @@ impl Nes {
         let mut buffer = vec![0f32; 0];
 
         while buffer.len() < buffer_size {
-            let sample = self.clock_components().1;
+            let sample = self.clock();
             if let Some(sample) = sample {
                 buffer.push(sample);
             }
@@ -252,26 +689,37 @@ impl Nes {
     }
 
     /// Run until the CPU's PC is at address **addr**
+    ///
+    /// This will not return if the address is never reached. See `Nes::run_until_bounded`
     pub fn run_until(&mut self, addr: u16) {
-        // TODO: Time limit
         if self.mapper.is_some() {
-            while self.cpu.borrow().get_pc() != addr {
-                self.clock_components();
+            while !self.reached(addr) {
+                self.clock();
             }
         }
     }
 
     /// Clock the NES components
-    fn clock_components(&mut self) -> (Option<Pixel>, Option<Sample>) {
-        let mut pixel: Option<Pixel> = None;
+    fn clock_components(&mut self) -> (Option<IndexedPixel>, Option<Sample>) {
+        let mut pixel: Option<IndexedPixel> = None;
         let mut sample: Option<Sample> = None;
 
         for event in self.sequencer.tick().iter() {
             match event {
                 Event::PPU => {
+                    let (scanline, dot) = self.ppu.borrow().position();
                     pixel = self.ppu.borrow_mut().tick();
+
+                    if !self.hooks.is_empty() {
+                        let ppu = &self.ppu;
+                        self.hooks.run(scanline, dot, self.get_region(), || ppu.borrow().registers());
+                    }
                 },
                 Event::CPU => {
+                    if let Some(ref mapper) = self.mapper {
+                        mapper.borrow_mut().cpu_tick();
+                    }
+
                     self.cpu.borrow_mut().tick();
                 },
                 Event::APU => {
@@ -289,22 +737,124 @@ impl Nes {
         self.cpu.borrow().is_holding()
     }
 
+    /// Address of the JAM instruction, if the CPU has been halted by one
+    pub fn jammed(&self) -> Option<u16> {
+        self.cpu.borrow().jammed()
+    }
+
     /// Load a cartridge
+    ///
+    /// Panics if the cartridge is not supported. See `Nes::try_insert`
     pub fn insert(&mut self, cart: Cartridge) {
+        if let Err(e) = self.try_insert(cart) {
+            panic!("Failed to insert cartridge: {}", e);
+        }
+    }
+
+    /// Load a cartridge, returning an error if the cartridge's mapper is not supported or its data is inconsistent
+    ///
+    /// The NES is left unchanged when an error is returned
+    pub fn try_insert(&mut self, cart: Cartridge) -> Result<(), CartridgeError> {
+        let region = Region::from_cartridge(&cart.info);
+
         // Consume provided cartridge and get the mapper
-        let mapper = crate::mapper::from_cartridge(cart);
+        let mapper = crate::mapper::from_cartridge(cart)?;
 
         // Complete initialization of components
-        let cpu_bus = CpuIoBus::new(self.ppu.clone(), self.apu.clone(), self.joy.clone(), mapper.clone());
+        self.connect(mapper.clone());
+
+        self.cart_region = region;
+        self.apply_region();
+
+        self.mapper = Some(mapper);
+
+        // History from a previous cartridge cannot be restored
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Press the RESET button
+    ///
+    /// The CPU restarts from the reset vector with RAM intact. The APU is silenced and PPUCTRL, PPUMASK and the scroll
+    /// are cleared. Mapper registers are kept, except on mappers that observe the RESET line
+    /// ```
+    /// # use nescore::Nes;
+    /// let mut nes = Nes::default();
+    /// nes.reset();
+    /// ```
+    pub fn reset(&mut self) {
+        self.cpu.borrow_mut().reset();
+        self.ppu.borrow_mut().reset();
+        self.apu.borrow_mut().reset();
+
+        if let Some(ref mapper) = self.mapper {
+            mapper.borrow_mut().reset();
+        }
+
+        self.hit = None;
+    }
+
+    /// Turn the NES off and on again, keeping the inserted cartridge
+    ///
+    /// All components return to their power up state. Battery backed RAM is kept
+    /// ```
+    /// # use nescore::Nes;
+    /// let mut nes = Nes::default();
+    /// nes.power_cycle();
+    /// ```
+    pub fn power_cycle(&mut self) {
+        self.cpu.borrow_mut().power_on();
+        self.ppu.borrow_mut().power_on();
+        self.apu.borrow_mut().power_on();
+
+        if let Some(mapper) = self.mapper.clone() {
+            mapper.borrow_mut().power_on();
+            self.connect(mapper);
+        }
+
+        self.sequencer = FrameSequencer::new(self.get_region());
+        self.cpu_cycles = 0;
+        self.frame = 0;
+        self.frame_progress = 0;
+        self.hit = None;
+
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.clear();
+        }
+    }
+
+    /// Configure the system timing for the selected region
+    fn apply_region(&mut self) {
+        let region = self.get_region();
+
+        self.sequencer = FrameSequencer::new(region);
+        self.ppu.borrow_mut().set_region(region);
+        self.apu.borrow_mut().set_region(region);
+
+        if let Some(ref palette) = self.palette {
+            self.ppu.borrow_mut().set_palette(palette.clone());
+        }
+
+        // The PPU restarts from the pre-render scanline
+        self.frame_progress = 0;
+    }
+
+    /// Connect the CPU, PPU and APU to their buses
+    fn connect(&mut self, mapper: Mapper) {
+        let mut cpu_bus = CpuIoBus::new(self.ppu.clone(), self.apu.clone(), self.joy.clone(), mapper.clone());
+        cpu_bus.load_debugger(self.debugger.clone());
         self.cpu.borrow_mut().load_bus(cpu_bus);
+        self.cpu.borrow_mut().load_debugger(self.debugger.clone());
 
-        let ppu_bus = PpuIoBus::new(self.cpu.clone(), mapper.clone());
+        let ppu_bus = PpuIoBus::new(mapper.clone());
         self.ppu.borrow_mut().load_bus(ppu_bus);
+        self.ppu.borrow_mut().load_debugger(self.debugger.clone());
 
-        let apu_bus = Rc::new(RefCell::new(ApuIoBus::new(self.cpu.clone(), mapper.clone())));
+        let apu_bus = Rc::new(RefCell::new(ApuIoBus::new(mapper)));
         self.apu.borrow_mut().load_bus(apu_bus);
-
-        self.mapper = Some(mapper);
     }
 
     /// Eject the cartridge, returning the save state
@@ -319,19 +869,221 @@ impl Nes {
     }
 
     //------------------------------------------------------------------------------------------------------------------
-    // Event Logging
+    // Save States
     //------------------------------------------------------------------------------------------------------------------
-    #[cfg(feature="events")]
-    pub fn cpu_event_channel(&mut self) -> Receiver<crate::events::CpuEvent> {
-        let (tx, rx) = channel::<crate::events::CpuEvent>();
-        self.cpu.borrow_mut().set_event_sender(tx);
-
-        rx
-    }
 
-    #[cfg(feature="events")]
-    pub fn apu_event_channel(&mut self) -> Receiver<crate::events::ApuEvent> {
-        let (tx, rx) = channel::<crate::events::ApuEvent>();
+    /// Save the state of the entire system
+    ///
+    /// The state can only be restored into a NES with the same cartridge inserted
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// let mut nes = Nes::from(cart);
+    /// let state = nes.save_state().unwrap();
+    /// nes.emulate_frame();
+    /// nes.load_state(&state).unwrap();
+    /// ```
+    pub fn save_state(&self) -> Result<Vec<u8>, StateError> {
+        let mapper = self.mapper.as_ref().ok_or(StateError::NoCartridge)?;
+
+        let mut state = StateWriter::default();
+        state.write_bytes(&STATE_MAGIC);
+        state.write_u32(STATE_VERSION);
+        state.write_u8(self.get_region().to_u8());
+
+        self.sequencer.save_state(&mut state);
+        state.write_u64(self.cpu_cycles);
+        self.cpu.borrow().save_state(&mut state);
+        self.ppu.borrow().save_state(&mut state);
+        self.apu.borrow().save_state(&mut state);
+        self.joy.borrow().save_state(&mut state);
+        mapper.borrow().save_state(&mut state);
+
+        Ok(state.into_inner())
+    }
+
+    /// Restore the system from a state created by `Nes::save_state`
+    ///
+    /// The NES is left unchanged when an error is returned
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let current = self.save_state()?;
+
+        // Restoring a state that was just saved cannot fail
+        self.try_load_state(data).inspect_err(|_| self.try_load_state(&current).unwrap())
+    }
+
+    fn try_load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data);
+
+        let mut magic = [0u8; 4];
+        state.read_bytes(&mut magic).map_err(|_| StateError::InvalidMagic)?;
+        if magic != STATE_MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+
+        let version = state.read_u32()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        // Timing cannot change when restoring a state
+        if state.read_u8()? != self.get_region().to_u8() {
+            return Err(StateError::InvalidData);
+        }
+
+        self.sequencer.load_state(&mut state)?;
+        self.cpu_cycles = state.read_u64()?;
+        self.cpu.borrow_mut().load_state(&mut state)?;
+        self.ppu.borrow_mut().load_state(&mut state)?;
+        self.apu.borrow_mut().load_state(&mut state)?;
+        self.joy.borrow_mut().load_state(&mut state)?;
+        if let Some(ref mapper) = self.mapper {
+            mapper.borrow_mut().load_state(&mut state)?;
+        }
+
+        match state.remaining() {
+            0 => Ok(()),
+            n => Err(StateError::TrailingData(n)),
+        }
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Rewind
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Restore the system to the state it was in `frames` frames ago. Returns the number of frames actually rewound
+    ///
+    /// Snapshots are only taken every few frames (see `Nes::rewind_buffer`), so the system is restored to the nearest
+    /// snapshot at or before the requested frame. If there is not enough history, the oldest snapshot is used
+    /// ```
+    /// # use nescore::Nes;
+    /// # let mut nes = Nes::default().rewind_buffer(300, 2);
+    /// // Go back one second
+    /// let rewound = nes.rewind(60);
+    /// ```
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let target = self.frame.saturating_sub(frames as u64);
+
+        let (frame, state) = match self.rewind.as_ref().and_then(|rewind| rewind.find(target)) {
+            Some(snapshot) => snapshot,
+            None => return 0,
+        };
+
+        // The system and the history are left unchanged if the snapshot cannot be restored
+        if self.load_state(&state).is_err() {
+            return 0;
+        }
+
+        if let Some(ref mut rewind) = self.rewind {
+            rewind.truncate(frame, state);
+        }
+
+        let rewound = self.frame - frame;
+        self.frame = frame;
+        self.frame_progress = 0;
+
+        rewound as usize
+    }
+
+    /// Capture a snapshot for the rewind buffer, if one is due
+    fn capture_rewind(&mut self) {
+        let frame = self.frame;
+        if self.rewind.as_ref().is_some_and(|rewind| rewind.should_capture(frame)) {
+            if let Ok(state) = self.save_state() {
+                if let Some(ref mut rewind) = self.rewind {
+                    rewind.push(frame, state);
+                }
+            }
+        }
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Breakpoints
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Add a breakpoint, returning an id used to remove it
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge, Breakpoint};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// let mut nes = Nes::from(cart);
+    /// // Halt when the game writes to PPUADDR
+    /// nes.add_breakpoint(Breakpoint::Write(0x2006, 0x2006));
+    /// nes.emulate_frame();
+    ///
+    /// if let Some(hit) = nes.breakpoint_hit() {
+    ///     println!("${:04X}: ${:04X} = {:02X?}", hit.pc, hit.addr, hit.value);
+    /// }
+    /// ```
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.debugger.borrow_mut().add(breakpoint)
+    }
+
+    /// Remove a breakpoint. Returns false if the id does not exist
+    pub fn remove_breakpoint(&mut self, id: usize) -> bool {
+        self.debugger.borrow_mut().remove(id)
+    }
+
+    /// Remove all breakpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.borrow_mut().clear();
+    }
+
+    /// List breakpoints and their ids
+    pub fn breakpoints(&self) -> Vec<(usize, Breakpoint)> {
+        self.debugger.borrow().breakpoints().to_vec()
+    }
+
+    /// The breakpoint hit during the last call to `emulate_frame` or `run_until_bounded`, if any
+    pub fn breakpoint_hit(&self) -> Option<BreakpointHit> {
+        self.hit
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // PPU Hooks
+    //------------------------------------------------------------------------------------------------------------------
+
+    /// Run a callback after the PPU runs a dot, returning an id used to remove it
+    /// ```no_run
+    /// # use nescore::{Nes, Cartridge, PpuHook};
+    /// # use std::{rc::Rc, cell::RefCell};
+    /// # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+    /// let mut nes = Nes::from(cart);
+    ///
+    /// // Capture the fine x scroll at the end of each scanline
+    /// let scroll = Rc::new(RefCell::new(vec![]));
+    /// let s = scroll.clone();
+    /// nes.add_ppu_hook(PpuHook::Dot(256), move |event| s.borrow_mut().push((event.scanline, event.registers.x)));
+    ///
+    /// nes.emulate_frame();
+    /// ```
+    pub fn add_ppu_hook<F: FnMut(&PpuHookEvent) + 'static>(&mut self, hook: PpuHook, callback: F) -> usize {
+        self.hooks.add(hook, Box::new(callback))
+    }
+
+    /// Remove a PPU hook. Returns false if the id does not exist
+    pub fn remove_ppu_hook(&mut self, id: usize) -> bool {
+        self.hooks.remove(id)
+    }
+
+    /// Remove all PPU hooks
+    pub fn clear_ppu_hooks(&mut self) {
+        self.hooks.clear();
+    }
+
+    //------------------------------------------------------------------------------------------------------------------
+    // Event Logging
+    //------------------------------------------------------------------------------------------------------------------
+    #[cfg(feature="events")]
+    pub fn cpu_event_channel(&mut self) -> Receiver<crate::events::CpuEvent> {
+        let (tx, rx) = channel::<crate::events::CpuEvent>();
+        self.cpu.borrow_mut().set_event_sender(tx);
+
+        rx
+    }
+
+    #[cfg(feature="events")]
+    pub fn apu_event_channel(&mut self) -> Receiver<crate::events::ApuEvent> {
+        let (tx, rx) = channel::<crate::events::ApuEvent>();
         self.apu.borrow_mut().set_event_sender(tx);
 
         rx
@@ -346,9 +1098,52 @@ impl Nes {
         self.cpu.borrow().get_pc()
     }
 
+    /// Get the console region in use
+    pub fn get_region(&self) -> Region {
+        self.forced_region.unwrap_or(self.cart_region)
+    }
+
+    /// Get the number of CPU cycles run
+    pub fn get_cpu_cycles(&self) -> u64 {
+        self.cpu_cycles
+    }
+
+    /// Get the CPU's registers
+    pub fn get_cpu_registers(&self) -> CpuRegisters {
+        self.cpu.borrow().registers()
+    }
+
+    /// Set the CPU's registers
+    pub fn set_cpu_registers(&mut self, regs: CpuRegisters) {
+        self.cpu.borrow_mut().set_registers(regs);
+    }
+
+    /// Get the PPU's registers
+    pub fn get_ppu_registers(&self) -> PpuRegisters {
+        self.ppu.borrow().registers()
+    }
+
+    /// Get the scanline and dot the PPU will process next
+    pub fn get_ppu_position(&self) -> (usize, usize) {
+        self.ppu.borrow().position()
+    }
+
     /// Read the byte, at the specified address, from CPU's internal RAM
     pub fn read_cpu_ram(&self, addr: u16) -> u8 {
-        self.cpu.borrow().read_ram(addr)
+        // Inspecting memory does not trigger watchpoints
+        self.debugger.borrow_mut().set_muted(true);
+        let value = self.cpu.borrow().read_ram(addr);
+        self.debugger.borrow_mut().set_muted(false);
+
+        value
+    }
+
+    /// Write to the CPU's memory map
+    pub fn write_cpu_ram(&mut self, addr: u16, value: u8) {
+        // Modifying memory does not trigger watchpoints
+        self.debugger.borrow_mut().set_muted(true);
+        self.cpu.borrow_mut().write_ram(addr, value);
+        self.debugger.borrow_mut().set_muted(false);
     }
 
     /// Read directly from VRAM
@@ -356,12 +1151,18 @@ impl Nes {
         self.ppu.borrow().read_vram(addr)
     }
 
+    /// Read directly from OAM
+    pub fn read_oam(&self, addr: u8) -> u8 {
+        self.ppu.borrow().read_oam(addr)
+    }
+
     /// Read a tile from the current nametable
     pub fn read_tile(&self, nametable: u16, x: usize, y: usize) -> u8 {
         self.ppu.borrow().read_tile(nametable, x, y)
     }
 }
 
+/// Panics if the cartridge is not supported. See `Nes::try_with_cart`
 impl From<Cartridge> for Nes {
     fn from(cart: Cartridge) -> Self {
         Nes::default().with_cart(cart)
@@ -370,5 +1171,711 @@ impl From<Cartridge> for Nes {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::ppu::BuiltinPalette;
+
+    #[test]
+    fn try_insert_unsupported_mapper() {
+        // Mapper 5 (MMC5)
+        let cart = init_cart(0x50, 0x00, 0x00);
 
+        let mut nes = Nes::default();
+        let result = nes.try_insert(cart);
+
+        assert!(matches!(result, Err(CartridgeError::UnsupportedMapper(5))));
+        assert!(nes.mapper.is_none());
+    }
+
+    #[test]
+    fn try_insert_unsupported_submapper() {
+        // NES 2.0 header, mapper 4 submapper 1 (MMC6)
+        let cart = init_cart(0x40, 0x08, 0x10);
+
+        let result = Nes::default().try_with_cart(cart);
+
+        assert!(matches!(result, Err(CartridgeError::UnsupportedSubmapper(4, 1))));
+    }
+
+    #[test]
+    fn try_insert_inconsistent_size() {
+        let header = init_header(0x00, 0x00, 0x00);
+        let info = crate::cart::CartridgeInfo::from(&header[..]).unwrap();
+        let cart = Cartridge::from_parts(info, vec![0; kb!(8)], vec![0; kb!(8)], vec![]);
+
+        let result = Nes::default().try_with_cart(cart);
+
+        assert!(matches!(result, Err(CartridgeError::PrgRomSize(_, _))));
+    }
+
+    #[test]
+    fn try_insert() {
+        let cart = init_cart(0x00, 0x00, 0x00);
+
+        let mut nes = Nes::default();
+
+        assert!(nes.try_insert(cart).is_ok());
+        assert!(nes.mapper.is_some());
+    }
+
+    #[test]
+    fn save_state_no_cartridge() {
+        let mut nes = Nes::default();
+
+        assert_eq!(nes.save_state(), Err(StateError::NoCartridge));
+        assert_eq!(nes.load_state(&[]), Err(StateError::NoCartridge));
+    }
+
+    #[test]
+    fn load_state_invalid_header() {
+        let mut nes = Nes::default().with_cart(init_cart(0x00, 0x00, 0x00));
+        let mut state = nes.save_state().unwrap();
+
+        state[0] = b'X';
+        assert_eq!(nes.load_state(&state), Err(StateError::InvalidMagic));
+
+        state[0] = STATE_MAGIC[0];
+        state[4] = 0xFF;
+        assert!(matches!(nes.load_state(&state), Err(StateError::UnsupportedVersion(_))));
+    }
+
+    #[test]
+    fn load_state_bad_length() {
+        let mut nes = Nes::default().with_cart(init_cart(0x00, 0x00, 0x00));
+        let state = nes.save_state().unwrap();
+
+        assert_eq!(nes.load_state(&state[..state.len() - 1]), Err(StateError::UnexpectedEnd));
+
+        let padded = [&state[..], &[0u8][..]].concat();
+        assert_eq!(nes.load_state(&padded), Err(StateError::TrailingData(1)));
+    }
+
+    #[test]
+    fn failed_load_state_leaves_nes_unchanged() {
+        let mut nes = Nes::default().with_cart(init_cart(0x00, 0x00, 0x00)).entry(0xC000);
+        let before = nes.save_state().unwrap();
+
+        // Locate the CPU in the save state. The program counter follows A, X and Y
+        let mut cpu_state = StateWriter::default();
+        nes.cpu.borrow().save_state(&mut cpu_state);
+        let cpu_state = cpu_state.into_inner();
+        let cpu_offset = before.windows(cpu_state.len()).position(|w| w == &cpu_state[..]).unwrap();
+
+        // Change the program counter to $8000
+        let mut state = before.clone();
+        state[cpu_offset + 4] = 0x80;
+
+        let mut other = Nes::default().with_cart(init_cart(0x00, 0x00, 0x00));
+        other.load_state(&state).unwrap();
+        assert_eq!(other.get_program_counter(), 0x8000);
+
+        // Truncate the state so it fails after the CPU has been loaded
+        assert!(nes.load_state(&state[..state.len() - 1]).is_err());
+
+        assert_eq!(nes.save_state().unwrap(), before);
+        assert_eq!(nes.get_program_counter(), 0xC000);
+    }
+
+    #[test]
+    fn corrupt_bank_load_state_fails() {
+        // UNROM
+        let mut nes = Nes::default().with_cart(init_cart(0x20, 0x00, 0x00)).entry(0xC000);
+        let before = nes.save_state().unwrap();
+
+        // The mapper is saved last and the UNROM bank selection is its last value. Select a bank that does not exist
+        let mut state = before.clone();
+        let len = state.len();
+        state[len - 8..].copy_from_slice(&5u64.to_le_bytes());
+
+        assert_eq!(nes.load_state(&state), Err(StateError::InvalidData));
+        assert_eq!(nes.save_state().unwrap(), before);
+        assert_eq!(nes.read_cpu_ram(0x8000), 0x00);
+    }
+
+    #[test]
+    fn failed_rewind_keeps_history() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM)).rewind_buffer(10, 1);
+        for _ in 0..3 {
+            nes.emulate_frame();
+        }
+
+        // Make the newest snapshot unusable
+        let frame = nes.frame;
+        nes.rewind.as_mut().unwrap().push(frame, vec![0u8; 4]);
+        let before = nes.save_state().unwrap();
+        let len = nes.rewind.as_ref().unwrap().len();
+
+        assert_eq!(nes.rewind(0), 0);
+        assert_eq!(nes.save_state().unwrap(), before);
+        assert_eq!(nes.rewind.as_ref().unwrap().len(), len);
+    }
+
+    #[test]
+    fn step_instruction() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        // Run through reset
+        nes.step_instruction();
+        assert_eq!(nes.get_program_counter(), 0x8000);
+
+        // LDX #$00
+        assert_eq!(nes.step_instruction(), 2);
+        assert_eq!(nes.get_program_counter(), 0x8002);
+        // INX
+        assert_eq!(nes.step_instruction(), 2);
+        assert_eq!(nes.get_program_counter(), 0x8003);
+        // JMP $8002
+        assert_eq!(nes.step_instruction(), 3);
+        assert_eq!(nes.get_program_counter(), 0x8002);
+    }
+
+    #[test]
+    fn step_instruction_matches_run_for_cycles() {
+        let mut a = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        let mut b = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+
+        let mut cycles = 0;
+        for _ in 0..100 {
+            cycles += a.step_instruction();
+        }
+
+        b.run_for_cycles(cycles as u64);
+
+        assert_eq!(a.save_state().unwrap(), b.save_state().unwrap());
+    }
+
+    #[test]
+    fn cpu_registers() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        nes.step_instruction();
+        nes.step_instruction();
+        nes.step_instruction();
+
+        let regs = nes.get_cpu_registers();
+        assert_eq!(regs.x, 0x01);
+        assert_eq!(regs.pc, 0x8003);
+        assert_eq!(regs.sp, 0xFD);
+    }
+
+    #[test]
+    fn ppu_registers() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.run_until_bounded(0x800F, Budget::Frames(1));
+
+        let regs = nes.get_ppu_registers();
+        // Incremented by the write to PPUDATA
+        assert_eq!(regs.v, 0x2001);
+        assert!(!regs.w);
+    }
+
+    #[test]
+    fn oam_dma_honours_oam_addr() {
+        let mut nes = Nes::default().with_cart(init_program_cart(DMA_PROGRAM));
+        nes.run_until_bounded(0x8019, Budget::Frames(1));
+
+        // The transfer starts at OAMADDR and wraps around
+        assert_eq!(nes.read_oam(0x04), 0xAB);
+        assert_eq!(nes.read_oam(0x05), 0xCD);
+        assert_eq!(nes.read_oam(0x03), 0xEF);
+        assert_eq!(nes.get_ppu_registers().oam_addr, 0x04);
+    }
+
+    #[test]
+    fn oam_dma_stalls_cpu() {
+        // STA $4014 takes 4 cycles, followed by 513 or 514 DMA cycles depending on alignment
+        let mut a = Nes::default().with_cart(init_program_cart(DMA_PROGRAM));
+        a.run_until_bounded(0x8016, Budget::Frames(1));
+        let a_cycles = a.step_instruction();
+
+        // Delay the write by one cycle by replacing LDA #$02 with LDA $02
+        let mut program = DMA_PROGRAM.to_vec();
+        program[0x14] = 0xA5;
+        let mut b = Nes::default().with_cart(init_program_cart(&program));
+        b.run_until_bounded(0x8016, Budget::Frames(1));
+        let b_cycles = b.step_instruction();
+
+        let mut cycles = [a_cycles, b_cycles];
+        cycles.sort_unstable();
+        assert_eq!(cycles, [4 + 513, 4 + 514]);
+    }
+
+    #[test]
+    fn dmc_fetch_during_oam_dma_stalls_cpu() {
+        // The second sample byte is fetched part way through the OAM transfer
+        let mut a = Nes::default().with_cart(init_program_cart(DMC_OAM_DMA_PROGRAM));
+        a.run_until_bounded(0x8016, Budget::Frames(1));
+        let a_cycles = a.step_instruction();
+
+        // Delay the write by one cycle by replacing LDA #$02 with LDA $02
+        let mut program = DMC_OAM_DMA_PROGRAM.to_vec();
+        program[0x14] = 0xA5;
+        let mut b = Nes::default().with_cart(init_program_cart(&program));
+        b.run_until_bounded(0x8016, Budget::Frames(1));
+        let b_cycles = b.step_instruction();
+
+        // The DMC read takes a get cycle from the transfer, which then needs another cycle to realign
+        let mut cycles = [a_cycles, b_cycles];
+        cycles.sort_unstable();
+        assert_eq!(cycles, [4 + 513 + 2, 4 + 514 + 2]);
+    }
+
+    #[test]
+    fn dmc_dma_stalls_cpu() {
+        let mut nes = Nes::default().with_cart(init_program_cart(DMC_PROGRAM));
+        nes.run_until_bounded(0x8012, Budget::Frames(1));
+
+        // The sample byte is fetched before the CPU executes JMP $8012
+        let cycles = nes.step_instruction() + nes.step_instruction();
+        assert!(cycles == 6 + 3 || cycles == 6 + 4, "{} cycles", cycles);
+
+        // The one byte sample has been read and the IRQ flag is set
+        let status = nes.read_cpu_ram(0x4015);
+        assert_eq!(status & 0x90, 0x80);
+    }
+
+    #[test]
+    fn step_scanline() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        nes.step_cycle();
+
+        // Starts on the pre-render scanline
+        assert_eq!(nes.get_ppu_position().0, 261);
+
+        nes.step_scanline();
+        assert_eq!(nes.get_ppu_position(), (0, 0));
+
+        nes.step_scanline();
+        assert_eq!(nes.get_ppu_position(), (1, 0));
+    }
+
+    #[test]
+    fn stepping_without_cartridge() {
+        let mut nes = Nes::default();
+
+        assert_eq!(nes.step_instruction(), 0);
+        nes.step_cycle();
+        nes.step_scanline();
+        nes.run_for_cycles(100);
+
+        assert_eq!(nes.run_until_bounded(0x8000, Budget::Cycles(100)), StopReason::NoCartridge);
+    }
+
+    #[test]
+    fn run_until_instruction_boundary() {
+        let program = [
+            0xAD, 0x00, 0x00, // $8000 LDA $0000
+            0xEA,             // $8003 NOP
+            0x4C, 0x03, 0x80, // $8004 JMP $8003
+        ];
+
+        let mut nes = Nes::default().with_cart(init_program_cart(&program));
+
+        // PC is $8003 after the LDA operand fetch, before the read cycle
+        nes.run_until(0x8003);
+        assert!(nes.cpu.borrow().at_instruction_boundary());
+
+        // NOP
+        assert_eq!(nes.step_instruction(), 2);
+        assert_eq!(nes.get_program_counter(), 0x8004);
+    }
+
+    #[test]
+    fn run_until_bounded_address() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+
+        assert_eq!(nes.run_until_bounded(0x8003, Budget::Cycles(100)), StopReason::Address(0x8003));
+        assert_eq!(nes.get_program_counter(), 0x8003);
+    }
+
+    #[test]
+    fn run_until_bounded_budget() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        // Never executed
+        assert_eq!(nes.run_until_bounded(0x9000, Budget::Cycles(100)), StopReason::BudgetExhausted);
+
+        let position = nes.get_ppu_position();
+        assert_eq!(nes.run_until_bounded(0x9000, Budget::Frames(1)), StopReason::BudgetExhausted);
+        assert_eq!(nes.get_ppu_position(), position);
+    }
+
+    #[test]
+    fn jam_halts_cpu() {
+        // $8000 LDX #$00, $8002 JAM
+        let mut nes = Nes::default().with_cart(init_program_cart(&[0xA2, 0x00, 0x02]));
+
+        assert_eq!(nes.run_until_bounded(0x9000, Budget::Frames(1)), StopReason::Jammed(0x8002));
+        assert_eq!(nes.jammed(), Some(0x8002));
+
+        // Stepping returns while the CPU is halted
+        assert_eq!(nes.step_instruction(), 1);
+        assert_eq!(nes.jammed(), Some(0x8002));
+
+        // Only a reset recovers
+        nes.reset();
+        assert_eq!(nes.run_until_bounded(0x8002, Budget::Frames(1)), StopReason::Address(0x8002));
+    }
+
+    #[test]
+    fn reset() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.run_until_bounded(0x8011, Budget::Frames(1));
+
+        nes.reset();
+        // Run through reset
+        let cycles = nes.get_cpu_cycles();
+        nes.step_instruction();
+
+        // The reset sequence takes 7 cycles
+        assert_eq!(nes.get_cpu_cycles() - cycles, 7);
+
+        let regs = nes.get_cpu_registers();
+        assert_eq!(regs.pc, 0x8000);
+        assert_eq!(regs.sp, 0xFA);
+        assert!(bit_is_set!(regs.p, 2));
+
+        // RAM and VRAM are untouched
+        assert_eq!(nes.read_cpu_ram(0x0010), 0x01);
+        assert_eq!(nes.read_ppu_memory(0x2000), 0x01);
+    }
+
+    #[test]
+    fn power_cycle() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.run_until_bounded(0x8011, Budget::Frames(1));
+
+        nes.power_cycle();
+
+        assert_eq!(nes.get_cpu_cycles(), 0);
+        assert_eq!(nes.read_cpu_ram(0x0010), 0x00);
+        assert_eq!(nes.read_ppu_memory(0x2000), 0x00);
+
+        assert_eq!(nes.run_until_bounded(0x8011, Budget::Frames(1)), StopReason::Address(0x8011));
+        assert_eq!(nes.get_cpu_registers().sp, 0xFD);
+        assert_eq!(nes.read_cpu_ram(0x0010), 0x01);
+    }
+
+    #[test]
+    fn palette_override() {
+        let mut nes = Nes::default().palette(Palette::from(BuiltinPalette::Ppu2C03));
+        assert!(nes.ppu.borrow().palette() == &Palette::from(BuiltinPalette::Ppu2C03));
+
+        // The palette is kept when the region changes
+        nes.set_region(Some(Region::Pal));
+        assert!(nes.ppu.borrow().palette() == &Palette::from(BuiltinPalette::Ppu2C03));
+
+        nes.set_palette(None);
+        assert!(nes.ppu.borrow().palette() == &Palette::from(BuiltinPalette::Ppu2C07));
+    }
+
+    #[test]
+    fn frames_end_at_prerender() {
+        let program = [
+            0xA9, 0x08, 0x8D, 0x01, 0x20, // LDA #$08; STA $2001 (Enable background)
+            0x4C, 0x05, 0x80,             // JMP $8005
+        ];
+
+        let mut nes = Nes::default().with_cart(init_program_cart(&program));
+
+        // Odd frames are one dot shorter with rendering enabled
+        for _ in 0..5 {
+            nes.emulate_frame();
+            assert!(nes.at_frame_start());
+        }
+    }
+
+    #[test]
+    fn ppu_hooks() {
+        let program = [
+            0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80; STA $2000 (Enable NMI)
+            0x4C, 0x05, 0x80,             // JMP $8005
+        ];
+
+        let mut nes = Nes::default().with_cart(init_program_cart(&program));
+
+        let events = Rc::new(RefCell::new(vec![]));
+
+        let hooks = [PpuHook::VBlank, PpuHook::PreRender, PpuHook::Dot(0), PpuHook::Position(100, 5)];
+        let ids = hooks.iter().map(|hook| {
+            let events = events.clone();
+            nes.add_ppu_hook(*hook, move |event| events.borrow_mut().push(*event))
+        })
+        .collect::<Vec<_>>();
+
+        nes.emulate_frame();
+
+        let count = |id: usize| events.borrow().iter().filter(|event| event.id == id).count();
+        assert_eq!(count(ids[0]), 1);
+        assert_eq!(count(ids[1]), 1);
+        assert_eq!(count(ids[2]), 262);
+        assert_eq!(count(ids[3]), 1);
+
+        let vblank = events.borrow().iter().find(|event| event.id == ids[0]).copied().unwrap();
+        assert_eq!((vblank.scanline, vblank.dot), (241, 1));
+        assert_eq!(vblank.registers.ctrl, 0x80);
+
+        assert!(nes.remove_ppu_hook(ids[2]));
+        nes.clear_ppu_hooks();
+        events.borrow_mut().clear();
+
+        nes.emulate_frame();
+        assert!(events.borrow().is_empty());
+    }
+
+    #[test]
+    fn indexed_pixel_format() {
+        let program = [
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F; STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00; STA $2006
+            0xA9, 0x16, 0x8D, 0x07, 0x20, // LDA #$16; STA $2007 (Backdrop color)
+            0xA9, 0x20, 0x8D, 0x01, 0x20, // LDA #$20; STA $2001 (Red emphasis)
+            0x4C, 0x14, 0x80,             // JMP $8014
+        ];
+
+        let mut indexed = Nes::default().pixel_format(PixelFormat::Indexed).with_cart(init_program_cart(&program));
+        let mut rgb = Nes::default().with_cart(init_program_cart(&program));
+
+        for _ in 0..2 {
+            indexed.emulate_frame();
+            rgb.emulate_frame();
+        }
+
+        let framebuffer = indexed.framebuffer();
+        assert_eq!(framebuffer.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT * 2);
+        assert_eq!(u16::from_le_bytes([framebuffer[0], framebuffer[1]]), 0x56);
+
+        let (r, g, b) = Palette::default().color(0x01, 0x16);
+        assert_eq!(rgb.framebuffer()[..3], [r, g, b]);
+    }
+
+    #[test]
+    fn region_from_cartridge() {
+        // NES 2.0 header, Dendy timing
+        let header = init_header(0x00, 0x08, 0x00);
+        let mut rom = [&header[..], &[0u8; kb!(24)][..]].concat();
+        rom[12] = 0x03;
+
+        let mut nes = Nes::default().with_cart(Cartridge::from(rom).unwrap());
+        assert_eq!(nes.get_region(), Region::Dendy);
+
+        nes.set_region(Some(Region::Pal));
+        assert_eq!(nes.get_region(), Region::Pal);
+
+        nes.set_region(None);
+        assert_eq!(nes.get_region(), Region::Dendy);
+    }
+
+    #[test]
+    fn region_timing() {
+        let expected = [(Region::Ntsc, 59562), (Region::Pal, 66495), (Region::Dendy, 70928)];
+
+        for &(region, cycles) in expected.iter() {
+            let mut nes = Nes::default().region(region).with_cart(init_program_cart(LOOP_PROGRAM));
+            nes.emulate_frame();
+            nes.emulate_frame();
+
+            assert_eq!(nes.get_cpu_cycles(), cycles, "{}", region);
+        }
+    }
+
+    #[test]
+    fn load_state_region_mismatch() {
+        let mut nes = Nes::default().with_cart(init_program_cart(LOOP_PROGRAM));
+        let state = nes.save_state().unwrap();
+
+        nes.set_region(Some(Region::Pal));
+        assert_eq!(nes.load_state(&state), Err(StateError::InvalidData));
+    }
+
+    #[test]
+    fn execute_breakpoint() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        let id = nes.add_breakpoint(Breakpoint::Execute(0x800F));
+
+        nes.emulate_frame();
+
+        let hit = nes.breakpoint_hit().unwrap();
+        assert_eq!(hit.id, id);
+        assert_eq!(hit.pc, 0x800F);
+        assert_eq!(hit.value, None);
+        // Halted before the instruction is executed
+        assert_eq!(nes.get_program_counter(), 0x800F);
+        assert!(nes.cpu.borrow().at_instruction_boundary());
+        assert_eq!(nes.read_cpu_ram(0x0010), 0x00);
+
+        // Resuming executes the instruction
+        nes.clear_breakpoints();
+        nes.step_instruction();
+        assert_eq!(nes.read_cpu_ram(0x0010), 0x01);
+    }
+
+    #[test]
+    fn breakpoint_resumes_frame() {
+        let mut a = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        let mut b = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+
+        b.add_breakpoint(Breakpoint::Execute(0x8011));
+
+        a.emulate_frame();
+        b.emulate_frame();
+        assert!(b.breakpoint_hit().is_some());
+
+        b.clear_breakpoints();
+        b.emulate_frame();
+        assert_eq!(b.breakpoint_hit(), None);
+
+        // The remainder of the frame was run
+        assert_eq!(a.save_state().unwrap(), b.save_state().unwrap());
+        assert_eq!(a.frame, b.frame);
+    }
+
+    #[test]
+    fn write_watchpoint() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.add_breakpoint(Breakpoint::Write(0x0000, 0x07FF));
+
+        let reason = nes.run_until_bounded(0x9000, Budget::Frames(1));
+
+        match reason {
+            StopReason::Breakpoint(hit) => {
+                assert_eq!(hit.pc, 0x800F);
+                assert_eq!(hit.addr, 0x0010);
+                assert_eq!(hit.value, Some(0x01));
+            },
+            _ => panic!("Unexpected stop reason: {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn read_watchpoint_ignores_inspection() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.add_breakpoint(Breakpoint::Read(0x0010, 0x0010));
+
+        nes.read_cpu_ram(0x0010);
+        nes.step_instruction();
+
+        assert_eq!(nes.breakpoint_hit(), None);
+    }
+
+    #[test]
+    fn remove_breakpoint() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        let id = nes.add_breakpoint(Breakpoint::Execute(0x8011));
+
+        assert!(nes.remove_breakpoint(id));
+        assert!(nes.breakpoints().is_empty());
+
+        nes.emulate_frame();
+        assert_eq!(nes.breakpoint_hit(), None);
+    }
+
+    #[test]
+    fn vram_watchpoint() {
+        let mut nes = Nes::default().with_cart(init_program_cart(PPU_PROGRAM));
+        nes.add_breakpoint(Breakpoint::VramWrite(0x2000, 0x23FF));
+
+        nes.emulate_frame();
+
+        let hit = nes.breakpoint_hit().unwrap();
+        assert_eq!(hit.pc, 0x800C);
+        assert_eq!(hit.addr, 0x2000);
+        assert_eq!(hit.value, Some(0x01));
+    }
+
+    /// LDX #$00; loop: INX; JMP loop
+    const LOOP_PROGRAM: &[u8] = &[0xA2, 0x00, 0xE8, 0x4C, 0x02, 0x80];
+
+    /// Write $01 to VRAM $2000 and RAM $0010, then loop
+    const PPU_PROGRAM: &[u8] = &[
+        0xA9, 0x20,       // $8000 LDA #$20
+        0x8D, 0x06, 0x20, // $8002 STA $2006
+        0xA9, 0x00,       // $8005 LDA #$00
+        0x8D, 0x06, 0x20, // $8007 STA $2006
+        0xA9, 0x01,       // $800A LDA #$01
+        0x8D, 0x07, 0x20, // $800C STA $2007
+        0x85, 0x10,       // $800F STA $10
+        0x4C, 0x11, 0x80, // $8011 JMP $8011
+    ];
+
+    // Play a one byte sample with the DMC IRQ enabled
+    const DMC_PROGRAM: &[u8] = &[
+        0xA9, 0x8F,       // $8000 LDA #$8F
+        0x8D, 0x10, 0x40, // $8002 STA $4010
+        0xA9, 0x00,       // $8005 LDA #$00
+        0x8D, 0x12, 0x40, // $8007 STA $4012
+        0x8D, 0x13, 0x40, // $800A STA $4013
+        0xA9, 0x10,       // $800D LDA #$10
+        0x8D, 0x15, 0x40, // $800F STA $4015
+        0x4C, 0x12, 0x80, // $8012 JMP $8012
+    ];
+
+    // Play a 17 byte sample at the highest rate, then start OAM DMA
+    const DMC_OAM_DMA_PROGRAM: &[u8] = &[
+        0xA9, 0x0F,       // $8000 LDA #$0F
+        0x8D, 0x10, 0x40, // $8002 STA $4010
+        0xA9, 0x00,       // $8005 LDA #$00
+        0x8D, 0x12, 0x40, // $8007 STA $4012
+        0xA9, 0x01,       // $800A LDA #$01
+        0x8D, 0x13, 0x40, // $800C STA $4013
+        0xA9, 0x10,       // $800F LDA #$10
+        0x8D, 0x15, 0x40, // $8011 STA $4015
+        0xA9, 0x02,       // $8014 LDA #$02
+        0x8D, 0x14, 0x40, // $8016 STA $4014
+        0x4C, 0x19, 0x80, // $8019 JMP $8019
+    ];
+
+    // Fill part of page 2 and copy it to OAM, starting at OAMADDR $04
+    const DMA_PROGRAM: &[u8] = &[
+        0xA9, 0x04,       // $8000 LDA #$04
+        0x8D, 0x03, 0x20, // $8002 STA $2003
+        0xA9, 0xAB,       // $8005 LDA #$AB
+        0x8D, 0x00, 0x02, // $8007 STA $0200
+        0xA9, 0xCD,       // $800A LDA #$CD
+        0x8D, 0x01, 0x02, // $800C STA $0201
+        0xA9, 0xEF,       // $800F LDA #$EF
+        0x8D, 0xFF, 0x02, // $8011 STA $02FF
+        0xA9, 0x02,       // $8014 LDA #$02
+        0x8D, 0x14, 0x40, // $8016 STA $4014
+        0x4C, 0x19, 0x80, // $8019 JMP $8019
+    ];
+
+    /// Cartridge that runs the program from $8000
+    fn init_program_cart(program: &[u8]) -> Cartridge {
+        let header = init_header(0x00, 0x00, 0x00);
+        let mut prg_rom = [0u8; kb!(16)];
+        prg_rom[..program.len()].copy_from_slice(program);
+        // Reset vector
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0x80;
+        let chr_rom = [0u8; kb!(8)];
+
+        let rom = [&header[..], &prg_rom[..], &chr_rom[..]].concat();
+
+        Cartridge::from(rom).unwrap()
+    }
+
+    fn init_cart(flag6: u8, flag7: u8, flag8: u8) -> Cartridge {
+        let header = init_header(flag6, flag7, flag8);
+        let prg_rom = [0u8; kb!(16)];
+        let chr_rom = [0u8; kb!(8)];
+
+        let rom = [&header[..], &prg_rom[..], &chr_rom[..]].concat();
+
+        Cartridge::from(rom).unwrap()
+    }
+
+    fn init_header(flag6: u8, flag7: u8, flag8: u8) -> [u8; 16] {
+        [
+            0x4E, 0x45, 0x53, 0x1A, // NES<EOF>
+            0x01,                   // PRG ROM
+            0x01,                   // CHR ROM
+            flag6,                  // Flag 6
+            flag7,                  // Flag 7
+            flag8,                  // Flag 8
+            0x00,                   // Flag 9
+            0x00,                   // Flag 10
+            0x00,                   // Flag 11
+            0x00,                   // Flag 12
+            0x00,                   // Flag 13
+            0x00,                   // Flag 14
+            0x00,                   // Flag 15
+        ]
+    }
 }
@@ -6,6 +6,7 @@
 //
 
 use crate::common::Clockable;
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
 
 // http://wiki.nesdev.com/w/index.php/PPU_rendering
 
@@ -46,6 +47,20 @@ impl TileRegister {
     }
 }
 
+impl Snapshot for TileRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.plane0);
+        state.write_u16(self.plane1);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.plane0 = state.read_u16()?;
+        self.plane1 = state.read_u16()?;
+
+        Ok(())
+    }
+}
+
 /// Representation of the two 8 bit shift registers used to hold pallette data for the ppu
 #[derive(Default)]
 pub struct PaletteRegister {
@@ -78,6 +93,22 @@ impl PaletteRegister {
     }
 }
 
+impl Snapshot for PaletteRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.r0);
+        state.write_u8(self.r1);
+        state.write_u8(self.latch);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.r0 = state.read_u8()?;
+        self.r1 = state.read_u8()?;
+        self.latch = state.read_u8()?;
+
+        Ok(())
+    }
+}
+
 /// Sprite shift registers
 #[derive(Default, Clone, Copy)]
 pub struct SpriteRegister {
@@ -135,6 +166,82 @@ impl SpriteRegister {
     }
 }
 
+impl Snapshot for SpriteRegister {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.x_counter);
+        state.write_bool(self.is_active);
+        state.write_u8(self.palette);
+        state.write_bool(self.priority);
+        state.write_u8(self.plane0);
+        state.write_u8(self.plane1);
+        state.write_u8(self.sprite_num);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.x_counter = state.read_u8()?;
+        self.is_active = state.read_bool()?;
+        self.palette = state.read_u8()?;
+        self.priority = state.read_bool()?;
+        self.plane0 = state.read_u8()?;
+        self.plane1 = state.read_u8()?;
+        self.sprite_num = state.read_u8()?;
+
+        Ok(())
+    }
+}
+
+/// PPU I/O data bus latch
+///
+/// Reading a write-only register returns the value left on the bus. Each bit decays to 0 when it has not been
+/// refreshed for a while
+#[derive(Default, Clone, Copy)]
+pub struct OpenBus {
+    value: u8,
+    age: [u8; 8], // Frames since each bit was last refreshed
+}
+
+impl OpenBus {
+    /// Drive the bits in `mask` onto the bus, refreshing them
+    pub fn drive(&mut self, value: u8, mask: u8) {
+        self.value = (self.value & !mask) | (value & mask);
+
+        for (bit, age) in self.age.iter_mut().enumerate() {
+            if bit_is_set!(mask, bit) {
+                *age = 0;
+            }
+        }
+    }
+
+    /// Advance one frame. Bits that have not been refreshed for `decay_frames` frames decay to 0
+    pub fn decay(&mut self, decay_frames: u8) {
+        for (bit, age) in self.age.iter_mut().enumerate() {
+            *age = age.saturating_add(1);
+
+            if *age >= decay_frames {
+                self.value &= !bv!(bit) as u8;
+            }
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        self.value
+    }
+}
+
+impl Snapshot for OpenBus {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.value);
+        state.write_bytes(&self.age);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.value = state.read_u8()?;
+        state.read_bytes(&mut self.age)?;
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,4 +306,37 @@ mod tests {
         assert_eq!(palette_reg.get_value(0), 0x03);
         assert_eq!(palette_reg.get_value(7), 0x03);
     }
+
+    #[test]
+    fn open_bus_drive() {
+        let mut bus = OpenBus::default();
+
+        bus.drive(0xFF, 0xFF);
+        assert_eq!(bus.value(), 0xFF);
+
+        // Only the masked bits are driven
+        bus.drive(0x00, 0xE0);
+        assert_eq!(bus.value(), 0x1F);
+    }
+
+    #[test]
+    fn open_bus_decay() {
+        let mut bus = OpenBus::default();
+        bus.drive(0xFF, 0xFF);
+
+        for _ in 0..9 {
+            bus.decay(10);
+        }
+        assert_eq!(bus.value(), 0xFF);
+
+        // Refresh the upper bits
+        bus.drive(0xF0, 0xF0);
+        bus.decay(10);
+        assert_eq!(bus.value(), 0xF0);
+
+        for _ in 0..9 {
+            bus.decay(10);
+        }
+        assert_eq!(bus.value(), 0x00);
+    }
 }
@@ -10,6 +10,9 @@ pub mod bus;
 mod regs;
 mod hw;
 mod sprite;
+mod palette;
+pub(crate) mod signal;
 
 // Public re-exports
-pub use ppu::{Ppu, Pixel, DISPLAY_HEIGHT, DISPLAY_WIDTH, CYCLES_PER_FRAME};
+pub use ppu::{Ppu, PpuRegisters, Pixel, IndexedPixel, DISPLAY_HEIGHT, DISPLAY_WIDTH, CYCLES_PER_SCANLINE};
+pub use palette::{Palette, BuiltinPalette, NtscPaletteParams, PaletteError};
@@ -0,0 +1,369 @@
+//
+// ppu/palette.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 20 2021
+//
+
+use super::{Pixel, IndexedPixel};
+use super::signal::{self, EMPHASIS_ATTENUATION};
+use crate::region::Region;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Number of colors the PPU can output
+const NUM_COLORS: usize = 64;
+/// Number of emphasis bit combinations
+const NUM_EMPHASIS: usize = 8;
+/// Size of a palette covering every color and emphasis combination
+const PALETTE_SIZE: usize = NUM_COLORS * NUM_EMPHASIS * 3;
+
+const NTSC_PALETTE: [u8; PALETTE_SIZE] = *include_bytes!("ntscpalette.pal");
+
+/// RGB PPU palette. Each channel is a 3-bit level
+/// https://wiki.nesdev.com/w/index.php/PPU_palettes#2C03_and_2C05
+const RGB_PPU_LEVELS: [u16; NUM_COLORS] = [
+    0o333, 0o014, 0o006, 0o326, 0o403, 0o503, 0o510, 0o420, 0o320, 0o120, 0o031, 0o040, 0o022, 0o000, 0o000, 0o000,
+    0o555, 0o036, 0o027, 0o407, 0o507, 0o704, 0o700, 0o630, 0o430, 0o140, 0o040, 0o053, 0o044, 0o000, 0o000, 0o000,
+    0o777, 0o357, 0o447, 0o637, 0o707, 0o737, 0o740, 0o750, 0o660, 0o360, 0o070, 0o276, 0o077, 0o444, 0o000, 0o000,
+    0o777, 0o567, 0o657, 0o757, 0o747, 0o755, 0o764, 0o772, 0o773, 0o572, 0o473, 0o276, 0o467, 0o666, 0o000, 0o000,
+];
+
+/// Error loading a palette
+#[derive(Debug)]
+pub enum PaletteError {
+    ReadFail(io::Error),
+    InvalidSize(usize),
+    UnknownPalette(String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PaletteError::ReadFail(ref e) => write!(f, "Failed to read palette file: {}", e),
+            PaletteError::InvalidSize(size) =>
+                write!(f, "Palette must be 192 or 1536 bytes (64 or 512 colors), found {} bytes", size),
+            PaletteError::UnknownPalette(ref name) => write!(f, "Unknown palette: {}", name),
+        }
+    }
+}
+
+impl Error for PaletteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PaletteError::ReadFail(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Palettes of the PPU variants
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuiltinPalette {
+    /// NTSC PPU
+    Ppu2C02,
+    /// RGB PPU used in the VS. System and PlayChoice-10. Also used for the 2C05
+    Ppu2C03,
+    /// PAL PPU
+    Ppu2C07,
+}
+
+impl FromStr for BuiltinPalette {
+    type Err = PaletteError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "2c02" => Ok(BuiltinPalette::Ppu2C02),
+            "2c03" | "2c05" => Ok(BuiltinPalette::Ppu2C03),
+            "2c07" => Ok(BuiltinPalette::Ppu2C07),
+            _ => Err(PaletteError::UnknownPalette(s.to_string())),
+        }
+    }
+}
+
+/// Parameters for generating a palette from the NTSC signal the PPU outputs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscPaletteParams {
+    /// Hue rotation in degrees
+    pub hue: f64,
+    /// Saturation scale
+    pub saturation: f64,
+    /// Contrast scale
+    pub contrast: f64,
+    /// Brightness offset
+    pub brightness: f64,
+}
+
+impl Default for NtscPaletteParams {
+    fn default() -> Self {
+        NtscPaletteParams {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+        }
+    }
+}
+
+/// RGB colors for each of the PPU's 64 colors and 8 emphasis bit combinations
+///
+/// Entries are ordered by the raw value of the emphasis bits in PPUMASK
+#[derive(Clone, PartialEq)]
+pub struct Palette {
+    rgb: [u8; PALETTE_SIZE],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::from(BuiltinPalette::Ppu2C02)
+    }
+}
+
+impl From<BuiltinPalette> for Palette {
+    fn from(palette: BuiltinPalette) -> Self {
+        match palette {
+            BuiltinPalette::Ppu2C02 => Palette { rgb: NTSC_PALETTE },
+            BuiltinPalette::Ppu2C03 => helpers::rgb_ppu_palette(),
+            BuiltinPalette::Ppu2C07 => helpers::pal_palette(),
+        }
+    }
+}
+
+impl Palette {
+    /// Default palette of the region. The 2C07 and UA6538 swap the red and green emphasis bits
+    pub fn for_region(region: Region) -> Self {
+        if region.swaps_emphasis() {
+            Palette::from(BuiltinPalette::Ppu2C07)
+        }
+        else {
+            Palette::from(BuiltinPalette::Ppu2C02)
+        }
+    }
+
+    /// Load a palette from the contents of a .pal file
+    ///
+    /// 64 color files have the emphasis colors derived from the base colors
+    /// ```
+    /// # use nescore::Palette;
+    /// let palette = Palette::from_slice(&[0u8; 192]).unwrap();
+    /// ```
+    pub fn from_slice(data: &[u8]) -> Result<Self, PaletteError> {
+        match data.len() {
+            PALETTE_SIZE => {
+                let mut rgb = [0u8; PALETTE_SIZE];
+                rgb.copy_from_slice(data);
+
+                Ok(Palette { rgb })
+            },
+            n if n == NUM_COLORS * 3 => Ok(helpers::expand_emphasis(data)),
+            n => Err(PaletteError::InvalidSize(n)),
+        }
+    }
+
+    /// Load a palette from a .pal file
+    pub fn from_path(path: &str) -> Result<Self, PaletteError> {
+        let mut data = Vec::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut data))
+            .map_err(PaletteError::ReadFail)?;
+
+        Palette::from_slice(&data)
+    }
+
+    /// Generate a palette by decoding the NTSC signal of each color
+    ///
+    /// https://wiki.nesdev.com/w/index.php/NTSC_video
+    /// ```
+    /// # use nescore::{Palette, NtscPaletteParams};
+    /// let palette = Palette::generate(NtscPaletteParams {
+    ///     saturation: 1.2,
+    ///     ..NtscPaletteParams::default()
+    /// });
+    /// ```
+    pub fn generate(params: NtscPaletteParams) -> Self {
+        let mut rgb = [0u8; PALETTE_SIZE];
+
+        for (pixel, color) in rgb.chunks_mut(3).enumerate() {
+            color.copy_from_slice(&helpers::decode_ntsc(pixel, &params));
+        }
+
+        Palette { rgb }
+    }
+
+    /// RGB color for the PPU color and emphasis bits
+    pub fn color(&self, emphasis: usize, color: usize) -> Pixel {
+        let idx = ((emphasis * NUM_COLORS) + color) * 3;
+        (self.rgb[idx], self.rgb[idx + 1], self.rgb[idx + 2])
+    }
+}
+
+mod helpers {
+    use super::*;
+
+    /// Palette of the RGB PPUs. Emphasis bits drive the corresponding channel to full intensity
+    pub fn rgb_ppu_palette() -> Palette {
+        let mut rgb = [0u8; PALETTE_SIZE];
+
+        for (idx, color) in rgb.chunks_mut(3).enumerate() {
+            let emphasis = idx / NUM_COLORS;
+            let levels = RGB_PPU_LEVELS[idx % NUM_COLORS];
+
+            for (channel, value) in color.iter_mut().enumerate() {
+                let level = if bit_is_set!(emphasis, channel) { 7 } else { (levels >> (6 - channel * 3)) & 0x07 };
+                *value = (level * 255 / 7) as u8;
+            }
+        }
+
+        Palette { rgb }
+    }
+
+    /// Derive the emphasis colors by attenuating the channels that are not emphasized
+    pub fn expand_emphasis(base: &[u8]) -> Palette {
+        let mut rgb = [0u8; PALETTE_SIZE];
+
+        for (idx, color) in rgb.chunks_mut(3).enumerate() {
+            let emphasis = idx / NUM_COLORS;
+            let base_idx = (idx % NUM_COLORS) * 3;
+
+            for (channel, value) in color.iter_mut().enumerate() {
+                let attenuated = (0..3).filter(|&bit| bit != channel && bit_is_set!(emphasis, bit)).count();
+                *value = (base[base_idx + channel] as f64 * EMPHASIS_ATTENUATION.powi(attenuated as i32)) as u8;
+            }
+        }
+
+        Palette { rgb }
+    }
+
+    /// Palette of the 2C07, decoded from its PAL signal. The red and green emphasis bits are swapped
+    pub fn pal_palette() -> Palette {
+        let params = NtscPaletteParams {
+            hue: signal::PAL_HUE_OFFSET,
+            ..NtscPaletteParams::default()
+        };
+
+        swap_red_green_emphasis(&Palette::generate(params).rgb)
+    }
+
+    /// Reorder the emphasis groups so the red and green bits select each other's colors
+    pub fn swap_red_green_emphasis(palette: &[u8; PALETTE_SIZE]) -> Palette {
+        let group_size = NUM_COLORS * 3;
+        let mut rgb = [0u8; PALETTE_SIZE];
+
+        for (emphasis, group) in rgb.chunks_mut(group_size).enumerate() {
+            let swapped = (emphasis & 0x04) | ((emphasis & 0x01) << 1) | ((emphasis & 0x02) >> 1);
+            group.copy_from_slice(&palette[swapped * group_size..(swapped + 1) * group_size]);
+        }
+
+        Palette { rgb }
+    }
+
+    /// Decode the color of a 9-bit pixel (emphasis and color) by sampling its signal over one color cycle
+    pub fn decode_ntsc(pixel: usize, params: &NtscPaletteParams) -> [u8; 3] {
+        let (mut y, mut i, mut q) = (0.0, 0.0, 0.0);
+
+        for phase in 0..signal::PHASES {
+            let level = signal::level(pixel as IndexedPixel, phase);
+            let (cos, sin) = signal::carrier(phase, params.hue);
+
+            y += level;
+            i += level * cos;
+            q += level * sin;
+        }
+
+        let phases = signal::PHASES as f64;
+        let y = (y / phases) * params.contrast + params.brightness;
+        let i = (i / phases) * params.contrast * params.saturation;
+        let q = (q / phases) * params.contrast * params.saturation;
+
+        let (r, g, b) = signal::yiq_to_rgb(y, i, q);
+        [r, g, b]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_matches_2c02() {
+        let generated = Palette::generate(NtscPaletteParams::default());
+
+        for (actual, expected) in generated.rgb.iter().zip(NTSC_PALETTE.iter()) {
+            assert!((*actual as i16 - *expected as i16).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn from_slice_sizes() {
+        assert!(Palette::from_slice(&NTSC_PALETTE).unwrap() == Palette::from(BuiltinPalette::Ppu2C02));
+        assert!(Palette::from_slice(&NTSC_PALETTE[..192]).is_ok());
+        assert!(matches!(Palette::from_slice(&[0u8; 100]), Err(PaletteError::InvalidSize(100))));
+    }
+
+    #[test]
+    fn from_slice_expands_emphasis() {
+        let base = [0xFFu8; 192];
+        let palette = Palette::from_slice(&base).unwrap();
+
+        // No emphasis
+        assert_eq!(palette.color(0, 0x30), (0xFF, 0xFF, 0xFF));
+        // Red emphasis attenuates green and blue
+        assert_eq!(palette.color(0x01, 0x30), (0xFF, 190, 190));
+        // Red and green emphasis
+        assert_eq!(palette.color(0x03, 0x30), (190, 190, 141));
+    }
+
+    #[test]
+    fn rgb_ppu_emphasis() {
+        let palette = Palette::from(BuiltinPalette::Ppu2C03);
+
+        assert_eq!(palette.color(0, 0x0F), (0, 0, 0));
+        assert_eq!(palette.color(0, 0x20), (255, 255, 255));
+        assert_eq!(palette.color(0x01, 0x0F), (255, 0, 0));
+        assert_eq!(palette.color(0x06, 0x0F), (0, 255, 255));
+    }
+
+    #[test]
+    fn region_palette_swaps_emphasis() {
+        let ntsc = Palette::for_region(Region::Ntsc);
+        let pal = Palette::for_region(Region::Pal);
+
+        assert!(ntsc == Palette::from(BuiltinPalette::Ppu2C02));
+        assert!(pal == Palette::from(BuiltinPalette::Ppu2C07));
+
+        let unswapped = Palette::generate(NtscPaletteParams {
+            hue: signal::PAL_HUE_OFFSET,
+            ..NtscPaletteParams::default()
+        });
+
+        assert_eq!(pal.color(0x01, 0x16), unswapped.color(0x02, 0x16));
+        assert_eq!(pal.color(0x02, 0x16), unswapped.color(0x01, 0x16));
+        assert_eq!(pal.color(0x04, 0x16), unswapped.color(0x04, 0x16));
+    }
+
+    #[test]
+    fn pal_palette_hue() {
+        let ntsc = Palette::generate(NtscPaletteParams::default());
+        let pal = Palette::from(BuiltinPalette::Ppu2C07);
+
+        let distance = |(r1, g1, b1): Pixel, (r2, g2, b2): Pixel| {
+            (r1 as i16 - r2 as i16).abs().max((g1 as i16 - g2 as i16).abs()).max((b1 as i16 - b2 as i16).abs())
+        };
+
+        // Grays have no color to shift
+        assert!(distance(pal.color(0, 0x20), ntsc.color(0, 0x20)) <= 1);
+        assert!(distance(pal.color(0, 0x16), ntsc.color(0, 0x16)) > 1);
+    }
+
+    #[test]
+    fn builtin_from_str() {
+        assert_eq!("2C02".parse::<BuiltinPalette>().unwrap(), BuiltinPalette::Ppu2C02);
+        assert_eq!("2c05".parse::<BuiltinPalette>().unwrap(), BuiltinPalette::Ppu2C03);
+        assert_eq!("2c07".parse::<BuiltinPalette>().unwrap(), BuiltinPalette::Ppu2C07);
+        assert!("2c04".parse::<BuiltinPalette>().is_err());
+    }
+}
@@ -6,20 +6,24 @@
 //
 use super::regs::*;
 use super::hw::*;
-use super::sprite::Sprite;
-use crate::common::{IoAccess, Clockable, Register, Interrupt};
+use super::sprite::{Sprite, SpriteEvaluator};
+use super::palette::Palette;
+use crate::common::{IoAccess, Clockable, Register};
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+use crate::debug::DebuggerRef;
+use crate::region::Region;
 
 use std::cell::RefCell;
 
-const NUM_SCANLINES: usize = 262;
-const CYCLES_PER_SCANLINE: usize = 341;
+pub const CYCLES_PER_SCANLINE: usize = 341;
 const TILES_PER_ROW: usize = 32;
 
 /// RGB Pixel
 pub type Pixel = (u8, u8, u8);
+/// Pixel as its 6-bit palette color (bits 0-5) and the PPUMASK emphasis bits (bits 6-8)
+pub type IndexedPixel = u16;
 pub const DISPLAY_WIDTH: usize = 256;
 pub const DISPLAY_HEIGHT: usize = 240;
-pub const CYCLES_PER_FRAME: usize = NUM_SCANLINES * CYCLES_PER_SCANLINE;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum Scanline {
@@ -30,22 +34,38 @@ enum Scanline {
 }
 
 impl Scanline {
-    pub fn from(scanline: usize) -> Self {
+    pub fn from(scanline: usize, region: Region) -> Self {
+        let prerender = region.scanlines() - 1;
+
         match scanline {
-            261 => Scanline::PreRender,
             0..=239 => Scanline::Visible,
-            240 => Scanline::PostRender,
-            241..=260 => Scanline::VBlank,
+            s if s < region.vblank_scanline() => Scanline::PostRender,
+            s if s < prerender => Scanline::VBlank,
+            s if s == prerender => Scanline::PreRender,
 
             _ => panic!("Invalid scanline!"),
         }
     }
 }
 
+/// PPU register values
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PpuRegisters {
+    pub ctrl: u8,
+    pub mask: u8,
+    pub status: u8,
+    pub oam_addr: u8,
+    pub v: u16,       // Current VRAM address
+    pub t: u16,       // Temporary VRAM address
+    pub x: u8,        // Fine X scroll
+    pub w: bool,      // Write toggle
+}
+
 /// NES Picture Processing Unit
 pub struct Ppu<Io: IoAccess> {
     oam: [u8; 256],            // Object Attribute Memory (Sprites)
     sprite_cache: [Option<Sprite>; 8],   // Up to 8 sprites per scanline
+    sprite_eval: SpriteEvaluator,        // Secondary OAM and sprite evaluation
 
     ctrl: PpuCtrl,              // PPUCTRL   - Control Register
     status: RefCell<PpuStatus>, // PPUSTATUS - Status Register
@@ -57,17 +77,30 @@ pub struct Ppu<Io: IoAccess> {
     x: u8,                   // Fine X scroll
     w: RefCell<bool>,        // Write toggle
 
+    reset_latch: bool,       // Writes to PPUCTRL, PPUMASK, PPUSCROLL and PPUADDR are ignored until the end of VBlank
+
+    open_bus: RefCell<OpenBus>, // I/O data bus latch
+    read_buffer: RefCell<u8>,   // PPUDATA read buffer
+
+    suppress_vblank: RefCell<bool>, // PPUSTATUS was read just before the vblank flag is set
+    odd_frame: bool,                // The idle dot is skipped on odd frames when rendering
+    prev_rendering_enabled: bool,   // Rendering enabled, as of the previous dot
+
     // Render pipeline hardware
     tile_reg: TileRegister,    // PPU tile shift registers
     pal_reg: PaletteRegister,  // PPU palette shift registers
     sprite_regs: [SpriteRegister; 8],
+    extra_sprite_regs: Vec<SpriteRegister>, // Sprites past the eighth on a scanline, when the sprite limit is removed
 
     cycle: usize,              // Cycle count per scanline
     scanline: usize,           // Current scanline
 
     bus: Option<Io>,
+    debugger: Option<DebuggerRef>,
 
-    rgb_palette: [u8; 0x600],
+    region: Region,
+    palette: Palette,
+    sprite_limit: bool,        // Render at most 8 sprites per scanline
 }
 
 impl<Io: IoAccess> Default for Ppu<Io> {
@@ -75,6 +108,7 @@ impl<Io: IoAccess> Default for Ppu<Io> {
         Ppu{
             oam: [0; 256],
             sprite_cache: [None; 8],
+            sprite_eval: SpriteEvaluator::default(),
 
             ctrl: PpuCtrl::default(),
             status: RefCell::new(PpuStatus::default()),
@@ -86,29 +120,48 @@ impl<Io: IoAccess> Default for Ppu<Io> {
             x: 0,
             w: RefCell::new(false),
 
+            reset_latch: false,
+
+            open_bus: RefCell::new(OpenBus::default()),
+            read_buffer: RefCell::new(0),
+
+            suppress_vblank: RefCell::new(false),
+            odd_frame: false,
+            prev_rendering_enabled: false,
+
             tile_reg: TileRegister::default(),
             pal_reg: PaletteRegister::default(),
             sprite_regs: [SpriteRegister::default(); 8],
+            extra_sprite_regs: Vec::new(),
 
             cycle: 0,
-            scanline: NUM_SCANLINES - 1, // Initialize to the Pre-render scanline
+            scanline: Region::default().scanlines() - 1, // Initialize to the Pre-render scanline
 
             bus: None,
+            debugger: None,
 
-            rgb_palette: *include_bytes!("ntscpalette.pal"),
+            region: Region::default(),
+            palette: Palette::default(),
+            sprite_limit: true,
         }
     }
 }
 
 impl<Io: IoAccess> Ppu<Io> {
-    fn run_cycle(&mut self) -> Option<Pixel> {
-        let scanline = Scanline::from(self.scanline);
+    fn run_cycle(&mut self) -> Option<IndexedPixel> {
+        let scanline = Scanline::from(self.scanline, self.region);
         match scanline {
             Scanline::PreRender => {
                 if self.cycle == 1 {
                     self.clear_sprite_data();
                     self.status.borrow_mut().sprite0_hit = false;
+                    self.status.borrow_mut().sprite_overflow = false;
                     self.status.borrow_mut().vblank = false;
+                    self.reset_latch = false;
+
+                    // Open bus bits decay after roughly 600ms
+                    let decay_frames = (self.region.frame_rate() * 0.6) as u8;
+                    self.open_bus.borrow_mut().decay(decay_frames);
                 }
 
                 if self.cycle >= 280 && self.cycle <= 304 {
@@ -150,13 +203,10 @@ impl<Io: IoAccess> Ppu<Io> {
                 None
             },
             Scanline::VBlank => {
-                if self.cycle == 1 && self.scanline == 241 {
+                // The NMI line is asserted while the vblank flag and NMI enable are both set
+                // Reading PPUSTATUS one dot before the flag is set prevents it from being set for the frame
+                if self.cycle == 1 && self.scanline == self.region.vblank_scanline() && !self.suppress_vblank.replace(false) {
                     self.status.borrow_mut().vblank = true;
-
-                    // Signal NMI interrupt
-                    if self.ctrl.nmi_enable {
-                        self.raise_interrupt();
-                    }
                 }
 
                 None
@@ -172,7 +222,14 @@ impl<Io: IoAccess> Ppu<Io> {
             },
             1..=256 => {
                 // 4 memory accesses each taking 2 cycles
-                // In addition to all that, the sprite evaluation happens independently
+                // In addition to all that, the sprite evaluation happens independently (not on the pre-render scanline)
+                if self.scanline < DISPLAY_HEIGHT {
+                    let overflow = self.sprite_eval.clock(dot, &self.oam, self.scanline as u16, self.ctrl.sprite_height());
+                    if overflow {
+                        self.status.borrow_mut().sprite_overflow = true;
+                    }
+                }
+
                 if dot % 8 == 0 && dot <= 240 {
                     self.load_shift_registers();
                 }
@@ -186,28 +243,29 @@ impl<Io: IoAccess> Ppu<Io> {
                 // Cycles 257 - 320: Get tile data for sprites on next scanline
                 // Sprite eval is complete by cycle 257
                 if dot == 257 {
-                    let scanline = ((self.scanline + 1) % NUM_SCANLINES) as u16;
-                    self.evaluate_sprites(scanline);
+                    self.load_sprite_cache();
+
+                    let scanline = ((self.scanline + 1) % self.region.scanlines()) as u16;
+                    self.load_extra_sprites(scanline);
 
                     // At dot 257, the horizontal bits of t are copied to v (if rendering)
                     if self.mask.rendering_enabled() {
                         self.v.borrow_mut().reload_x(self.t.borrow().value());
                     }
                 }
+
+                // Each sprite slot takes 8 cycles to fetch, pattern data is read in the second half
+                if (dot - 257) % 8 == 4 {
+                    let scanline = ((self.scanline + 1) % self.region.scanlines()) as u16;
+                    self.load_sprite_data((dot - 257) / 8, scanline);
+                }
             },
             321..=336 => {
                 // Cycles 321-336: Fetch first two tiles of the next scanline
                 // accesses: 2 nametable bytes, attribute, pattern table low, pattern table high
-                let scanline = (self.scanline + 1) % NUM_SCANLINES;
-
                 if dot % 8 == 0 {
                     self.load_shift_registers();
                 }
-
-                // Sprite data loading has completed by cycle 321
-                if dot == 321 {
-                    self.load_sprite_data(scanline as u16);
-                }
             },
             337..=340 => {
                 // Cycles 337 - 340
@@ -243,81 +301,91 @@ impl<Io: IoAccess> Ppu<Io> {
         }
     }
 
-    fn evaluate_sprites(&mut self, scanline: u16) {
-        // Sprite data is delay by one scanline
-        let scanline = scanline as i16;
-        // Scan primary OAM for sprites that are on the specified scanline
-        let mut found_sprites = 0;
-
-        // Clear sprite cache
-        self.sprite_cache = [None; 8];
-
-        if scanline < 240 {
-            for n in 0..64 {
-                if found_sprites < 8 {
-                    let offset = n * 4;
-                    let sprite = Sprite::from(&self.oam[offset..offset+4], n as u8);
+    fn load_sprite_cache(&mut self) {
+        // Sprites evaluated on this scanline are rendered on the next.
+        // Sprite evaluation does not occur on the pre-render scanline, so no sprites are rendered on scanline 0
+        self.sprite_cache = if self.scanline < DISPLAY_HEIGHT {
+            self.sprite_eval.sprites()
+        }
+        else {
+            [None; 8]
+        };
+    }
 
-                    let intersect = scanline - sprite.y as i16;
-                    let h = self.ctrl.sprite_height() as i16;
+    fn load_sprite_data(&mut self, slot: usize, scanline: u16) {
+        match self.sprite_cache[slot] {
+            Some(sprite) => {
+                let (pattern_table, tile, fine_y) = self.sprite_pattern_address(&sprite, scanline);
+                let pattern = self.read_pattern(pattern_table, tile, fine_y);
 
-                    if intersect >= 0 && intersect < h {
-                        // Found a valid sprite
-                        // Move to the sprite cache
-                        self.sprite_cache[found_sprites] = Some(sprite);
-                        found_sprites += 1;
-                    }
-                }
-                else {
-                    // TODO: Set sprite overflow flag
-                    break;
-                }
+                helpers::load_sprite_register(&mut self.sprite_regs[slot], &sprite, pattern);
+            },
+            None => {
+                // Empty slots still perform a dummy fetch of tile $FF
+                // Mappers watching PPU A12 (MMC3) depend on this
+                let pattern_table = if self.ctrl.sprite_height() == 16 { 0x1000 } else { self.ctrl.sprite_pattern_table() };
+                self.read_pattern(pattern_table, 0xFF, 0);
             }
         }
     }
 
-    fn load_sprite_data(&mut self, scanline: u16) {
-        for (i, sprite) in self.sprite_cache.iter().enumerate() {
-            if let Some(ref sprite) = sprite {
-                let sprite_height = self.ctrl.sprite_height();
+    fn load_extra_sprites(&mut self, scanline: u16) {
+        self.extra_sprite_regs.clear();
 
-                // Determine fine y for vertical flipping
-                let fine_y = if !sprite.flip_v() {
-                    (scanline - sprite.y) as u8
-                }
-                else {
-                    (sprite_height - 1) - (scanline - sprite.y) as u8
-                };
+        if self.sprite_limit || self.scanline >= DISPLAY_HEIGHT {
+            return;
+        }
 
-                // In 8x16 mode the PPU ignores the sprite pattern table selection in the CTRL register
-                // The table selection instead comes from the first bit of the sprite's tile attribute
-                // The tile number selection is then the upper bits of the tile attribute (upper tile)
-                // the bottom tile is the next one
-                // Re-adjusting the fine y will also be necessary
-                let (pattern_table, tile, fine_y) = if sprite_height == 16 {
-                    let bottom_tile = fine_y > 7;
-                    (
-                        sprite.pattern_table_8x16(),
-                        sprite.tile_number_8x16() + if bottom_tile { 1 } else { 0 },
-                        fine_y - if bottom_tile { 8 } else { 0 }
-                    )
-                }
-                else {
-                    (self.ctrl.sprite_pattern_table(), sprite.tile, fine_y)
-                };
+        // Find the sprites past the eighth on the scanline. These are fetched without driving the PPU bus so that the
+        // mapper sees the same accesses as it would on hardware
+        let h = self.ctrl.sprite_height() as i16;
+        let sprites = (0..64)
+            .map(|n| Sprite::from(&self.oam[n*4..n*4+4], n as u8))
+            .filter(|sprite| {
+                let intersect = scanline as i16 - sprite.y as i16;
+                intersect >= 0 && intersect < h
+            })
+            .skip(8)
+            .collect::<Vec<_>>();
+
+        for sprite in sprites {
+            let (pattern_table, tile, fine_y) = self.sprite_pattern_address(&sprite, scanline);
+            let pattern = self.peek_pattern(pattern_table, tile, fine_y);
+
+            let mut sprite_reg = SpriteRegister::default();
+            helpers::load_sprite_register(&mut sprite_reg, &sprite, pattern);
+
+            self.extra_sprite_regs.push(sprite_reg);
+        }
+    }
 
-                let pattern = self.read_pattern(pattern_table, tile, fine_y);
+    /// Pattern table, tile number and fine y of the sprite row on the given scanline
+    fn sprite_pattern_address(&self, sprite: &Sprite, scanline: u16) -> (u16, u8, u8) {
+        let sprite_height = self.ctrl.sprite_height();
 
-                // Reverse bit pattern if the sprite is horizontally flipped
-                let pattern = if sprite.flip_h() {
-                    (reverse_bits!(pattern.0), reverse_bits!(pattern.1))
-                }
-                else {
-                    pattern
-                };
+        // Determine fine y for vertical flipping
+        let fine_y = if !sprite.flip_v() {
+            (scanline - sprite.y) as u8
+        }
+        else {
+            (sprite_height - 1) - (scanline - sprite.y) as u8
+        };
 
-                self.sprite_regs[i].load(sprite.x, pattern, sprite.palette(), sprite.priority(), sprite.num);
-            }
+        // In 8x16 mode the PPU ignores the sprite pattern table selection in the CTRL register
+        // The table selection instead comes from the first bit of the sprite's tile attribute
+        // The tile number selection is then the upper bits of the tile attribute (upper tile)
+        // the bottom tile is the next one
+        // Re-adjusting the fine y will also be necessary
+        if sprite_height == 16 {
+            let bottom_tile = fine_y > 7;
+            (
+                sprite.pattern_table_8x16(),
+                sprite.tile_number_8x16() + if bottom_tile { 1 } else { 0 },
+                fine_y - if bottom_tile { 8 } else { 0 }
+            )
+        }
+        else {
+            (self.ctrl.sprite_pattern_table(), sprite.tile, fine_y)
         }
     }
 
@@ -326,6 +394,7 @@ impl<Io: IoAccess> Ppu<Io> {
         for sprite_reg in &mut self.sprite_regs {
             sprite_reg.load(0, (0, 0), 0, false, 0);
         }
+        self.extra_sprite_regs.clear();
     }
 
     fn read_nametable(&self, nametable: u16, idx: usize) -> u8 {
@@ -367,11 +436,22 @@ impl<Io: IoAccess> Ppu<Io> {
         (lo, hi)
     }
 
+    fn peek_pattern(&self, base: u16, tile_no: u8, fine_y: u8) -> (u8, u8) {
+        let tile_offset = (tile_no as u16 * 16) + fine_y as u16;
+
+        if let Some(ref bus) = self.bus {
+            (bus.peek_byte(base + tile_offset), bus.peek_byte(base + tile_offset + 8))
+        }
+        else {
+            panic!("PPU's bus not initialized");
+        }
+    }
+
     fn get_sprite_pixel_data(&self) -> (u8, u8, bool, bool) {
         let mut pixel_data = (0, 0, false, false);
 
         // Find the first opaque pixel for the active sprites
-        for sprite_reg in self.sprite_regs.iter() {
+        for sprite_reg in self.sprite_regs.iter().chain(self.extra_sprite_regs.iter()) {
             if sprite_reg.active() {
                 let sprite_data = sprite_reg.get_value();
                 // Check if not opaque
@@ -385,7 +465,7 @@ impl<Io: IoAccess> Ppu<Io> {
         pixel_data
     }
 
-    fn apply_mux(&self) -> Pixel {
+    fn apply_mux(&self) -> IndexedPixel {
         let dot = self.cycle;
 
         // Fetch pattern and attributes from shifters
@@ -434,14 +514,8 @@ impl<Io: IoAccess> Ppu<Io> {
         // Four rows of colors in the palette: $00, $10, $20, $30.
         // The first colors in the row are the grey colors
         let color = if self.mask.greyscale { color & 0x30 } else { color };
-        // Get the index into the RGB palette, account for emphasis bits
-        let color_idx = (self.mask.pal_idx * 64) + (color * 3);
-
-        (
-            self.rgb_palette[color_idx],
-            self.rgb_palette[color_idx + 1],
-            self.rgb_palette[color_idx + 2],
-        )
+        // Combine with the emphasis bits
+        ((self.mask.pal_idx << 6) | color) as IndexedPixel
     }
 
     fn tick_shifters(&mut self) {
@@ -450,18 +524,11 @@ impl<Io: IoAccess> Ppu<Io> {
     }
 
     fn tick_sprite_registers(&mut self) {
-        for sprite_reg in self.sprite_regs.iter_mut() {
+        for sprite_reg in self.sprite_regs.iter_mut().chain(self.extra_sprite_regs.iter_mut()) {
             sprite_reg.tick();
         }
     }
 
-    /// Raise NMI interrupt
-    fn raise_interrupt(&mut self) {
-        if let Some(ref mut bus) = self.bus {
-            bus.raise_interrupt(Interrupt::Nmi);
-        }
-    }
-
     /// Read directly from PPU VRAM
     pub fn read_vram(&self, addr: u16) -> u8 {
         if let Some(ref bus) = self.bus {
@@ -474,66 +541,208 @@ impl<Io: IoAccess> Ppu<Io> {
 
     /// Write directly to PPU VRAM
     pub fn write_vram(&mut self, addr: u16, value: u8) {
+        if let Some(ref debugger) = self.debugger {
+            debugger.borrow_mut().check_vram_write(addr & 0x3FFF, value);
+        }
+
         if let Some(ref mut bus) = self.bus {
             bus.write_byte(addr & 0x3FFF, value);
         }
     }
 
+    /// Place the current VRAM address on the bus. Outside of rendering, the PPU bus holds the value of v
+    fn drive_address(&mut self) {
+        if !self.mask.rendering_enabled() {
+            let addr = self.v.borrow().value() & 0x3FFF;
+            if let Some(ref mut bus) = self.bus {
+                bus.drive_address(addr);
+            }
+        }
+    }
+
+    pub fn read_oam(&self, addr: u8) -> u8 {
+        self.oam[addr as usize]
+    }
+
+    #[cfg(test)]
     pub fn write_oam(&mut self, addr: u8, value: u8) {
         self.oam[addr as usize] = value;
     }
 
+    /// Reset the PPU. PPUCTRL, PPUMASK, the scroll and the write toggle are cleared
+    ///
+    /// Writes to PPUCTRL, PPUMASK, PPUSCROLL and PPUADDR are ignored until the end of the next VBlank
+    pub fn reset(&mut self) {
+        self.ctrl.load(0x00);
+        self.mask.load(0x00);
+
+        self.t.borrow_mut().load(0x0000);
+        self.x = 0;
+        *self.w.borrow_mut() = false;
+        *self.read_buffer.borrow_mut() = 0;
+
+        self.reset_latch = true;
+    }
+
+    /// Return the PPU to its power up state. The bus must be reloaded
+    pub fn power_on(&mut self) {
+        *self = Ppu {
+            debugger: self.debugger.take(),
+            region: self.region,
+            palette: self.palette.clone(),
+            sprite_limit: self.sprite_limit,
+            scanline: self.region.scanlines() - 1,
+            ..Ppu::default()
+        };
+    }
+
+    /// Set the frame timing and default palette for the region. The frame restarts from the pre-render scanline
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.palette = Palette::for_region(region);
+
+        self.scanline = region.scanlines() - 1;
+        self.cycle = 0;
+    }
+
+    /// Set the colors used to render pixels
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Limit rendering to 8 sprites per scanline. When disabled, all sprites on a scanline are drawn.
+    /// Sprite evaluation, and the sprite overflow flag, are not affected
+    pub fn set_sprite_limit(&mut self, enabled: bool) {
+        self.sprite_limit = enabled;
+
+        if enabled {
+            self.extra_sprite_regs.clear();
+        }
+    }
+
+    /// RGB color of a pixel in the current palette
+    pub fn color(&self, pixel: IndexedPixel) -> Pixel {
+        self.palette.color((pixel >> 6) as usize, (pixel & 0x3F) as usize)
+    }
+
+    #[cfg(test)]
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
     pub fn load_bus(&mut self, bus: Io) {
         self.bus = Some(bus);
     }
 
+    pub fn load_debugger(&mut self, debugger: DebuggerRef) {
+        self.debugger = Some(debugger);
+    }
+
     pub fn read_tile(&self, nametable: u16, x: usize, y: usize) -> u8 {
         let idx = (y * TILES_PER_ROW) + x;
         self.read_nametable(nametable, idx)
     }
-}
 
-// TODO: Latch behaviour
+    /// The scanline and dot that will be processed on the next tick
+    pub fn position(&self) -> (usize, usize) {
+        (self.scanline, self.cycle)
+    }
+
+    /// Move the PPU to a scanline and dot
+    pub fn set_position(&mut self, scanline: usize, dot: usize) {
+        self.scanline = scanline;
+        self.cycle = dot;
+    }
+
+    /// The vblank flag as it will be after the next two dots
+    fn vblank_ahead(&self) -> bool {
+        if self.cycle <= 1 {
+            if self.scanline == self.region.vblank_scanline() && !*self.suppress_vblank.borrow() {
+                return true;
+            }
+            if self.scanline == self.region.scanlines() - 1 {
+                return false;
+            }
+        }
+
+        self.status.borrow().vblank
+    }
+
+    /// Register values, read without side effects
+    pub fn registers(&self) -> PpuRegisters {
+        PpuRegisters {
+            ctrl: self.ctrl.value(),
+            mask: self.mask.value(),
+            status: self.status.borrow().value(),
+            oam_addr: *self.oam_addr.borrow() as u8,
+            v: self.v.borrow().value(),
+            t: self.t.borrow().value(),
+            x: self.x,
+            w: *self.w.borrow(),
+        }
+    }
+}
 
 impl<Io: IoAccess> IoAccess for Ppu<Io> {
     fn read_byte(&self, addr: u16) -> u8 {
         match addr {
-            0x2000 => {
-                // Clear the write toggle
-                *self.w.borrow_mut() = false;
-                self.ctrl.value()
-            },
-            0x2001 => {
-                self.mask.value()
+            // Write only registers return the value on the open bus
+            0x2000 | 0x2001 | 0x2003 | 0x2005 | 0x2006 => {
+                self.open_bus.borrow().value()
             },
             0x2002 => {
-                let status = self.status.borrow().value();
-                // VBlank flag is clear on reading the status register
+                // Only the upper 3 bits are driven by the status register
+                let data = self.status.borrow().value() | (self.open_bus.borrow().value() & 0x1F);
+                self.open_bus.borrow_mut().drive(data, 0xE0);
+
+                // VBlank flag and the write toggle are cleared on reading the status register
                 self.status.borrow_mut().vblank = false;
+                *self.w.borrow_mut() = false;
 
-                status
-            },
-            0x2003 => {
-                0
+                // Reading the flag one dot before it is set, reads it as clear and the flag is not set for the frame
+                if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+                    *self.suppress_vblank.borrow_mut() = true;
+                }
+
+                data
             },
             0x2004 => {
-                let data = self.oam[*self.oam_addr.borrow() as usize];
+                let oam_addr = *self.oam_addr.borrow() as usize;
+                let data = self.oam[oam_addr];
+                // Bits 2-4 of the sprite attribute byte do not exist
+                let data = if oam_addr % 4 == 2 { data & 0xE3 } else { data };
+                self.open_bus.borrow_mut().drive(data, 0xFF);
+
                 // Increment OAM pointer
                 let new_oam_addr = self.oam_addr.borrow().wrapping_add(1) % 256;
                 *self.oam_addr.borrow_mut() = new_oam_addr;
 
                 data
             },
-            0x2005 => {
-                self.x
-            },
-            // PPU Address
-            0x2006 => {
-                self.v.borrow().value() as u8
-            },
             // PPU Data
             0x2007 => {
-                let data = self.read_vram(self.v.borrow().value());
+                let addr = self.v.borrow().value() & 0x3FFF;
+
+                let data = if addr >= 0x3F00 {
+                    // Palette reads are returned immediately. The upper 2 bits are open bus
+                    let color = self.read_vram(addr) & 0x3F;
+                    let color = if self.mask.greyscale { color & 0x30 } else { color };
+                    let data = color | (self.open_bus.borrow().value() & 0xC0);
+                    self.open_bus.borrow_mut().drive(data, 0x3F);
+
+                    // The read buffer is filled with the nametable data "underneath" the palette
+                    *self.read_buffer.borrow_mut() = self.read_vram(addr - 0x1000);
+
+                    data
+                }
+                else {
+                    // Other reads return the contents of the read buffer, which is then filled from VRAM
+                    let data = self.read_buffer.replace(self.read_vram(addr));
+                    self.open_bus.borrow_mut().drive(data, 0xFF);
+
+                    data
+                };
+
                 *self.v.borrow_mut() += self.ctrl.vram_increment();
 
                 data
@@ -544,6 +753,13 @@ impl<Io: IoAccess> IoAccess for Ppu<Io> {
     }
 
     fn write_byte(&mut self, addr: u16, value: u8) {
+        // Writes to any register fill the open bus
+        self.open_bus.borrow_mut().drive(value, 0xFF);
+
+        if self.reset_latch && matches!(addr, 0x2000 | 0x2001 | 0x2005 | 0x2006) {
+            return;
+        }
+
         match addr {
             // PPU Control Register
             0x2000 => {
@@ -586,6 +802,9 @@ impl<Io: IoAccess> IoAccess for Ppu<Io> {
                 if *self.w.borrow() {
                     self.t.borrow_mut().set_low_byte(value);
                     self.v.borrow_mut().load(self.t.borrow().value());
+
+                    // The new VRAM address is placed on the PPU bus
+                    self.drive_address();
                 }
                 else {
                     self.t.borrow_mut().set_high_byte(value);
@@ -601,29 +820,41 @@ impl<Io: IoAccess> IoAccess for Ppu<Io> {
                 self.write_vram(addr, value);
 
                 *self.v.borrow_mut() += self.ctrl.vram_increment();
+                self.drive_address();
             }
-            _ => {
-                // FIXME: OAM DMA
-                // FIXME: DMA uses OAM ADDR?
-                if mask_is_set!(addr, 0xFF00) {
-                    let oam_addr = (addr & 0xFF) as u8;
-                    self.write_oam(oam_addr, value);
-                }
-            }
+            _ => {}
         }
+    }
 
-        self.status.borrow_mut().lsb = value;
+    fn nmi_line(&self) -> bool {
+        // The CPU samples the NMI line at the end of its cycle, two dots after its bus access. The line reflects the
+        // vblank flag as it will be at that point
+        self.ctrl.nmi_enable && self.vblank_ahead()
     }
 }
 
-impl<Io: IoAccess> Clockable<Option<Pixel>> for Ppu<Io> {
-    fn tick(&mut self) -> Option<Pixel> {
+impl<Io: IoAccess> Clockable<Option<IndexedPixel>> for Ppu<Io> {
+    fn tick(&mut self) -> Option<IndexedPixel> {
         let pixel = self.run_cycle();
 
-        self.cycle += 1;
+        // On odd frames with rendering enabled, the idle dot at the end of the pre-render scanline is skipped
+        let prerender = self.region.scanlines() - 1;
+        let skip_dot = self.region.skips_idle_dot()
+                    && self.odd_frame
+                    && self.prev_rendering_enabled
+                    && self.scanline == prerender
+                    && self.cycle == CYCLES_PER_SCANLINE - 2;
+
+        self.prev_rendering_enabled = self.mask.rendering_enabled();
+
+        self.cycle += if skip_dot { 2 } else { 1 };
 
         if self.cycle == CYCLES_PER_SCANLINE {
-            self.scanline = (self.scanline + 1) % NUM_SCANLINES;
+            self.scanline = (self.scanline + 1) % self.region.scanlines();
+
+            if self.scanline == 0 {
+                self.odd_frame = !self.odd_frame;
+            }
         }
 
         self.cycle %= CYCLES_PER_SCANLINE;
@@ -632,7 +863,123 @@ impl<Io: IoAccess> Clockable<Option<Pixel>> for Ppu<Io> {
     }
 }
 
+impl<Io: IoAccess> Snapshot for Ppu<Io> {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.oam);
+        for sprite in self.sprite_cache.iter() {
+            state.write_bool(sprite.is_some());
+            sprite.unwrap_or_default().save_state(state);
+        }
+        self.sprite_eval.save_state(state);
+
+        state.write_u8(self.ctrl.value());
+        {
+            let status = self.status.borrow();
+            state.write_bool(status.sprite_overflow);
+            state.write_bool(status.sprite0_hit);
+            state.write_bool(status.vblank);
+        }
+        state.write_u8(self.mask.value());
+        state.write_u16(*self.oam_addr.borrow());
+
+        state.write_u16(self.v.borrow().value());
+        state.write_u16(self.t.borrow().value());
+        state.write_u8(self.x);
+        state.write_bool(*self.w.borrow());
+        state.write_bool(self.reset_latch);
+        self.open_bus.borrow().save_state(state);
+        state.write_u8(*self.read_buffer.borrow());
+        state.write_bool(*self.suppress_vblank.borrow());
+        state.write_bool(self.odd_frame);
+        state.write_bool(self.prev_rendering_enabled);
+
+        self.tile_reg.save_state(state);
+        self.pal_reg.save_state(state);
+        for reg in self.sprite_regs.iter() {
+            reg.save_state(state);
+        }
+        state.write_u8(self.extra_sprite_regs.len() as u8);
+        for reg in self.extra_sprite_regs.iter() {
+            reg.save_state(state);
+        }
+
+        state.write_usize(self.cycle);
+        state.write_usize(self.scanline);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.oam)?;
+        for sprite in self.sprite_cache.iter_mut() {
+            let present = state.read_bool()?;
+            let mut s = Sprite::default();
+            s.load_state(state)?;
+
+            *sprite = if present { Some(s) } else { None };
+        }
+        self.sprite_eval.load_state(state)?;
+
+        self.ctrl.load(state.read_u8()?);
+        {
+            let mut status = self.status.borrow_mut();
+            status.sprite_overflow = state.read_bool()?;
+            status.sprite0_hit = state.read_bool()?;
+            status.vblank = state.read_bool()?;
+        }
+        self.mask.load(state.read_u8()?);
+        *self.oam_addr.borrow_mut() = state.read_u16()?;
+
+        self.v.borrow_mut().load(state.read_u16()?);
+        self.t.borrow_mut().load(state.read_u16()?);
+        self.x = state.read_u8()?;
+        *self.w.borrow_mut() = state.read_bool()?;
+        self.reset_latch = state.read_bool()?;
+        self.open_bus.borrow_mut().load_state(state)?;
+        *self.read_buffer.borrow_mut() = state.read_u8()?;
+        *self.suppress_vblank.borrow_mut() = state.read_bool()?;
+        self.odd_frame = state.read_bool()?;
+        self.prev_rendering_enabled = state.read_bool()?;
+
+        self.tile_reg.load_state(state)?;
+        self.pal_reg.load_state(state)?;
+        for reg in self.sprite_regs.iter_mut() {
+            reg.load_state(state)?;
+        }
+        let num_extra_sprites = state.read_u8()? as usize;
+        if num_extra_sprites > 56 {
+            return Err(StateError::InvalidData);
+        }
+        self.extra_sprite_regs = vec![SpriteRegister::default(); num_extra_sprites];
+        for reg in self.extra_sprite_regs.iter_mut() {
+            reg.load_state(state)?;
+        }
+
+        let cycle = state.read_usize()?;
+        let scanline = state.read_usize()?;
+        if cycle >= CYCLES_PER_SCANLINE || scanline >= self.region.scanlines() {
+            return Err(StateError::InvalidData);
+        }
+        self.cycle = cycle;
+        self.scanline = scanline;
+
+        Ok(())
+    }
+}
+
 mod helpers {
+    use super::{Sprite, SpriteRegister};
+
+    pub fn load_sprite_register(sprite_reg: &mut SpriteRegister, sprite: &Sprite, pattern: (u8, u8)) {
+        // Reverse bit pattern if the sprite is horizontally flipped
+        let pattern = if sprite.flip_h() {
+            (reverse_bits!(pattern.0), reverse_bits!(pattern.1))
+        }
+        else {
+            pattern
+        };
+
+        sprite_reg.load(sprite.x, pattern, sprite.palette(), sprite.priority(), sprite.num);
+    }
+
     pub fn calc_nametable_address(base: u16, tile_offset: usize) -> u16 {
         base + (tile_offset as u16)
     }
@@ -677,6 +1024,7 @@ mod helpers {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::super::palette::BuiltinPalette;
 
     #[test]
     fn mux() {
@@ -719,7 +1067,7 @@ mod tests {
 
         let mut pixel_counter = 0;
 
-        for _ in 0..CYCLES_PER_FRAME {
+        for _ in 0..Region::Ntsc.ppu_cycles_per_frame() {
             if ppu.tick().is_some() {
                 pixel_counter += 1;
             }
@@ -771,7 +1119,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Sprites cannot be displayed on the first scanline
         // Run for one more scanline
@@ -785,7 +1133,7 @@ mod tests {
 
         // The color of the pixel should be the index one of the color table
         let color = pixel.unwrap();
-        assert_eq!(color, target_color, "Color was: RGB{:?}", color);
+        assert_eq!(color, target_color, "Color was: {:?}", color);
     }
 
     #[test]
@@ -803,8 +1151,7 @@ mod tests {
         // -- Setup OAM
         let oam_data: [u8; 4] = [0x00, 0x01, 0x20, 0x00];
         for (i, oam_byte) in oam_data.iter().enumerate() {
-            let addr = (0xFF00 | i) as u16;
-            ppu.write_byte(addr, *oam_byte);
+            ppu.write_oam(i as u8, *oam_byte);
         }
 
         // Write pattern into pattern table
@@ -820,7 +1167,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Sprites cannot be displayed on the first scanline
         // Run for one more scanline
@@ -834,7 +1181,7 @@ mod tests {
 
         // The color of the pixel should be the index one of the color table
         let color = pixel.unwrap();
-        assert_eq!(color, target_color, "Color was: RGB{:?}", color);
+        assert_eq!(color, target_color, "Color was: {:?}", color);
     }
 
     #[test]
@@ -852,8 +1199,7 @@ mod tests {
         // -- Setup OAM
         let oam_data: [u8; 4] = [0x00, 0x01, 0x20, 0x01];
         for (i, oam_byte) in oam_data.iter().enumerate() {
-            let addr = (0xFF00 | i) as u16;
-            ppu.write_byte(addr, *oam_byte);
+            ppu.write_oam(i as u8, *oam_byte);
         }
 
         // Write pattern into pattern table
@@ -869,7 +1215,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Sprites cannot be displayed on the first scanline
         // Run for one more scanline
@@ -899,8 +1245,7 @@ mod tests {
         // -- Setup OAM
         let oam_data: [u8; 4] = [238, 0x01, 0x20, 0x00];
         for (i, oam_byte) in oam_data.iter().enumerate() {
-            let addr = (0xFF00 | i) as u16;
-            ppu.write_byte(addr, *oam_byte);
+            ppu.write_oam(i as u8, *oam_byte);
         }
 
         // Write pattern into pattern table
@@ -916,7 +1261,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Run for all but the last scanline
         for _ in 0..239 {
@@ -948,8 +1293,7 @@ mod tests {
         // -- Setup OAM
         let oam_data: [u8; 4] = [238, 0x01, 0x00, 128];
         for (i, oam_byte) in oam_data.iter().enumerate() {
-            let addr = (0xFF00 | i) as u16;
-            ppu.write_byte(addr, *oam_byte);
+            ppu.write_oam(i as u8, *oam_byte);
         }
 
         // Sprite pattern
@@ -974,7 +1318,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Run for all but the last scanline
         for _ in 0..239 {
@@ -1013,8 +1357,7 @@ mod tests {
         // -- Setup OAM
         let oam_data: [u8; 4] = [0x00, 0x01, 0x20, 0x01];
         for (i, oam_byte) in oam_data.iter().enumerate() {
-            let addr = (0xFF00 | i) as u16;
-            ppu.write_byte(addr, *oam_byte);
+            ppu.write_oam(i as u8, *oam_byte);
         }
 
         // Write pattern into pattern table
@@ -1030,7 +1373,7 @@ mod tests {
             assert!(pixel.is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // Sprites cannot be displayed on the first scanline
         // Run for one more scanline
@@ -1086,7 +1429,7 @@ mod tests {
 
         // The color of the pixel should be the index one of the color table
         let color = pixel.unwrap();
-        assert_eq!(color, ppu_rgb(&ppu, 0x01), "Color was: RGB{:?}", color);
+        assert_eq!(color, 0x01, "Color was: {:?}", color);
     }
 
     #[test]
@@ -1119,7 +1462,7 @@ mod tests {
             assert!(ppu.tick().is_none());
         }
 
-        let target_color = ppu_rgb(&ppu, 0x01);
+        let target_color: IndexedPixel = 0x01;
 
         // The first tile has no data
         for _ in 0..8 {
@@ -1214,22 +1557,79 @@ mod tests {
         ppu.write_byte(0x2006, 0x01);
         ppu.write_byte(0x2006, 0x50);
 
+        // The first read returns the stale contents of the read buffer
+        ppu.read_byte(0x2007);
         let data = (ppu.read_byte(0x2007), ppu.read_byte(0x2007));
 
         assert_eq!(data, (0xDE, 0xAD));
     }
 
+    #[test]
+    fn palette_read() {
+        let mut ppu = init_ppu();
+
+        ppu.write_vram(0x3F01, 0x16);
+        ppu.write_vram(0x2F01, 0xAB);
+
+        ppu.write_byte(0x2006, 0x3F);
+        ppu.write_byte(0x2006, 0x01);
+
+        // Palette data is returned immediately, with the upper 2 bits from the open bus (the last write)
+        assert_eq!(ppu.read_byte(0x2007), 0x16);
+
+        // The read buffer holds the nametable data underneath the palette
+        ppu.write_byte(0x2006, 0x00);
+        ppu.write_byte(0x2006, 0x00);
+        assert_eq!(ppu.read_byte(0x2007), 0xAB);
+    }
+
+    #[test]
+    fn open_bus_write_only_registers() {
+        let mut ppu = init_ppu();
+
+        ppu.write_byte(0x2000, 0x00);
+        ppu.write_byte(0x2003, 0xA5);
+
+        for addr in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006].iter() {
+            assert_eq!(ppu.read_byte(*addr), 0xA5);
+        }
+
+        // The low 5 bits of the status register are open bus
+        ppu.write_byte(0x2001, 0xFF);
+        assert_eq!(ppu.read_byte(0x2002) & 0x1F, 0x1F);
+    }
+
+    #[test]
+    fn open_bus_decay() {
+        let mut ppu = init_ppu();
+
+        ppu.write_byte(0x2003, 0xFF);
+
+        // Run for about half a second
+        for _ in 0..(Region::Ntsc.ppu_cycles_per_frame() * 30) {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_byte(0x2000), 0xFF);
+
+        for _ in 0..(Region::Ntsc.ppu_cycles_per_frame() * 10) {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_byte(0x2000), 0x00);
+    }
+
     #[test]
     fn vblank() {
         const CYCLES_TO_VBLANK: usize = CYCLES_PER_SCANLINE * 242 + 2;
 
         let mut ppu = init_ppu();
 
-        for _ in 0..CYCLES_TO_VBLANK-1 {
+        for _ in 0..CYCLES_TO_VBLANK-2 {
             ppu.tick();
             assert!(bit_is_clear!(ppu.read_byte(0x2002), 7));
         }
 
+        // Reading the status register one dot before the flag is set would suppress it
+        ppu.tick();
         ppu.tick();
         assert!(bit_is_set!(ppu.read_byte(0x2002), 7));
         // Should be cleared after reading
@@ -1239,26 +1639,181 @@ mod tests {
         assert!(bit_is_clear!(ppu.read_byte(0x2002), 7));
     }
 
+    #[test]
+    fn vblank_region() {
+        for &(region, vblank_scanline) in [(Region::Pal, 241), (Region::Dendy, 291)].iter() {
+            let mut ppu = init_ppu();
+            ppu.set_region(region);
+
+            // Starts on the pre-render scanline
+            ppu.tick();
+            ppu.tick();
+            assert_eq!(ppu.position(), (311, 2));
+
+            while ppu.position() != (vblank_scanline, 2) {
+                assert!(!ppu.status.borrow().vblank);
+                ppu.tick();
+            }
+
+            assert!(ppu.status.borrow().vblank);
+        }
+    }
+
+    #[test]
+    fn region_palette_swaps_emphasis() {
+        let mut ppu = init_ppu();
+        ppu.set_region(Region::Pal);
+
+        assert!(ppu.palette == Palette::from(BuiltinPalette::Ppu2C07));
+    }
+
+    #[test]
+    fn vblank_suppression() {
+        const CYCLES_TO_VBLANK: usize = CYCLES_PER_SCANLINE * 242 + 2;
+
+        let mut ppu = init_ppu();
+        ppu.write_byte(0x2000, 0x80);
+
+        for _ in 0..CYCLES_TO_VBLANK-1 {
+            ppu.tick();
+        }
+
+        // Reading one dot before the flag is set reads it as clear, and the flag is never set
+        assert!(bit_is_clear!(ppu.read_byte(0x2002), 7));
+
+        for _ in 0..CYCLES_PER_SCANLINE {
+            ppu.tick();
+            assert!(!ppu.nmi_line());
+        }
+        assert!(bit_is_clear!(ppu.read_byte(0x2002), 7));
+    }
+
+    #[test]
+    fn nmi_line() {
+        // The NMI line leads the vblank flag by two dots, as the CPU samples it at the end of its cycle
+        const CYCLES_TO_NMI: usize = CYCLES_PER_SCANLINE * 242;
+
+        let mut ppu = init_ppu();
+        ppu.write_byte(0x2000, 0x80);
+
+        for _ in 0..CYCLES_TO_NMI {
+            assert!(!ppu.nmi_line());
+            ppu.tick();
+        }
+
+        assert!(ppu.nmi_line());
+        ppu.tick();
+        ppu.tick();
+        assert!(ppu.nmi_line());
+
+        // Disabling NMI releases the line, enabling it again while in vblank asserts it
+        ppu.write_byte(0x2000, 0x00);
+        assert!(!ppu.nmi_line());
+        ppu.write_byte(0x2000, 0x80);
+        assert!(ppu.nmi_line());
+        // Reading the status register clears the vblank flag
+        ppu.read_byte(0x2002);
+        assert!(!ppu.nmi_line());
+    }
+
+    #[test]
+    fn reset_ignores_writes_until_prerender() {
+        let mut ppu = init_ppu();
+        ppu.write_byte(0x2000, 0x80);
+        ppu.write_byte(0x2001, 0x1E);
+        ppu.write_byte(0x2005, 0x7D);
+
+        ppu.reset();
+
+        let regs = ppu.registers();
+        assert_eq!(regs.ctrl, 0x00);
+        assert_eq!(regs.mask, 0x00);
+        assert_eq!(regs.x, 0);
+        assert!(!regs.w);
+
+        ppu.write_byte(0x2000, 0x80);
+        assert_eq!(ppu.registers().ctrl, 0x00);
+
+        // The latch is cleared at dot 1 of the pre-render scanline
+        ppu.tick();
+        ppu.tick();
+
+        ppu.write_byte(0x2000, 0x80);
+        assert_eq!(ppu.registers().ctrl, 0x80);
+    }
+
     #[test]
     fn scanline_state() {
-        assert_eq!(Scanline::from(261), Scanline::PreRender);
-        assert_eq!(Scanline::from(0), Scanline::Visible);
-        assert_eq!(Scanline::from(239), Scanline::Visible);
-        assert_eq!(Scanline::from(240), Scanline::PostRender);
-        assert_eq!(Scanline::from(241), Scanline::VBlank);
-        assert_eq!(Scanline::from(260), Scanline::VBlank);
+        assert_eq!(Scanline::from(261, Region::Ntsc), Scanline::PreRender);
+        assert_eq!(Scanline::from(0, Region::Ntsc), Scanline::Visible);
+        assert_eq!(Scanline::from(239, Region::Ntsc), Scanline::Visible);
+        assert_eq!(Scanline::from(240, Region::Ntsc), Scanline::PostRender);
+        assert_eq!(Scanline::from(241, Region::Ntsc), Scanline::VBlank);
+        assert_eq!(Scanline::from(260, Region::Ntsc), Scanline::VBlank);
+    }
+
+    #[test]
+    fn scanline_state_pal() {
+        assert_eq!(Scanline::from(240, Region::Pal), Scanline::PostRender);
+        assert_eq!(Scanline::from(241, Region::Pal), Scanline::VBlank);
+        assert_eq!(Scanline::from(310, Region::Pal), Scanline::VBlank);
+        assert_eq!(Scanline::from(311, Region::Pal), Scanline::PreRender);
+    }
+
+    #[test]
+    fn scanline_state_dendy() {
+        assert_eq!(Scanline::from(290, Region::Dendy), Scanline::PostRender);
+        assert_eq!(Scanline::from(291, Region::Dendy), Scanline::VBlank);
+        assert_eq!(Scanline::from(311, Region::Dendy), Scanline::PreRender);
     }
 
     #[test]
     #[should_panic]
     fn scanline_state_invalid() {
-        Scanline::from(262);
+        Scanline::from(262, Region::Ntsc);
+    }
+
+    #[test]
+    fn odd_frame_skip() {
+        let mut ppu = init_ppu();
+        let frame = Region::Ntsc.ppu_cycles_per_frame();
+
+        // Rendering disabled, frames are the full length
+        for _ in 0..(frame * 2) {
+            ppu.tick();
+        }
+        assert_eq!(ppu.position(), (261, 0));
+
+        // With rendering enabled, every other frame is one dot shorter
+        ppu.write_byte(0x2001, 0x08);
+
+        for _ in 0..frame {
+            ppu.tick();
+        }
+        assert_eq!(ppu.position(), (261, 0));
+
+        for _ in 0..(frame - 1) {
+            ppu.tick();
+        }
+        assert_eq!(ppu.position(), (261, 0));
+    }
+
+    #[test]
+    fn pal_no_odd_frame_skip() {
+        let mut ppu = init_ppu();
+        ppu.set_region(Region::Pal);
+        ppu.write_byte(0x2001, 0x08);
+
+        for _ in 0..(Region::Pal.ppu_cycles_per_frame() * 2) {
+            ppu.tick();
+        }
+        assert_eq!(ppu.position(), (311, 0));
     }
 
     #[test]
     fn scanline_transition() {
         let mut ppu = init_ppu();
-        assert_eq!(ppu.scanline, NUM_SCANLINES - 1);
+        assert_eq!(ppu.scanline, 261);
 
         for _ in 0..341 {
             ppu.tick();
@@ -1267,13 +1822,115 @@ mod tests {
         assert_eq!(ppu.scanline, 0);
     }
 
-    fn ppu_rgb(ppu: &Ppu<FakeBus>, color: usize) -> (u8, u8, u8) {
-        let idx = color * 3;
-        (
-            ppu.rgb_palette[idx],
-            ppu.rgb_palette[idx + 1],
-            ppu.rgb_palette[idx + 2]
-        )
+    #[test]
+    fn sprite_overflow_flag() {
+        let mut ppu = init_ppu();
+
+        // Enable sprites
+        ppu.write_byte(0x2001, 0x10);
+
+        // Nine sprites on scanline 0, the rest off screen
+        for i in 0..=255u8 {
+            let value = if i < 9 * 4 { 0x00 } else { 0xFF };
+            ppu.write_oam(i, value);
+        }
+
+        // Run the pre-render scanline. Sprite evaluation does not occur
+        for _ in 0..CYCLES_PER_SCANLINE {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_byte(0x2002) & 0x20, 0);
+
+        // Run scanline 0 through sprite evaluation
+        for _ in 0..=256 {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_byte(0x2002) & 0x20, 0x20);
+
+        // The flag is cleared on the pre-render scanline
+        while ppu.scanline != 261 || ppu.cycle != 2 {
+            ppu.tick();
+        }
+        assert_eq!(ppu.read_byte(0x2002) & 0x20, 0);
+    }
+
+    #[test]
+    fn no_sprite_limit() {
+        let render_scanline = |sprite_limit: bool| {
+            let mut ppu = init_ppu();
+            ppu.set_sprite_limit(sprite_limit);
+
+            // Enable sprites
+            ppu.write_byte(0x2001, 0x14);
+
+            // Nine sprites on scanline 1, side by side
+            for i in 0..=255u8 {
+                ppu.write_oam(i, 0xFF);
+            }
+            for n in 0..9u8 {
+                let oam_data: [u8; 4] = [0x00, 0x01, 0x00, n * 8];
+                for (i, oam_byte) in oam_data.iter().enumerate() {
+                    ppu.write_oam(n * 4 + i as u8, *oam_byte);
+                }
+            }
+
+            ppu.write_vram(0x0010, 0x80);
+            ppu.write_vram(0x3F11, 0x01);
+
+            // Pre-render scanline and scanline 0
+            for _ in 0..(CYCLES_PER_SCANLINE * 2) {
+                ppu.tick();
+            }
+
+            let pixels = (0..CYCLES_PER_SCANLINE).filter_map(|_| ppu.tick()).collect::<Vec<_>>();
+            let overflow = ppu.read_byte(0x2002) & 0x20 != 0;
+
+            (pixels, overflow)
+        };
+
+        let (pixels, overflow) = render_scanline(true);
+        assert_eq!(pixels[56], 0x01);
+        assert_ne!(pixels[64], 0x01);
+        assert!(overflow);
+
+        let (pixels, overflow) = render_scanline(false);
+        assert_eq!(pixels[56], 0x01);
+        assert_eq!(pixels[64], 0x01);
+        assert!(overflow);
+    }
+
+    #[test]
+    fn save_state_round_trip() {
+        let mut ppu = init_ppu();
+
+        // Enable NMI, background and sprite rendering
+        ppu.write_byte(0x2000, 0x80);
+        ppu.write_byte(0x2001, 0x18);
+        ppu.write_byte(0x2003, 0x00);
+        for i in 0..8u8 {
+            ppu.write_byte(0x2004, i * 8);
+            ppu.write_byte(0x2004, i);
+            ppu.write_byte(0x2004, 0x00);
+            ppu.write_byte(0x2004, i * 16);
+        }
+
+        for _ in 0..(CYCLES_PER_SCANLINE * 10 + 123) {
+            ppu.tick();
+        }
+
+        let mut writer = StateWriter::default();
+        ppu.save_state(&mut writer);
+        let data = writer.into_inner();
+
+        let mut restored = init_ppu();
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+
+        for _ in 0..Region::Ntsc.ppu_cycles_per_frame() {
+            assert_eq!(restored.tick(), ppu.tick());
+        }
+
+        assert_eq!(restored.scanline, ppu.scanline);
+        assert_eq!(restored.cycle, ppu.cycle);
     }
 
     struct FakeBus {
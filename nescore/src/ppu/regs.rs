@@ -64,7 +64,6 @@ impl Register<u8> for PpuCtrl {
 /// PPU Status
 #[derive(Default)]
 pub struct PpuStatus {
-    pub lsb: u8,               // Least significant bits of the previous write to a PPU register
     pub sprite_overflow: bool, //
     pub sprite0_hit: bool,     // Set when a non-zero pixel of sprite 0 overlaps a nonzero background pixel.
     pub vblank: bool,          // Set when PPU enters vertical blanking period
@@ -72,8 +71,7 @@ pub struct PpuStatus {
 
 impl Register<u8> for PpuStatus {
     fn value(&self) -> u8 {
-        (self.lsb & 0x1F)
-        | (self.sprite_overflow as u8) << 5
+        (self.sprite_overflow as u8) << 5
         | (self.sprite0_hit as u8) << 6
         | (self.vblank as u8) << 7
     }
@@ -320,14 +318,6 @@ mod tests {
         assert!(mask_is_set!(status.value(), 0x40));
     }
 
-    #[test]
-    fn ppustatus_lsb() {
-        let mut status = PpuStatus::default();
-        status.lsb = 0xFF;
-
-        assert_eq!(status.value(), 0x1F);
-    }
-
     #[test]
     fn ppumask() {
         let mut mask = PpuMask::default();
@@ -0,0 +1,79 @@
+//
+// ppu/signal.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 27 2021
+//
+// Model of the NTSC composite signal generated by the PPU
+// https://wiki.nesdev.com/w/index.php/NTSC_video
+//
+
+use super::{Pixel, IndexedPixel};
+
+use std::f64::consts::PI;
+
+/// Number of phases in one cycle of the color subcarrier
+pub const PHASES: usize = 12;
+/// The PPU outputs 8 signal samples per pixel
+pub const SAMPLES_PER_PIXEL: usize = 8;
+/// Signal level during the phases of an emphasized color channel
+pub const EMPHASIS_ATTENUATION: f64 = 0.746;
+
+/// Composite voltage levels for the low and high parts of the wave at each luminance
+const SIGNAL_LEVELS: [f64; 8] = [0.350, 0.518, 0.962, 1.550, 1.094, 1.506, 1.962, 1.962];
+const SIGNAL_BLACK: f64 = 0.518;
+const SIGNAL_WHITE: f64 = 1.962;
+/// Phase of the color burst relative to color $x0
+const BURST_PHASE: f64 = 4.0;
+
+/// Hue offset in degrees of the 2C07's color phases relative to the 2C02
+///
+/// The 2C07 inverts V on alternate lines and PAL TVs average each pair of lines. This cancels phase errors, so
+/// the colors decode as they would on the 2C02 with this offset
+/// https://wiki.nesdev.com/w/index.php/PAL_video
+pub const PAL_HUE_OFFSET: f64 = -15.0;
+const GAMMA: f64 = 2.2 / 1.8;
+
+/// Signal level of the pixel at one of the 12 phases of the color subcarrier. Black is 0.0 and white is 1.0
+pub fn level(pixel: IndexedPixel, phase: usize) -> f64 {
+    let color = (pixel & 0x0F) as usize;
+    let emphasis = (pixel >> 6) as usize;
+    // Colors $xE and $xF output black
+    let luma = if color > 0x0D { 1 } else { ((pixel >> 4) & 0x03) as usize };
+
+    let high = if color > 0x0C { SIGNAL_LEVELS[luma] } else { SIGNAL_LEVELS[luma + 4] };
+    let low = if color == 0x00 { high } else { SIGNAL_LEVELS[luma] };
+
+    let in_phase = |color: usize| (color + phase) % PHASES < 6;
+
+    let level = if in_phase(color) { high } else { low };
+
+    // Emphasis bits attenuate the signal during the red, green and blue phases
+    let attenuate = (bit_is_set!(emphasis, 0) && in_phase(0))
+                 || (bit_is_set!(emphasis, 1) && in_phase(4))
+                 || (bit_is_set!(emphasis, 2) && in_phase(8));
+
+    let level = if attenuate { level * EMPHASIS_ATTENUATION } else { level };
+
+    (level - SIGNAL_BLACK) / (SIGNAL_WHITE - SIGNAL_BLACK)
+}
+
+/// Reference carrier used to demodulate the I and Q components at the given phase. Returns (cos, sin)
+pub fn carrier(phase: usize, hue: f64) -> (f64, f64) {
+    let angle = PI * (phase as f64 + BURST_PHASE) / 6.0 + hue.to_radians();
+    (angle.cos(), angle.sin())
+}
+
+/// Convert a decoded YIQ color to gamma corrected RGB
+pub fn yiq_to_rgb(y: f64, i: f64, q: f64) -> Pixel {
+    let r = y + 0.946882 * i + 0.623557 * q;
+    let g = y - 0.274788 * i - 0.635691 * q;
+    let b = y - 1.108545 * i + 1.709007 * q;
+
+    (gamma_correct(r), gamma_correct(g), gamma_correct(b))
+}
+
+fn gamma_correct(value: f64) -> u8 {
+    let value = if value <= 0.0 { 0.0 } else { value.powf(GAMMA) };
+    (value * 255.0).min(255.0) as u8
+}
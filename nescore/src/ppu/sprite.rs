@@ -5,6 +5,8 @@
 // @date Mar 07 2020
 //
 
+use crate::state::{Snapshot, StateWriter, StateReader, StateError};
+
 #[derive(Default, Copy, Clone)]
 pub struct Sprite {
     pub y: u16,
@@ -50,6 +52,238 @@ impl Sprite {
     }
 }
 
+impl Snapshot for Sprite {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.y);
+        state.write_u8(self.x);
+        state.write_u8(self.tile);
+        state.write_u8(self.attr);
+        state.write_u8(self.num);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.y = state.read_u16()?;
+        self.x = state.read_u8()?;
+        self.tile = state.read_u8()?;
+        self.attr = state.read_u8()?;
+        self.num = state.read_u8()?;
+
+        Ok(())
+    }
+}
+
+/// Sprite evaluation progress for the current scanline
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum EvalState {
+    Copy,        // Copying in-range sprites to secondary OAM
+    Overflow,    // Eight sprites found, checking for overflow (with the hardware m increment bug)
+    OverflowRead(u8), // Reading the remaining bytes of the overflowing sprite
+    Done,        // All sprites have been evaluated
+}
+
+/// Secondary OAM and the sprite evaluation state machine
+///
+/// Evaluation runs from dots 65 to 256. Odd dots read from primary OAM and even dots write to secondary OAM.
+/// See: https://wiki.nesdev.com/w/index.php/PPU_sprite_evaluation
+pub struct SpriteEvaluator {
+    secondary_oam: [u8; 32],
+    nums: [u8; 8],     // Primary OAM index of each sprite in secondary OAM
+    found: usize,      // Number of sprites copied into secondary OAM
+
+    n: usize,          // Sprite index in primary OAM
+    m: usize,          // Byte index in the sprite
+    latch: u8,         // Value read from primary OAM on the previous (odd) cycle
+    state: EvalState,
+}
+
+impl Default for SpriteEvaluator {
+    fn default() -> Self {
+        SpriteEvaluator {
+            secondary_oam: [0xFF; 32],
+            nums: [0; 8],
+            found: 0,
+            n: 0,
+            m: 0,
+            latch: 0,
+            state: EvalState::Copy,
+        }
+    }
+}
+
+impl SpriteEvaluator {
+    /// Run sprite evaluation for a single dot. Returns true if sprite overflow was detected
+    pub fn clock(&mut self, dot: usize, oam: &[u8; 256], scanline: u16, height: u8) -> bool {
+        match dot {
+            1..=64 => {
+                // Secondary OAM is cleared to $FF, one byte every two cycles
+                if !bit_is_set!(dot, 0) {
+                    self.secondary_oam[(dot / 2) - 1] = 0xFF;
+                }
+
+                if dot == 64 {
+                    self.found = 0;
+                    self.n = 0;
+                    self.m = 0;
+                    self.state = EvalState::Copy;
+                }
+
+                false
+            },
+            65..=256 => {
+                if bit_is_set!(dot, 0) {
+                    // Read from primary OAM
+                    self.latch = oam[(self.n * 4) + self.m];
+                    false
+                }
+                else {
+                    self.evaluate(scanline, height)
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Sprites found on the evaluated scanline
+    pub fn sprites(&self) -> [Option<Sprite>; 8] {
+        let mut sprites = [None; 8];
+
+        for (i, sprite) in sprites.iter_mut().enumerate().take(self.found) {
+            let offset = i * 4;
+            *sprite = Some(Sprite::from(&self.secondary_oam[offset..offset+4], self.nums[i]));
+        }
+
+        sprites
+    }
+
+    fn evaluate(&mut self, scanline: u16, height: u8) -> bool {
+        let in_range = {
+            let row = scanline as i16 - self.latch as i16;
+            row >= 0 && row < height as i16
+        };
+
+        match self.state {
+            EvalState::Copy => {
+                // The Y coordinate is always copied to secondary OAM. Remaining bytes only for in-range sprites
+                self.secondary_oam[(self.found * 4) + self.m] = self.latch;
+
+                if self.m == 0 && !in_range {
+                    self.next_sprite();
+                }
+                else {
+                    if self.m == 0 {
+                        self.nums[self.found] = self.n as u8;
+                    }
+
+                    self.m += 1;
+
+                    if self.m == 4 {
+                        self.m = 0;
+                        self.found += 1;
+                        self.next_sprite();
+                    }
+                }
+
+                false
+            },
+            EvalState::Overflow => {
+                if in_range {
+                    // The remaining 3 bytes of the sprite are read before evaluation stops
+                    self.state = EvalState::OverflowRead(3);
+                    self.increment_m();
+                    true
+                }
+                else {
+                    // Hardware bug: m is incremented along with n (without carry), causing a diagonal scan of OAM
+                    self.m = (self.m + 1) % 4;
+                    self.n += 1;
+
+                    if self.n == 64 {
+                        self.n = 0;
+                        self.state = EvalState::Done;
+                    }
+
+                    false
+                }
+            },
+            EvalState::OverflowRead(remaining) => {
+                self.increment_m();
+                self.state = if remaining > 1 { EvalState::OverflowRead(remaining - 1) } else { EvalState::Done };
+
+                false
+            },
+            EvalState::Done => {
+                // Attempt (and fail) to copy the Y coordinate of the next sprite
+                self.n = (self.n + 1) % 64;
+                false
+            },
+        }
+    }
+
+    fn increment_m(&mut self) {
+        self.m = (self.m + 1) % 4;
+
+        if self.m == 0 {
+            self.n = (self.n + 1) % 64;
+        }
+    }
+
+    fn next_sprite(&mut self) {
+        self.n += 1;
+
+        if self.n == 64 {
+            self.n = 0;
+            self.state = EvalState::Done;
+        }
+        else if self.found == 8 {
+            // Writes to secondary OAM are disabled once eight sprites have been found
+            self.state = EvalState::Overflow;
+        }
+    }
+}
+
+impl Snapshot for SpriteEvaluator {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.secondary_oam);
+        state.write_bytes(&self.nums);
+        state.write_usize(self.found);
+        state.write_usize(self.n);
+        state.write_usize(self.m);
+        state.write_u8(self.latch);
+        state.write_u8(match self.state {
+            EvalState::Copy => 0,
+            EvalState::Overflow => 1,
+            EvalState::OverflowRead(remaining) => 0x10 | remaining,
+            EvalState::Done => 2,
+        });
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.read_bytes(&mut self.secondary_oam)?;
+        state.read_bytes(&mut self.nums)?;
+
+        let found = state.read_usize()?;
+        let n = state.read_usize()?;
+        let m = state.read_usize()?;
+        if found > 8 || n >= 64 || m >= 4 {
+            return Err(StateError::InvalidData);
+        }
+        self.found = found;
+        self.n = n;
+        self.m = m;
+
+        self.latch = state.read_u8()?;
+        self.state = match state.read_u8()? {
+            0 => EvalState::Copy,
+            1 => EvalState::Overflow,
+            2 => EvalState::Done,
+            r @ 0x11..=0x13 => EvalState::OverflowRead(r & 0x0F),
+            _ => return Err(StateError::InvalidData),
+        };
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,4 +326,71 @@ mod tests {
         assert_eq!(sprite.pattern_table_8x16(), 0x1000);
         assert_eq!(sprite.tile_number_8x16(), 0x0E);
     }
+
+    fn run_evaluation(evaluator: &mut SpriteEvaluator, oam: &[u8; 256], scanline: u16) -> bool {
+        (1..=256).fold(false, |overflow, dot| evaluator.clock(dot, oam, scanline, 8) | overflow)
+    }
+
+    #[test]
+    fn evaluation_copies_in_range_sprites() {
+        let mut oam = [0xFFu8; 256];
+        // Sprites 3 and 10 are on scanline 20
+        oam[3 * 4..3 * 4 + 4].copy_from_slice(&[15, 0x01, 0x02, 0x03]);
+        oam[10 * 4..10 * 4 + 4].copy_from_slice(&[20, 0x04, 0x05, 0x06]);
+
+        let mut evaluator = SpriteEvaluator::default();
+        let overflow = run_evaluation(&mut evaluator, &oam, 20);
+
+        assert!(!overflow);
+
+        let sprites = evaluator.sprites();
+        assert_eq!(sprites[0].unwrap().num, 3);
+        assert_eq!(sprites[0].unwrap().tile, 0x01);
+        assert_eq!(sprites[1].unwrap().num, 10);
+        assert_eq!(sprites[1].unwrap().x, 0x06);
+        assert!(sprites[2].is_none());
+    }
+
+    #[test]
+    fn evaluation_sets_overflow() {
+        let mut oam = [0xFFu8; 256];
+        // Nine sprites on scanline 0
+        for n in 0..9 {
+            oam[n * 4] = 0;
+        }
+
+        let mut evaluator = SpriteEvaluator::default();
+        let overflow = run_evaluation(&mut evaluator, &oam, 0);
+
+        assert!(overflow);
+        assert!(evaluator.sprites().iter().all(|s| s.is_some()));
+    }
+
+    #[test]
+    fn evaluation_overflow_false_negative() {
+        let mut oam = [0xFFu8; 256];
+        // Eight sprites on scanline 0
+        for n in 0..8 {
+            oam[n * 4] = 0;
+        }
+        // Sprite 10 is on the scanline, but due to the diagonal scan its attribute byte is checked instead of its Y coordinate
+        oam[10 * 4] = 0;
+
+        let mut evaluator = SpriteEvaluator::default();
+        assert!(!run_evaluation(&mut evaluator, &oam, 0));
+    }
+
+    #[test]
+    fn evaluation_overflow_false_positive() {
+        let mut oam = [0xFFu8; 256];
+        // Eight sprites on scanline 0
+        for n in 0..8 {
+            oam[n * 4] = 0;
+        }
+        // The tile index of sprite 9 is treated as a Y coordinate
+        oam[9 * 4 + 1] = 0;
+
+        let mut evaluator = SpriteEvaluator::default();
+        assert!(run_evaluation(&mut evaluator, &oam, 0));
+    }
 }
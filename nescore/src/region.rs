@@ -0,0 +1,172 @@
+//
+// region.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 14 2021
+//
+
+use crate::cart::{CartridgeInfo, Format};
+use std::fmt;
+
+/// Console region. Determines the system timing
+///
+/// https://wiki.nesdev.com/w/index.php/Cycle_reference_chart
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Region {
+    /// NTSC (RP2A03/RP2C02)
+    #[default]
+    Ntsc,
+    /// PAL (RP2A07/RP2C07)
+    Pal,
+    /// Dendy famiclone (UA6527P/UA6538)
+    Dendy,
+}
+
+impl Region {
+    /// Detect the region from the cartridge header. Multi-region cartridges run as NTSC
+    pub fn from_cartridge(info: &CartridgeInfo) -> Self {
+        match info.format {
+            Format::NES2 => match info.tv_system_ext {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            },
+            _ => if info.tv_system_pal { Region::Pal } else { Region::Ntsc },
+        }
+    }
+
+    /// Master clock frequency in Hz
+    pub fn master_clock_rate(&self) -> f64 {
+        match *self {
+            Region::Ntsc => 236.25e6 / 11.0,
+            Region::Pal | Region::Dendy => 26_601_712.5,
+        }
+    }
+
+    /// CPU clock frequency in Hz
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock_rate() / self.cpu_divider() as f64
+    }
+
+    /// Frames per second
+    pub fn frame_rate(&self) -> f64 {
+        // NTSC frames are half a dot shorter on average, as the idle dot is skipped on odd frames
+        let dots = if self.skips_idle_dot() {
+            self.ppu_cycles_per_frame() as f64 - 0.5
+        }
+        else {
+            self.ppu_cycles_per_frame() as f64
+        };
+
+        self.master_clock_rate() / (dots * self.ppu_divider() as f64)
+    }
+
+    /// Rate of the raw APU output. The APU produces a sample every other CPU cycle
+    pub fn apu_output_rate(&self) -> f32 {
+        (self.cpu_clock_rate() / 2.0) as f32
+    }
+
+    /// Number of PPU cycles in a frame
+    pub fn ppu_cycles_per_frame(&self) -> usize {
+        self.scanlines() * crate::ppu::CYCLES_PER_SCANLINE
+    }
+
+    /// Master clock cycles per CPU cycle
+    pub(crate) fn cpu_divider(&self) -> u32 {
+        match *self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clock cycles per PPU cycle
+    pub(crate) fn ppu_divider(&self) -> u32 {
+        match *self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    /// Number of scanlines per frame, including the pre-render scanline
+    pub(crate) fn scanlines(&self) -> usize {
+        match *self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// The scanline the vblank flag is set on
+    pub(crate) fn vblank_scanline(&self) -> usize {
+        match *self {
+            Region::Ntsc | Region::Pal => 241,
+            // Dendy has a longer post-render period and the same length of vblank as NTSC
+            Region::Dendy => 291,
+        }
+    }
+
+    /// The PPU skips the idle dot at the end of the pre-render scanline on odd frames when rendering is enabled
+    pub(crate) fn skips_idle_dot(&self) -> bool {
+        *self == Region::Ntsc
+    }
+
+    /// The PPU swaps the red and green emphasis bits
+    pub(crate) fn swaps_emphasis(&self) -> bool {
+        *self != Region::Ntsc
+    }
+
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+            Region::Ntsc => 0,
+            Region::Pal => 1,
+            Region::Dendy => 2,
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Region::Ntsc => write!(f, "NTSC"),
+            Region::Pal => write!(f, "PAL"),
+            Region::Dendy => write!(f, "Dendy"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_rates() {
+        assert!((Region::Ntsc.frame_rate() - 60.0988).abs() < 0.0001);
+        assert!((Region::Pal.frame_rate() - 50.0070).abs() < 0.0001);
+        assert!((Region::Dendy.frame_rate() - 50.0070).abs() < 0.0001);
+    }
+
+    #[test]
+    fn cpu_clock_rates() {
+        assert_eq!(Region::Ntsc.cpu_clock_rate().round(), 1_789_773.0);
+        assert_eq!(Region::Pal.cpu_clock_rate().round(), 1_662_607.0);
+        assert_eq!(Region::Dendy.cpu_clock_rate().round(), 1_773_448.0);
+    }
+
+    #[test]
+    fn from_cartridge() {
+        let mut header = [0u8; 16];
+        header[0..4].copy_from_slice(b"NES\x1A");
+
+        // iNES flag 9
+        header[9] = 0x01;
+        assert_eq!(Region::from_cartridge(&CartridgeInfo::from(&header[..]).unwrap()), Region::Pal);
+
+        // NES 2.0 byte 12
+        header[7] = 0x08;
+        header[9] = 0x00;
+        for &(timing, region) in [(0, Region::Ntsc), (1, Region::Pal), (2, Region::Ntsc), (3, Region::Dendy)].iter() {
+            header[12] = timing;
+            assert_eq!(Region::from_cartridge(&CartridgeInfo::from(&header[..]).unwrap()), region);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//
+// rewind.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 15 2021
+//
+
+use std::collections::VecDeque;
+
+/// Bounded history of save states used to rewind the system
+///
+/// The most recent snapshot is kept in full. Older snapshots are stored as compressed deltas against the next newer
+/// snapshot, so restoring walks backwards from the most recent snapshot. Consecutive frames differ very little, which
+/// keeps each delta small
+pub struct RewindBuffer {
+    capacity: usize,                    // Maximum number of snapshots
+    interval: usize,                    // Number of frames between snapshots
+    latest: Option<(u64, Vec<u8>)>,     // Most recent snapshot and the frame it was taken on
+    history: VecDeque<(u64, Vec<u8>)>,  // Older snapshots as deltas. The back is the newest
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        RewindBuffer {
+            capacity: capacity.max(1),
+            interval: interval.max(1),
+            latest: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Check if a snapshot should be taken on the given frame
+    pub fn should_capture(&self, frame: u64) -> bool {
+        frame.is_multiple_of(self.interval as u64) && self.latest.as_ref().is_none_or(|(f, _)| *f != frame)
+    }
+
+    /// Add a snapshot taken on the given frame
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        if let Some((prev_frame, prev_state)) = self.latest.take() {
+            let delta = helpers::encode(&prev_state, &state);
+            self.history.push_back((prev_frame, delta));
+        }
+
+        self.latest = Some((frame, state));
+
+        while self.len() > self.capacity {
+            self.history.pop_front();
+        }
+    }
+
+    /// Find the newest snapshot taken on or before `frame`. The buffer is not modified
+    ///
+    /// If no snapshot is old enough, the oldest snapshot is returned
+    pub fn find(&self, frame: u64) -> Option<(u64, Vec<u8>)> {
+        let (latest_frame, latest_state) = self.latest.as_ref()?;
+
+        let mut found = (*latest_frame, latest_state.clone());
+
+        for (prev_frame, delta) in self.history.iter().rev() {
+            if found.0 <= frame {
+                break;
+            }
+
+            found = (*prev_frame, helpers::decode(&found.1, delta));
+        }
+
+        Some(found)
+    }
+
+    /// Make a snapshot returned by `RewindBuffer::find` the most recent, discarding newer snapshots
+    pub fn truncate(&mut self, frame: u64, state: Vec<u8>) {
+        while self.history.back().is_some_and(|(f, _)| *f >= frame) {
+            self.history.pop_back();
+        }
+
+        self.latest = Some((frame, state));
+    }
+
+    /// Number of snapshots in the buffer
+    pub fn len(&self) -> usize {
+        self.history.len() + self.latest.is_some() as usize
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.history.clear();
+    }
+}
+
+mod helpers {
+    //! A delta is the XOR of two states, run length encoded
+    //!
+    //! Layout: target length, then repeated (zero run length, literal length, literal bytes). Lengths are LEB128
+
+    pub fn encode(target: &[u8], base: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_len(&mut out, target.len());
+
+        let xor: Vec<u8> = target.iter().enumerate().map(|(i, b)| b ^ base.get(i).copied().unwrap_or(0)).collect();
+
+        let mut i = 0;
+        while i < xor.len() {
+            let zeros = xor[i..].iter().take_while(|b| **b == 0).count();
+            i += zeros;
+
+            let literals = xor[i..].iter().take_while(|b| **b != 0).count();
+
+            write_len(&mut out, zeros);
+            write_len(&mut out, literals);
+            out.extend_from_slice(&xor[i..i + literals]);
+
+            i += literals;
+        }
+
+        out
+    }
+
+    pub fn decode(base: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let len = read_len(delta, &mut pos);
+
+        let mut target: Vec<u8> = (0..len).map(|i| base.get(i).copied().unwrap_or(0)).collect();
+
+        let mut i = 0;
+        while pos < delta.len() {
+            i += read_len(delta, &mut pos);
+
+            let literals = read_len(delta, &mut pos);
+            for b in &delta[pos..pos + literals] {
+                target[i] ^= b;
+                i += 1;
+            }
+
+            pos += literals;
+        }
+
+        target
+    }
+
+    fn write_len(out: &mut Vec<u8>, mut len: usize) {
+        loop {
+            let byte = (len & 0x7F) as u8;
+            len >>= 7;
+
+            if len == 0 {
+                out.push(byte);
+                break;
+            }
+
+            out.push(byte | 0x80);
+        }
+    }
+
+    fn read_len(data: &[u8], pos: &mut usize) -> usize {
+        let mut len = 0usize;
+        let mut shift = 0;
+
+        loop {
+            let byte = data[*pos];
+            *pos += 1;
+
+            len |= ((byte & 0x7F) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break len;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_round_trip() {
+        let base = vec![0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut target = base.clone();
+        target[2] = 0xDE;
+        target[3] = 0xAD;
+        target[9] = 0xFF;
+
+        let delta = helpers::encode(&target, &base);
+        assert_eq!(helpers::decode(&base, &delta), target);
+    }
+
+    #[test]
+    fn delta_different_lengths() {
+        let base = vec![1u8; 4];
+        let target = vec![2u8; 300];
+
+        let delta = helpers::encode(&target, &base);
+        assert_eq!(helpers::decode(&base, &delta), target);
+
+        let delta = helpers::encode(&base, &target);
+        assert_eq!(helpers::decode(&target, &delta), base);
+    }
+
+    #[test]
+    fn delta_is_compact() {
+        let base = vec![0x55u8; 4096];
+        let mut target = base.clone();
+        target[100] = 0;
+
+        let delta = helpers::encode(&target, &base);
+        assert!(delta.len() < 16);
+    }
+
+    #[test]
+    fn rewind_to_frame() {
+        let mut buffer = RewindBuffer::new(10, 2);
+
+        for frame in (0..10).step_by(2) {
+            buffer.push(frame, vec![frame as u8; 8]);
+        }
+
+        assert_eq!(buffer.len(), 5);
+        assert_eq!(buffer.find(5), Some((4, vec![4u8; 8])));
+        // Finding a snapshot does not discard history
+        assert_eq!(buffer.len(), 5);
+
+        buffer.truncate(4, vec![4u8; 8]);
+        assert_eq!(buffer.len(), 3);
+
+        assert_eq!(buffer.find(0), Some((0, vec![0u8; 8])));
+        buffer.truncate(0, vec![0u8; 8]);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut buffer = RewindBuffer::new(3, 1);
+
+        for frame in 0..10 {
+            buffer.push(frame, vec![frame as u8; 8]);
+        }
+
+        assert_eq!(buffer.len(), 3);
+        // Not enough history, the oldest snapshot is returned
+        assert_eq!(buffer.find(0), Some((7, vec![7u8; 8])));
+    }
+
+    #[test]
+    fn should_capture() {
+        let mut buffer = RewindBuffer::new(3, 2);
+
+        assert!(buffer.should_capture(0));
+        assert!(!buffer.should_capture(1));
+
+        buffer.push(2, vec![]);
+        assert!(!buffer.should_capture(2));
+        assert!(buffer.should_capture(4));
+    }
+
+    #[test]
+    fn empty() {
+        let buffer = RewindBuffer::new(3, 2);
+        assert_eq!(buffer.find(0), None);
+    }
+}
@@ -0,0 +1,219 @@
+//
+// state.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 09 2021
+//
+
+use std::fmt;
+use std::error::Error;
+
+/// Identifies a nescore save state
+pub const STATE_MAGIC: [u8; 4] = *b"NESS";
+/// Save state format version. Bump when the layout of any component changes
+pub const STATE_VERSION: u32 = 13;
+
+/// Error loading a save state
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    UnexpectedEnd,
+    InvalidData,
+    TrailingData(usize),
+    NoCartridge,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::InvalidMagic => write!(f, "Not a save state"),
+            StateError::UnsupportedVersion(v) =>
+                write!(f, "Unsupported save state version {}. Expected {}", v, STATE_VERSION),
+            StateError::UnexpectedEnd => write!(f, "Save state is truncated"),
+            StateError::InvalidData => write!(f, "Save state contains invalid data"),
+            StateError::TrailingData(n) => write!(f, "Save state has {} bytes of unexpected data at the end", n),
+            StateError::NoCartridge => write!(f, "No cartridge is inserted"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// A component that can save and restore its internal state
+pub trait Snapshot {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Serializes component state. All values are little endian
+#[derive(Default)]
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// `usize` is always stored as 64 bits so save states are portable
+    pub fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Deserializes component state written by `StateWriter`
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader {
+            data,
+            pos: 0,
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        let mut buf = [0u8; 1];
+        self.read_bytes(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        let mut buf = [0u8; 2];
+        self.read_bytes(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        let mut buf = [0u8; 4];
+        self.read_bytes(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        let mut buf = [0u8; 8];
+        self.read_bytes(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize, StateError> {
+        let value = self.read_u64()?;
+        if value > usize::MAX as u64 {
+            return Err(StateError::InvalidData);
+        }
+
+        Ok(value as usize)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidData),
+        }
+    }
+
+    /// Fill `buf` with the next `buf.len()` bytes
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> Result<(), StateError> {
+        let end = self.pos + buf.len();
+        if end > self.data.len() {
+            return Err(StateError::UnexpectedEnd);
+        }
+
+        buf.copy_from_slice(&self.data[self.pos..end]);
+        self.pos = end;
+
+        Ok(())
+    }
+
+    /// Number of bytes that have not been read
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut writer = StateWriter::default();
+        writer.write_u8(0xDE);
+        writer.write_u16(0xBEEF);
+        writer.write_u32(0xDEADBEEF);
+        writer.write_u64(0x0123456789ABCDEF);
+        writer.write_usize(42);
+        writer.write_bool(true);
+        writer.write_bytes(&[1, 2, 3]);
+
+        let data = writer.into_inner();
+        let mut reader = StateReader::new(&data);
+
+        assert_eq!(reader.read_u8().unwrap(), 0xDE);
+        assert_eq!(reader.read_u16().unwrap(), 0xBEEF);
+        assert_eq!(reader.read_u32().unwrap(), 0xDEADBEEF);
+        assert_eq!(reader.read_u64().unwrap(), 0x0123456789ABCDEF);
+        assert_eq!(reader.read_usize().unwrap(), 42);
+        assert!(reader.read_bool().unwrap());
+
+        let mut bytes = [0u8; 3];
+        reader.read_bytes(&mut bytes).unwrap();
+        assert_eq!(bytes, [1, 2, 3]);
+
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn little_endian() {
+        let mut writer = StateWriter::default();
+        writer.write_u16(0x1234);
+
+        assert_eq!(writer.into_inner(), vec![0x34, 0x12]);
+    }
+
+    #[test]
+    fn unexpected_end() {
+        let data = [0x00u8];
+        let mut reader = StateReader::new(&data);
+
+        assert_eq!(reader.read_u16(), Err(StateError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn invalid_bool() {
+        let data = [0x02u8];
+        let mut reader = StateReader::new(&data);
+
+        assert_eq!(reader.read_bool(), Err(StateError::InvalidData));
+    }
+}
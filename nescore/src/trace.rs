@@ -0,0 +1,296 @@
+//
+// trace.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 05 2021
+//
+
+//! CPU trace logging in formats used by reference emulators
+//!
+//! ```no_run
+//! # use nescore::{Nes, Cartridge};
+//! # use nescore::trace::{TraceLogger, TraceFormat};
+//! # let cart = Cartridge::from_path("/path/to/rom").unwrap();
+//! let file = std::fs::File::create("trace.log").unwrap();
+//!
+//! let mut nes = Nes::from(cart).trace_logger(TraceLogger::new(file, TraceFormat::Nestest));
+//! nes.emulate_frame();
+//! ```
+
+use crate::Nes;
+use crate::asm::{self, Instruction, AddressingMode};
+
+use std::io::{self, Write};
+
+/// Trace line format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    /// Nintendulator format used by `nestest.log`
+    ///
+    /// `C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7`
+    Nestest,
+    /// Mesen's trace logger format
+    ///
+    /// `C000  $4C $F5 $C5  JMP $C5F5                   A:00 X:00 Y:00 S:FD P:nvUbdIzc  V:0   H:21  Fr:0   Cycle:7`
+    Mesen,
+}
+
+/// CPU state at the start of an instruction
+pub struct TraceState {
+    pub pc: u16,
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub p: u8,
+    pub sp: u8,
+    pub scanline: usize,
+    pub dot: usize,
+    pub frame: u64,
+    pub cycles: u64,
+}
+
+/// Writes a line for each instruction executed
+pub struct TraceLogger {
+    writer: Box<dyn Write>,
+    format: TraceFormat,
+}
+
+impl TraceLogger {
+    pub fn new<W: Write + 'static>(writer: W, format: TraceFormat) -> Self {
+        TraceLogger {
+            writer: Box::new(writer),
+            format,
+        }
+    }
+
+    /// Log the instruction the CPU is about to execute
+    pub fn trace(&mut self, nes: &Nes, state: &TraceState) -> io::Result<()> {
+        let line = match self.format {
+            TraceFormat::Nestest => format_nestest(state, |addr| peek(nes, addr)),
+            TraceFormat::Mesen => format_mesen(state, |addr| peek(nes, addr)),
+        };
+
+        writeln!(self.writer, "{}", line)
+    }
+}
+
+/// Read memory without triggering side effects of reading IO registers
+fn peek(nes: &Nes, addr: u16) -> Option<u8> {
+    if (0x2000..=0x401F).contains(&addr) {
+        None
+    }
+    else {
+        Some(nes.read_cpu_ram(addr))
+    }
+}
+
+pub fn format_nestest<F: Fn(u16) -> Option<u8>>(state: &TraceState, read: F) -> String {
+    let (opcode, bytes) = instruction_bytes(state.pc, &read);
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+
+    let (prefix, disassembly) = disassemble(state, opcode, &read);
+
+    format!("{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+            state.pc, bytes.join(" "), prefix, disassembly,
+            state.a, state.x, state.y, state.p, state.sp,
+            state.scanline, state.dot, state.cycles)
+}
+
+pub fn format_mesen<F: Fn(u16) -> Option<u8>>(state: &TraceState, read: F) -> String {
+    let (opcode, bytes) = instruction_bytes(state.pc, &read);
+    let bytes: Vec<String> = bytes.iter().map(|b| format!("${:02X}", b)).collect();
+
+    let (prefix, disassembly) = disassemble(state, opcode, &read);
+    let disassembly = format!("{}{}", prefix.trim(), disassembly);
+
+    format!("{:04X}  {:<11}  {:<28}A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{}  V:{:<3} H:{:<3} Fr:{:<3} Cycle:{}",
+            state.pc, bytes.join(" "), disassembly,
+            state.a, state.x, state.y, state.sp, mesen_flags(state.p),
+            state.scanline, state.dot, state.frame, state.cycles)
+}
+
+/// The opcode and all bytes of the instruction at `pc`
+fn instruction_bytes<F: Fn(u16) -> Option<u8>>(pc: u16, read: &F) -> (u8, Vec<u8>) {
+    let opcode = read(pc).unwrap_or(0);
+    let len = asm::decode(opcode).1.operand_len();
+
+    let bytes = (0..=len).map(|i| read(pc.wrapping_add(i as u16)).unwrap_or(0)).collect();
+
+    (opcode, bytes)
+}
+
+/// Disassemble the instruction, resolving effective addresses and the values at them
+///
+/// Returns a prefix marking unofficial opcodes and the disassembly
+fn disassemble<F: Fn(u16) -> Option<u8>>(state: &TraceState, opcode: u8, read: &F) -> (&'static str, String) {
+    let (instr, mode) = asm::decode(opcode);
+
+    let prefix = if is_official(instr, opcode) { " " } else { "*" };
+
+    let byte = read(state.pc.wrapping_add(1)).unwrap_or(0);
+    let word = u16::from_le_bytes([byte, read(state.pc.wrapping_add(2)).unwrap_or(0)]);
+
+    let read_word_zp = |addr: u8| {
+        let lo = read(addr as u16).unwrap_or(0);
+        let hi = read(addr.wrapping_add(1) as u16).unwrap_or(0);
+        u16::from_le_bytes([lo, hi])
+    };
+
+    // Value at the effective address
+    let value = |addr: u16| match read(addr) {
+        Some(value) => format!(" = {:02X}", value),
+        None => String::new(),
+    };
+
+    let operand = match mode {
+        AddressingMode::Implied => String::new(),
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Immediate => format!("#${:02X}", byte),
+        AddressingMode::ZeroPage => format!("${:02X}{}", byte, value(byte as u16)),
+        AddressingMode::ZeroPageX => {
+            let addr = byte.wrapping_add(state.x);
+            format!("${:02X},X @ {:02X}{}", byte, addr, value(addr as u16))
+        },
+        AddressingMode::ZeroPageY => {
+            let addr = byte.wrapping_add(state.y);
+            format!("${:02X},Y @ {:02X}{}", byte, addr, value(addr as u16))
+        },
+        AddressingMode::Absolute => match instr {
+            Instruction::JMP | Instruction::JSR => format!("${:04X}", word),
+            _ => format!("${:04X}{}", word, value(word)),
+        },
+        AddressingMode::AbsoluteX => {
+            let addr = word.wrapping_add(state.x as u16);
+            format!("${:04X},X @ {:04X}{}", word, addr, value(addr))
+        },
+        AddressingMode::AbsoluteY => {
+            let addr = word.wrapping_add(state.y as u16);
+            format!("${:04X},Y @ {:04X}{}", word, addr, value(addr))
+        },
+        AddressingMode::Indirect => {
+            // The high byte is not fetched across pages
+            let hi_addr = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+            let lo = read(word).unwrap_or(0);
+            let hi = read(hi_addr).unwrap_or(0);
+            format!("(${:04X}) = {:04X}", word, u16::from_le_bytes([lo, hi]))
+        },
+        AddressingMode::IndexedIndirect => {
+            let ptr = byte.wrapping_add(state.x);
+            let addr = read_word_zp(ptr);
+            format!("(${:02X},X) @ {:02X} = {:04X}{}", byte, ptr, addr, value(addr))
+        },
+        AddressingMode::IndirectIndexed => {
+            let base = read_word_zp(byte);
+            let addr = base.wrapping_add(state.y as u16);
+            format!("(${:02X}),Y = {:04X} @ {:04X}{}", byte, base, addr, value(addr))
+        },
+        AddressingMode::Relative => {
+            let target = state.pc.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${:04X}", target)
+        },
+    };
+
+    let disassembly = if operand.is_empty() {
+        format!("{:?}", instr)
+    }
+    else {
+        format!("{:?} {}", instr, operand)
+    };
+
+    (prefix, disassembly)
+}
+
+/// Check if the opcode is part of the documented 6502 instruction set
+fn is_official(instr: Instruction, opcode: u8) -> bool {
+    match instr {
+        Instruction::LAX | Instruction::SAX | Instruction::DCP | Instruction::ISB | Instruction::SLO
+        | Instruction::RLA | Instruction::RRA | Instruction::SRE | Instruction::ANC | Instruction::ALR
+        | Instruction::ARR | Instruction::AXS | Instruction::SHY | Instruction::SHX | Instruction::SHA
+        | Instruction::TAS | Instruction::LAS | Instruction::XAA | Instruction::LXA | Instruction::JAM => false,
+        Instruction::NOP => opcode == 0xEA,
+        Instruction::SBC => opcode != 0xEB,
+        _ => true,
+    }
+}
+
+/// Flags as `NV-BDIZC`, upper case when set
+fn mesen_flags(p: u8) -> String {
+    "nvubdizc".chars().enumerate().map(|(i, c)| {
+        if p & (0x80 >> i) != 0 { c.to_ascii_uppercase() } else { c }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nestest_absolute_jump() {
+        let line = format_nestest(&init_state(0xC000, 0xFD, 7, 21), read_from(0xC000, &[0x4C, 0xF5, 0xC5]));
+        assert_eq!(line, "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7");
+    }
+
+    #[test]
+    fn nestest_zero_page() {
+        let line = format_nestest(&init_state(0xC5F7, 0xFD, 12, 36), read_from(0xC5F7, &[0x86, 0x00]));
+        assert_eq!(line, "C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 36 CYC:12");
+    }
+
+    #[test]
+    fn nestest_implied() {
+        let line = format_nestest(&init_state(0xC72D, 0xFB, 24, 72), read_from(0xC72D, &[0xEA]));
+        assert_eq!(line, "C72D  EA        NOP                             A:00 X:00 Y:00 P:24 SP:FB PPU:  0, 72 CYC:24");
+    }
+
+    #[test]
+    fn nestest_unofficial() {
+        let line = format_nestest(&init_state(0xC000, 0xFD, 7, 21), read_from(0xC000, &[0xA7, 0x10]));
+        assert!(line.starts_with("C000  A7 10    *LAX $10 = 00"), "{}", line);
+    }
+
+    #[test]
+    fn nestest_indirect_indexed() {
+        let read = |addr: u16| match addr {
+            0xC000 => Some(0xB1),
+            0xC001 => Some(0x89),
+            0x0089 => Some(0x00),
+            0x008A => Some(0x03),
+            0x0300 => Some(0x89),
+            _ => Some(0x00),
+        };
+
+        let line = format_nestest(&init_state(0xC000, 0xFD, 7, 21), read);
+        assert!(line.starts_with("C000  B1 89     LDA ($89),Y = 0300 @ 0300 = 89"), "{}", line);
+    }
+
+    #[test]
+    fn nestest_relative() {
+        let line = format_nestest(&init_state(0xC72E, 0xFD, 7, 21), read_from(0xC72E, &[0xB0, 0xFE]));
+        assert!(line.starts_with("C72E  B0 FE     BCS $C72E"), "{}", line);
+    }
+
+    #[test]
+    fn mesen() {
+        let line = format_mesen(&init_state(0xC000, 0xFD, 7, 21), read_from(0xC000, &[0x4C, 0xF5, 0xC5]));
+        assert_eq!(line, "C000  $4C $F5 $C5  JMP $C5F5                   A:00 X:00 Y:00 S:FD P:nvUbdIzc  V:0   H:21  Fr:0   Cycle:7");
+    }
+
+    fn init_state(pc: u16, sp: u8, cycles: u64, dot: usize) -> TraceState {
+        TraceState {
+            pc,
+            a: 0,
+            x: 0,
+            y: 0,
+            p: 0x24,
+            sp,
+            scanline: 0,
+            dot,
+            frame: 0,
+            cycles,
+        }
+    }
+
+    fn read_from(base: u16, bytes: &[u8]) -> impl Fn(u16) -> Option<u8> + '_ {
+        move |addr| Some(addr.checked_sub(base).and_then(|i| bytes.get(i as usize).copied()).unwrap_or(0))
+    }
+}
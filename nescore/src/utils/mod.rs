@@ -1 +1,2 @@
 pub mod sampler;
+pub mod ntsc;
@@ -0,0 +1,401 @@
+//
+// utils/ntsc.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 27 2021
+//
+// Software NTSC video filter. Re-creates the composite signal output by the PPU and decodes it like a TV would
+//
+use crate::nes::PixelFormat;
+use crate::ppu::{IndexedPixel, DISPLAY_WIDTH, DISPLAY_HEIGHT};
+use crate::ppu::signal::{self, PHASES, SAMPLES_PER_PIXEL};
+
+/// Width of the filtered image. Every 3 NES pixels become 7 output pixels
+pub const NTSC_DISPLAY_WIDTH: usize = ((DISPLAY_WIDTH - 1) / 3 + 1) * 7;
+
+/// Number of signal samples in a scanline
+const LINE_SAMPLES: usize = DISPLAY_WIDTH * SAMPLES_PER_PIXEL;
+/// Output pixels line up with the signal samples again every 7 pixels
+const OUTPUT_GROUP: usize = 7;
+const SAMPLES_PER_GROUP: usize = 3 * SAMPLES_PER_PIXEL;
+/// Subcarrier phase change from one scanline to the next. A scanline is 341 pixels
+const LINE_PHASE_SHIFT: usize = (341 * SAMPLES_PER_PIXEL) % PHASES;
+/// Subcarrier phase change from one frame to the next
+const FRAME_PHASE_SHIFT: usize = 4;
+
+/// Output color format of the NTSC filter. The filter produces RGB colors, so there is no indexed format
+#[derive(Clone, Copy)]
+pub enum RgbFormat {
+    RGB8,
+    RGBA8,
+    GBRA8,
+    BGRA8,
+}
+
+impl From<RgbFormat> for PixelFormat {
+    fn from(format: RgbFormat) -> Self {
+        match format {
+            RgbFormat::RGB8 => PixelFormat::RGB8,
+            RgbFormat::RGBA8 => PixelFormat::RGBA8,
+            RgbFormat::GBRA8 => PixelFormat::GBRA8,
+            RgbFormat::BGRA8 => PixelFormat::BGRA8,
+        }
+    }
+}
+
+/// NTSC filter settings
+///
+/// `sharpness`, `artifacts`, `fringing` and `bleed` range from -1.0 to 1.0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtscSetup {
+    /// Hue rotation in degrees
+    pub hue: f64,
+    /// Saturation scale
+    pub saturation: f64,
+    /// Contrast scale
+    pub contrast: f64,
+    /// Brightness offset
+    pub brightness: f64,
+    /// Luma detail. Lower values blur the image
+    pub sharpness: f64,
+    /// Color signal interpreted as luma. Produces the dot patterns on colored areas
+    pub artifacts: f64,
+    /// Luma edges interpreted as color. Produces color fringes around bright details
+    pub fringing: f64,
+    /// Horizontal color blur
+    pub bleed: f64,
+}
+
+impl Default for NtscSetup {
+    fn default() -> Self {
+        NtscSetup::composite()
+    }
+}
+
+impl NtscSetup {
+    /// Composite video, the NES's standard video output
+    pub fn composite() -> Self {
+        NtscSetup {
+            hue: 0.0,
+            saturation: 1.0,
+            contrast: 1.0,
+            brightness: 0.0,
+            sharpness: 0.0,
+            artifacts: 0.0,
+            fringing: 0.0,
+            bleed: 0.0,
+        }
+    }
+
+    /// S-Video. Luma and color are carried separately, so there are no artifacts or fringing
+    pub fn svideo() -> Self {
+        NtscSetup {
+            sharpness: 0.2,
+            artifacts: -1.0,
+            fringing: -1.0,
+            ..NtscSetup::composite()
+        }
+    }
+
+    /// RGB. Only the blur of the scaling remains
+    pub fn rgb() -> Self {
+        NtscSetup {
+            bleed: -1.0,
+            ..NtscSetup::svideo()
+        }
+    }
+}
+
+/// Black samples on either side of the scanline, so kernels never run off the end
+const PADDING: usize = 64;
+
+/// Separated signal of a scanline
+struct LineSignal {
+    y: Vec<f64>,
+    i: Vec<f64>,
+    q: Vec<f64>,
+}
+
+impl Default for LineSignal {
+    fn default() -> Self {
+        LineSignal {
+            y: vec![0.0; LINE_SAMPLES + 2 * PADDING],
+            i: vec![0.0; LINE_SAMPLES + 2 * PADDING],
+            q: vec![0.0; LINE_SAMPLES + 2 * PADDING],
+        }
+    }
+}
+
+/// Weights applied to the signal samples around an output pixel
+struct Kernel {
+    offset: usize,
+    weights: Vec<f64>,
+}
+
+impl Kernel {
+    /// Hann window of `width` samples centered on `center`
+    fn new(center: f64, width: f64) -> Self {
+        let first = (center - width / 2.0).floor() as isize;
+        let last = (center + width / 2.0).ceil() as isize;
+
+        let mut weights: Vec<f64> = (first..=last).map(|n| {
+            let distance = (n as f64 + 0.5) - center;
+            if distance.abs() < width / 2.0 {
+                0.5 + 0.5 * (2.0 * std::f64::consts::PI * distance / width).cos()
+            }
+            else {
+                0.0
+            }
+        })
+        .collect();
+
+        let total: f64 = weights.iter().sum();
+        for weight in weights.iter_mut() {
+            *weight /= total;
+        }
+
+        Kernel {
+            offset: (first + PADDING as isize) as usize,
+            weights,
+        }
+    }
+
+    /// Weighted sum of the padded samples under the kernel, for the output pixel group starting at `base`
+    fn apply(&self, samples: &[f64], base: usize) -> f64 {
+        let start = base + self.offset;
+        self.weights.iter().zip(&samples[start..start + self.weights.len()]).map(|(w, s)| w * s).sum()
+    }
+}
+
+/// NTSC composite video filter
+///
+/// Takes frames in the `PixelFormat::Indexed` format and produces `NTSC_DISPLAY_WIDTH` x `DISPLAY_HEIGHT` images
+/// ```
+/// # use nescore::{Nes, specs::PixelFormat};
+/// # use nescore::utils::ntsc::{NtscFilter, NtscSetup};
+/// let mut nes = Nes::default().pixel_format(PixelFormat::Indexed);
+/// let mut filter = NtscFilter::new(NtscSetup::composite());
+///
+/// let (framebuffer, _) = nes.emulate_frame();
+/// let image = filter.filter(framebuffer);
+/// ```
+pub struct NtscFilter {
+    setup: NtscSetup,
+    pixel_format: PixelFormat,
+
+    levels: Vec<[f64; PHASES]>,    // Signal of each pixel value over a subcarrier cycle
+    luma: Vec<f64>,                // Average signal level of each pixel value
+    carrier: [(f64, f64); PHASES], // Reference carrier for demodulating I and Q
+
+    luma_kernels: Vec<Kernel>,
+    chroma_kernels: Vec<Kernel>,
+
+    burst_phase: usize,
+    output: Vec<u8>,
+}
+
+impl NtscFilter {
+    pub fn new(setup: NtscSetup) -> Self {
+        let levels: Vec<[f64; PHASES]> = (0..0x200).map(|pixel| {
+            let mut levels = [0.0; PHASES];
+            for (phase, level) in levels.iter_mut().enumerate() {
+                *level = signal::level(pixel as IndexedPixel, phase);
+            }
+            levels
+        })
+        .collect();
+
+        let luma = levels.iter().map(|levels| levels.iter().sum::<f64>() / PHASES as f64).collect();
+
+        let mut carrier = [(0.0, 0.0); PHASES];
+        for (phase, reference) in carrier.iter_mut().enumerate() {
+            *reference = signal::carrier(phase, setup.hue);
+        }
+
+        // Center of each output pixel in the group, in signal samples
+        let centers: Vec<f64> = (0..OUTPUT_GROUP)
+            .map(|x| (x as f64 + 0.5) * SAMPLES_PER_GROUP as f64 / OUTPUT_GROUP as f64)
+            .collect();
+
+        let luma_width = PHASES as f64 * 2f64.powf(-setup.sharpness);
+        let chroma_width = 2.0 * PHASES as f64 * (1.5 + 0.5 * setup.bleed);
+
+        let pixel_format = PixelFormat::RGB8;
+
+        NtscFilter {
+            setup,
+            pixel_format,
+
+            levels,
+            luma,
+            carrier,
+
+            luma_kernels: centers.iter().map(|&center| Kernel::new(center, luma_width)).collect(),
+            chroma_kernels: centers.iter().map(|&center| Kernel::new(center, chroma_width)).collect(),
+
+            burst_phase: 0,
+            output: vec![0; NTSC_DISPLAY_WIDTH * DISPLAY_HEIGHT * pixel_format.num_bytes()],
+        }
+    }
+
+    /// Set the output color format
+    pub fn pixel_format(mut self, format: RgbFormat) -> Self {
+        self.pixel_format = PixelFormat::from(format);
+        self.output = vec![0; NTSC_DISPLAY_WIDTH * DISPLAY_HEIGHT * self.pixel_format.num_bytes()];
+
+        self
+    }
+
+    /// Filter a frame of indexed pixels. The subcarrier phase advances each frame, producing dot crawl
+    pub fn filter(&mut self, framebuffer: &[u8]) -> &[u8] {
+        assert_eq!(framebuffer.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT * PixelFormat::Indexed.num_bytes());
+
+        let mut line_signal = LineSignal::default();
+
+        for (line, pixels) in framebuffer.chunks(DISPLAY_WIDTH * 2).enumerate() {
+            let line_phase = (self.burst_phase + line * LINE_PHASE_SHIFT) % PHASES;
+
+            self.modulate(pixels, line_phase, &mut line_signal);
+            self.demodulate(line, &line_signal);
+        }
+
+        self.burst_phase = (self.burst_phase + FRAME_PHASE_SHIFT) % PHASES;
+
+        &self.output
+    }
+
+    /// Generate the signal for a scanline and separate it into what the TV interprets as luma, I and Q
+    fn modulate(&self, pixels: &[u8], line_phase: usize, line_signal: &mut LineSignal) {
+        let mut luma = [0.0; LINE_SAMPLES];
+        let mut chroma = [0.0; LINE_SAMPLES];
+
+        for (x, pixel) in pixels.chunks(2).enumerate() {
+            let pixel = (u16::from_le_bytes([pixel[0], pixel[1]]) & 0x1FF) as usize;
+
+            for sample in x * SAMPLES_PER_PIXEL..(x + 1) * SAMPLES_PER_PIXEL {
+                luma[sample] = self.luma[pixel];
+                chroma[sample] = self.levels[pixel][(sample + line_phase) % PHASES] - self.luma[pixel];
+            }
+        }
+
+        // Luma edges are luma minus its average over a subcarrier cycle
+        let mut sums = [0.0; LINE_SAMPLES + 1];
+        for (n, level) in luma.iter().enumerate() {
+            sums[n + 1] = sums[n] + level;
+        }
+
+        let artifacts = self.setup.artifacts + 1.0;
+        let fringing = self.setup.fringing + 1.0;
+
+        for n in 0..LINE_SAMPLES {
+            let start = n.saturating_sub(PHASES / 2);
+            let end = (n + PHASES / 2).min(LINE_SAMPLES);
+            let fringe = luma[n] - (sums[end] - sums[start]) / PHASES as f64;
+
+            let chroma_in = chroma[n] + fringing * fringe;
+            let (cos, sin) = self.carrier[(n + line_phase) % PHASES];
+
+            line_signal.y[n + PADDING] = luma[n] + artifacts * chroma[n];
+            line_signal.i[n + PADDING] = chroma_in * cos;
+            line_signal.q[n + PADDING] = chroma_in * sin;
+        }
+    }
+
+    /// Decode a scanline of signal into output pixels
+    fn demodulate(&mut self, line: usize, line_signal: &LineSignal) {
+        let num_bytes = self.pixel_format.num_bytes();
+
+        for x in 0..NTSC_DISPLAY_WIDTH {
+            let base = x / OUTPUT_GROUP * SAMPLES_PER_GROUP;
+
+            let y = self.luma_kernels[x % OUTPUT_GROUP].apply(&line_signal.y, base);
+
+            let chroma_kernel = &self.chroma_kernels[x % OUTPUT_GROUP];
+            let i = chroma_kernel.apply(&line_signal.i, base);
+            let q = chroma_kernel.apply(&line_signal.q, base);
+
+            let y = y * self.setup.contrast + self.setup.brightness;
+            let i = i * self.setup.contrast * self.setup.saturation;
+            let q = q * self.setup.contrast * self.setup.saturation;
+
+            let bytes = self.pixel_format.encode(signal::yiq_to_rgb(y, i, q));
+            let idx = ((line * NTSC_DISPLAY_WIDTH) + x) * num_bytes;
+            self.output[idx..idx + num_bytes].copy_from_slice(&bytes[..num_bytes]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::{Palette, NtscPaletteParams};
+
+    #[test]
+    fn output_size() {
+        let mut filter = NtscFilter::new(NtscSetup::composite()).pixel_format(RgbFormat::BGRA8);
+        let image = filter.filter(&solid_frame(0x0F));
+
+        assert_eq!(NTSC_DISPLAY_WIDTH, 602);
+        assert_eq!(image.len(), NTSC_DISPLAY_WIDTH * DISPLAY_HEIGHT * 4);
+    }
+
+    #[test]
+    fn svideo_matches_palette() {
+        let palette = Palette::generate(NtscPaletteParams::default());
+
+        for &pixel in [0x16, 0x2A, 0x30, 0x0F, 0x12 | (0x01 << 6)].iter() {
+            let mut filter = NtscFilter::new(NtscSetup::svideo());
+            let image = filter.filter(&solid_frame(pixel));
+
+            let idx = ((120 * NTSC_DISPLAY_WIDTH) + 300) * 3;
+            let expected = palette.color((pixel >> 6) as usize, (pixel & 0x3F) as usize);
+
+            assert!(close(image[idx], expected.0), "Pixel ${:03X}", pixel);
+            assert!(close(image[idx + 1], expected.1), "Pixel ${:03X}", pixel);
+            assert!(close(image[idx + 2], expected.2), "Pixel ${:03X}", pixel);
+        }
+    }
+
+    #[test]
+    fn composite_dot_crawl() {
+        let frame = solid_frame(0x16);
+
+        let mut filter = NtscFilter::new(NtscSetup::composite());
+        let first = middle(filter.filter(&frame));
+        let second = middle(filter.filter(&frame));
+        assert_ne!(first, second);
+
+        // The subcarrier phase repeats every three frames
+        filter.filter(&frame);
+        assert_eq!(middle(filter.filter(&frame)), first);
+
+        // Without artifacts the frames are the same
+        let mut filter = NtscFilter::new(NtscSetup::rgb());
+        let first = middle(filter.filter(&frame));
+        assert_eq!(middle(filter.filter(&frame)), first);
+    }
+
+    #[test]
+    fn grey_has_no_color() {
+        let mut filter = NtscFilter::new(NtscSetup::composite());
+        let image = filter.filter(&solid_frame(0x10));
+
+        let idx = ((120 * NTSC_DISPLAY_WIDTH) + 300) * 3;
+        assert_eq!(image[idx], image[idx + 1]);
+        assert_eq!(image[idx + 1], image[idx + 2]);
+    }
+
+    fn solid_frame(pixel: IndexedPixel) -> Vec<u8> {
+        pixel.to_le_bytes().iter().cloned().cycle().take(DISPLAY_WIDTH * DISPLAY_HEIGHT * 2).collect()
+    }
+
+    /// Pixels from the middle of the image, away from the edges of the scanlines
+    fn middle(image: &[u8]) -> Vec<u8> {
+        let start = ((120 * NTSC_DISPLAY_WIDTH) + 280) * 3;
+        image[start..start + 14 * 3].to_vec()
+    }
+
+    fn close(actual: u8, expected: u8) -> bool {
+        (actual as i16 - expected as i16).abs() <= 4
+    }
+}
@@ -44,3 +44,15 @@ fn apu_irq_timing() {
     let mut nes = common::init_nes("tests/roms/nes-test-roms/apu_test/rom_singles/6-irq_flag_timing.nes");
     common::run_test(&mut nes, "Irq timing test failed with");
 }
+
+#[test]
+fn apu_dmc_basics() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/apu_test/rom_singles/7-dmc_basics.nes");
+    common::run_test(&mut nes, "DMC basics test failed with");
+}
+
+#[test]
+fn apu_dmc_rates() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/apu_test/rom_singles/8-dmc_rates.nes");
+    common::run_test(&mut nes, "DMC rates test failed with");
+}
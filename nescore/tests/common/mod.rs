@@ -1,7 +1,10 @@
 use nescore::{Nes, Cartridge};
 
 pub fn init_nes(path: &str) -> Nes {
-    Cartridge::from_path(path).map(|cart| Nes::default().with_cart(cart).debug_mode(false)).unwrap()
+    match Cartridge::from_path(path) {
+        Ok(cart) => Nes::default().with_cart(cart).debug_mode(false),
+        Err(e) => panic!("Failed to load {}: {}. The test ROMs are in the nes-test-roms submodule", path, e),
+    }
 }
 
 pub fn run_test(nes: &mut Nes, fail_msg: &str) {
@@ -0,0 +1,25 @@
+//
+// dma.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 05 2021
+//
+mod common;
+
+#[test]
+fn oam_read() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/oam_read/oam_read.nes");
+    common::run_test(&mut nes, "OAM read test failed with");
+}
+
+#[test]
+fn dma_sync() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprdma_and_dmc_dma/sprdma_and_dmc_dma.nes");
+    common::run_test(&mut nes, "DMA sync test failed with");
+}
+
+#[test]
+fn dma_sync_512() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprdma_and_dmc_dma/sprdma_and_dmc_dma_512.nes");
+    common::run_test(&mut nes, "DMA sync test failed with");
+}
@@ -0,0 +1,165 @@
+//
+// gdb.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 29 2021
+//
+
+use nescore::{Nes, Cartridge};
+use nescore::gdb::GdbServer;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+
+#[test]
+fn registers_and_memory() {
+    let responses = run_session(&[
+        "qSupported:swbreak+",
+        "?",
+        "g",
+        "mc000,3",
+        "M0010,2:abcd",
+        "m0010,2",
+        "P0=42",
+        "p0",
+        "s",
+        "p5",
+    ]);
+
+    assert!(responses[0].contains("qXfer:features:read+"));
+    assert_eq!(responses[1], "S05");
+    // A, X, Y, P, SP, PC (little endian)
+    assert_eq!(responses[2], "00000024fd00c0");
+    // JMP $C5F5
+    assert_eq!(responses[3], "4cf5c5");
+    assert_eq!(responses[4], "OK");
+    assert_eq!(responses[5], "abcd");
+    assert_eq!(responses[6], "OK");
+    assert_eq!(responses[7], "42");
+    assert_eq!(responses[8], "S05");
+    assert_eq!(responses[9], "f5c5");
+}
+
+#[test]
+fn io_memory() {
+    let responses = run_session(&[
+        "m2000,2",
+        "M2000,1:80",
+        "M1fff,2:0102",
+        "m1fff,1",
+    ]);
+
+    // I/O registers are not accessed by the debugger
+    assert_eq!(responses[0], "0000");
+    assert_eq!(responses[1], "E01");
+    assert_eq!(responses[2], "E01");
+    assert_eq!(responses[3], "00");
+}
+
+#[test]
+fn breakpoints() {
+    let responses = run_session(&[
+        "Z2,0010,1",
+        "c",
+        "z2,0010,1",
+        "Z0,c72d,1",
+        "c",
+        "p5",
+        "z0,c72d,1",
+    ]);
+
+    assert_eq!(responses[0], "OK");
+    // STX $10
+    assert_eq!(responses[1], "T05watch:0010;");
+    assert_eq!(responses[2], "OK");
+    assert_eq!(responses[3], "OK");
+    assert_eq!(responses[4], "S05");
+    assert_eq!(responses[5], "2dc7");
+    assert_eq!(responses[6], "OK");
+}
+
+#[test]
+fn access_watchpoint() {
+    let responses = run_session(&[
+        "Z4,0010,1",
+        "c",
+        "z4,0010,1",
+    ]);
+
+    assert_eq!(responses[0], "OK");
+    assert_eq!(responses[1], "T05awatch:0010;");
+    assert_eq!(responses[2], "OK");
+}
+
+#[test]
+fn resume_address() {
+    let responses = run_session(&[
+        "sc5f5",
+        "p5",
+        "sxyz",
+        "cxyz",
+    ]);
+
+    // LDX #$00
+    assert_eq!(responses[0], "S05");
+    assert_eq!(responses[1], "f7c5");
+    assert_eq!(responses[2], "E01");
+    assert_eq!(responses[3], "E01");
+}
+
+#[test]
+fn target_description() {
+    let responses = run_session(&["qXfer:features:read:target.xml:0,1000"]);
+
+    assert!(responses[0].starts_with("l<?xml"));
+    assert!(responses[0].contains(r#"<reg name="pc" bitsize="16""#));
+}
+
+/// Serve a debugger session on the current thread while a client sends each packet and collects the responses
+fn run_session(packets: &[&str]) -> Vec<String> {
+    let cart = Cartridge::from_path("tests/roms/nestest/nestest.nes").unwrap();
+    let mut nes = Nes::default().with_cart(cart).entry(0xC000);
+
+    let server = GdbServer::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let packets: Vec<String> = packets.iter().map(|p| p.to_string()).collect();
+    let client = thread::spawn(move || client(addr, packets));
+
+    server.serve(&mut nes).unwrap();
+
+    client.join().unwrap()
+}
+
+fn client(addr: SocketAddr, packets: Vec<String>) -> Vec<String> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+
+    let responses = packets.iter().map(|packet| request(&mut stream, packet)).collect();
+    assert_eq!(request(&mut stream, "D"), "OK");
+
+    responses
+}
+
+fn request(stream: &mut TcpStream, packet: &str) -> String {
+    let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    write!(stream, "${}#{:02x}", packet, checksum).unwrap();
+
+    assert_eq!(read_byte(stream), b'+', "Packet was not acknowledged");
+    assert_eq!(read_byte(stream), b'$');
+
+    let response: Vec<u8> = std::iter::from_fn(|| Some(read_byte(stream))).take_while(|b| *b != b'#').collect();
+    let mut checksum = [0u8; 2];
+    stream.read_exact(&mut checksum).unwrap();
+
+    stream.write_all(b"+").unwrap();
+
+    String::from_utf8(response).unwrap()
+}
+
+fn read_byte(stream: &mut TcpStream) -> u8 {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).unwrap();
+
+    byte[0]
+}
@@ -34,10 +34,8 @@ fn nes_instr_implied() {
 
 #[test]
 fn nes_instr_immediate() {
-    // Fail on implementation for ARR and AXS instructions. Apparently these are sketchy
-    // https://wiki.nesdev.com/w/index.php/Talk:Programming_with_unofficial_opcodes#ARR_and_AXS_instructions
     let mut nes = common::init_nes("tests/roms/nes-test-roms/nes_instr_test/rom_singles/02-immediate.nes");
-    common::run_test_with_ignore(&mut nes, "Immediate instructions exited with", vec![String::from("arr"), String::from("axs")]);
+    common::run_test(&mut nes, "Immediate instructions exited with");
 }
 
 #[test]
@@ -61,7 +59,7 @@ fn nes_instr_absolute() {
 #[test]
 fn nes_instr_abs_xy() {
     let mut nes = common::init_nes("tests/roms/nes-test-roms/nes_instr_test/rom_singles/06-abs_xy.nes");
-    common::run_test_with_ignore(&mut nes, "Absolute XY instructions exited with", vec![String::from("sxa"), String::from("sya")]);
+    common::run_test(&mut nes, "Absolute XY instructions exited with");
 }
 
 #[test]
@@ -92,3 +90,135 @@ fn nes_instr_special() {
     let mut nes = common::init_nes("tests/roms/nes-test-roms/nes_instr_test/rom_singles/11-special.nes");
     common::run_test(&mut nes, "Special instructions exited with");
 }
+
+#[test]
+fn instr_test_basics() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/01-basics.nes");
+    common::run_test(&mut nes, "Basic instructions exited with");
+}
+
+#[test]
+fn instr_test_implied() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/02-implied.nes");
+    common::run_test(&mut nes, "Implied instructions exited with");
+}
+
+#[test]
+fn instr_test_immediate() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/03-immediate.nes");
+    common::run_test(&mut nes, "Immediate instructions exited with");
+}
+
+#[test]
+fn instr_test_zero_page() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/04-zero_page.nes");
+    common::run_test(&mut nes, "Zeropage instructions exited with");
+}
+
+#[test]
+fn instr_test_zp_xy() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/05-zp_xy.nes");
+    common::run_test(&mut nes, "Zeropage XY instructions exited with");
+}
+
+#[test]
+fn instr_test_absolute() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/06-absolute.nes");
+    common::run_test(&mut nes, "Absolute instructions exited with");
+}
+
+#[test]
+fn instr_test_abs_xy() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/07-abs_xy.nes");
+    common::run_test(&mut nes, "Absolute XY instructions exited with");
+}
+
+#[test]
+fn instr_test_ind_x() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/08-ind_x.nes");
+    common::run_test(&mut nes, "Indirect X instructions exited with");
+}
+
+#[test]
+fn instr_test_ind_y() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/09-ind_y.nes");
+    common::run_test(&mut nes, "Indirect Y instructions exited with");
+}
+
+#[test]
+fn instr_test_branches() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/10-branches.nes");
+    common::run_test(&mut nes, "Branch instructions exited with");
+}
+
+#[test]
+fn instr_test_stack() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/11-stack.nes");
+    common::run_test(&mut nes, "Stack instructions exited with");
+}
+
+#[test]
+fn instr_test_jmp_jsr() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/12-jmp_jsr.nes");
+    common::run_test(&mut nes, "JMP and JSR instructions exited with");
+}
+
+#[test]
+fn instr_test_rts() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/13-rts.nes");
+    common::run_test(&mut nes, "RTS instruction exited with");
+}
+
+#[test]
+fn instr_test_rti() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/14-rti.nes");
+    common::run_test(&mut nes, "RTI instruction exited with");
+}
+
+#[test]
+fn instr_test_brk() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/15-brk.nes");
+    common::run_test(&mut nes, "BRK instruction exited with");
+}
+
+#[test]
+fn instr_test_special() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_test-v5/rom_singles/16-special.nes");
+    common::run_test(&mut nes, "Special instructions exited with");
+}
+
+#[test]
+fn instr_misc_abs_x_wrap() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_misc/rom_singles/01-abs_x_wrap.nes");
+    common::run_test(&mut nes, "Absolute X wrap test exited with");
+}
+
+#[test]
+fn instr_misc_branch_wrap() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_misc/rom_singles/02-branch_wrap.nes");
+    common::run_test(&mut nes, "Branch wrap test exited with");
+}
+
+#[test]
+fn instr_misc_dummy_reads() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_misc/rom_singles/03-dummy_reads.nes");
+    common::run_test(&mut nes, "Dummy reads test exited with");
+}
+
+#[test]
+fn instr_misc_dummy_reads_apu() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/instr_misc/rom_singles/04-dummy_reads_apu.nes");
+    common::run_test(&mut nes, "APU dummy reads test exited with");
+}
+
+#[test]
+fn cpu_dummy_writes_oam() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_dummy_writes/cpu_dummy_writes_oam.nes");
+    common::run_test(&mut nes, "OAM dummy writes test exited with");
+}
+
+#[test]
+fn cpu_dummy_writes_ppumem() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_dummy_writes/cpu_dummy_writes_ppumem.nes");
+    common::run_test(&mut nes, "PPU memory dummy writes test exited with");
+}
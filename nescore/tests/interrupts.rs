@@ -0,0 +1,37 @@
+//
+// interrupts.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 12 2021
+//
+mod common;
+
+#[test]
+fn cpu_interrupts_cli_latency() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_interrupts_v2/rom_singles/1-cli_latency.nes");
+    common::run_test(&mut nes, "CLI latency test failed with");
+}
+
+#[test]
+fn cpu_interrupts_nmi_and_brk() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_interrupts_v2/rom_singles/2-nmi_and_brk.nes");
+    common::run_test(&mut nes, "NMI and BRK test failed with");
+}
+
+#[test]
+fn cpu_interrupts_nmi_and_irq() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_interrupts_v2/rom_singles/3-nmi_and_irq.nes");
+    common::run_test(&mut nes, "NMI and IRQ test failed with");
+}
+
+#[test]
+fn cpu_interrupts_irq_and_dma() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_interrupts_v2/rom_singles/4-irq_and_dma.nes");
+    common::run_test(&mut nes, "IRQ and DMA test failed with");
+}
+
+#[test]
+fn cpu_interrupts_branch_delays_irq() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/cpu_interrupts_v2/rom_singles/5-branch_delays_irq.nes");
+    common::run_test(&mut nes, "Branch delays IRQ test failed with");
+}
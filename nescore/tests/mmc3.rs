@@ -0,0 +1,44 @@
+//
+// mmc3.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 02 2021
+//
+mod common;
+
+#[test]
+fn mmc3_clocking() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/1-clocking.nes");
+    common::run_test(&mut nes, "MMC3 clocking test failed with");
+}
+
+#[test]
+fn mmc3_details() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/2-details.nes");
+    common::run_test(&mut nes, "MMC3 details test failed with");
+}
+
+#[test]
+fn mmc3_a12_clocking() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/3-A12_clocking.nes");
+    common::run_test(&mut nes, "MMC3 A12 clocking test failed with");
+}
+
+#[test]
+fn mmc3_scanline_timing() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/4-scanline_timing.nes");
+    common::run_test(&mut nes, "MMC3 scanline timing test failed with");
+}
+
+#[test]
+fn mmc3_irq_behavior() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/5-MMC3.nes");
+    common::run_test(&mut nes, "MMC3 IRQ behavior test failed with");
+}
+
+#[test]
+#[ignore = "MMC6 IRQ behavior is not implemented"]
+fn mmc6_irq_behavior() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/mmc3_test/6-MMC6.nes");
+    common::run_test(&mut nes, "MMC6 IRQ behavior test failed with");
+}
@@ -0,0 +1,19 @@
+//
+// ppu.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jul 10 2021
+//
+mod common;
+
+#[test]
+fn ppu_open_bus() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/ppu_open_bus/ppu_open_bus.nes");
+    common::run_test(&mut nes, "PPU open bus test failed with");
+}
+
+#[test]
+fn ppu_read_buffer() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/ppu_read_buffer/test_ppu_read_buffer.nes");
+    common::run_test(&mut nes, "PPU read buffer test failed with");
+}
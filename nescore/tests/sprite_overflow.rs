@@ -0,0 +1,37 @@
+//
+// sprite_overflow.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jul 04 2021
+//
+mod common;
+
+#[test]
+fn sprite_overflow_basics() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprite_overflow_tests/1.Basics.nes");
+    common::run_test(&mut nes, "Basics test failed with");
+}
+
+#[test]
+fn sprite_overflow_details() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprite_overflow_tests/2.Details.nes");
+    common::run_test(&mut nes, "Details test failed with");
+}
+
+#[test]
+fn sprite_overflow_timing() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprite_overflow_tests/3.Timing.nes");
+    common::run_test(&mut nes, "Timing test failed with");
+}
+
+#[test]
+fn sprite_overflow_obscure() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprite_overflow_tests/4.Obscure.nes");
+    common::run_test(&mut nes, "Obscure test failed with");
+}
+
+#[test]
+fn sprite_overflow_emulator() {
+    let mut nes = common::init_nes("tests/roms/nes-test-roms/sprite_overflow_tests/5.Emulator.nes");
+    common::run_test(&mut nes, "Emulator test failed with");
+}
@@ -0,0 +1,107 @@
+//
+// state.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date May 09 2021
+//
+
+use nescore::{Nes, Cartridge, Button};
+
+#[test]
+fn save_state_round_trip() {
+    let mut nes = init_nes();
+
+    // Wait for the menu to be displayed
+    for _ in 0..30 {
+        nes.emulate_frame();
+    }
+
+    let state = nes.save_state().unwrap();
+    let expected = run_tests(&mut nes);
+
+    nes.load_state(&state).unwrap();
+    let actual = run_tests(&mut nes);
+
+    // The test results should be drawn to the screen
+    assert_ne!(expected.first().unwrap().0, expected.last().unwrap().0);
+    assert!(actual == expected, "Frames after restoring the save state do not match");
+}
+
+#[test]
+fn save_state_into_new_instance() {
+    let mut nes = init_nes();
+
+    for _ in 0..30 {
+        nes.emulate_frame();
+    }
+
+    let state = nes.save_state().unwrap();
+    let expected = run_tests(&mut nes);
+
+    let mut restored = init_nes();
+    restored.load_state(&state).unwrap();
+    let actual = run_tests(&mut restored);
+
+    assert!(actual == expected, "Frames after restoring the save state do not match");
+    assert_eq!(restored.save_state().unwrap(), nes.save_state().unwrap());
+}
+
+#[test]
+fn rewind() {
+    let mut nes = init_nes().rewind_buffer(100, 2);
+
+    for _ in 0..30 {
+        nes.emulate_frame();
+    }
+
+    let expected = run_tests(&mut nes);
+
+    assert_eq!(nes.rewind(60), 60);
+    let actual = run_tests(&mut nes);
+
+    assert!(actual == expected, "Frames after rewinding do not match");
+}
+
+#[test]
+fn rewind_nearest_snapshot() {
+    let mut nes = init_nes().rewind_buffer(5, 4);
+
+    for _ in 0..30 {
+        nes.emulate_frame();
+    }
+
+    // Snapshots are taken every 4 frames, the nearest snapshot to frame 25 is frame 24
+    assert_eq!(nes.rewind(5), 6);
+    // Only 5 snapshots are kept (frames 12 to 28), so this stops at the oldest one
+    assert_eq!(nes.rewind(1000), 12);
+}
+
+#[test]
+fn rewind_disabled() {
+    let mut nes = init_nes();
+
+    for _ in 0..10 {
+        nes.emulate_frame();
+    }
+
+    assert_eq!(nes.rewind(5), 0);
+}
+
+fn init_nes() -> Nes {
+    let cart = Cartridge::from_path("tests/roms/nestest/nestest.nes").unwrap();
+    Nes::default().with_cart(cart)
+}
+
+/// Press start to run all tests and capture the following frames
+fn run_tests(nes: &mut Nes) -> Vec<(Vec<u8>, Vec<f32>)> {
+    let mut frames = vec![];
+
+    for i in 0..60 {
+        nes.input(Button::Start, i < 5);
+
+        let (video, audio) = nes.emulate_frame();
+        frames.push((video.to_vec(), audio));
+    }
+
+    frames
+}
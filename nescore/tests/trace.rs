@@ -0,0 +1,72 @@
+//
+// trace.rs
+//
+// @author Natesh Narain <nnaraindev@gmail.com>
+// @date Jun 05 2021
+//
+
+use nescore::{Nes, Cartridge};
+use nescore::trace::{TraceLogger, TraceFormat};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Shared buffer so the log can be read back after running
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn nestest_log() {
+    let expected = std::fs::read_to_string("tests/roms/nestest/nestest.log")
+        .expect("tests/roms/nestest/nestest.log is required");
+
+    let actual = run_nestest(TraceFormat::Nestest);
+
+    let mut lines = 0;
+
+    for (i, (expected, actual)) in expected.lines().zip(actual.lines()).enumerate() {
+        assert_eq!(actual, expected, "Line {} does not match\n{}\n{}", i + 1, expected, actual);
+        lines += 1;
+    }
+
+    assert_eq!(lines, expected.lines().count(), "Trace ended early");
+}
+
+#[test]
+fn mesen_log() {
+    let log = run_nestest(TraceFormat::Mesen);
+    let first = log.lines().next().unwrap();
+
+    assert!(first.starts_with("C000  $4C $F5 $C5  JMP $C5F5"), "{}", first);
+    assert!(first.ends_with("Cycle:7"), "{}", first);
+}
+
+/// Run nestest in automation mode and return the trace log
+fn run_nestest(format: TraceFormat) -> String {
+    let cart = Cartridge::from_path("tests/roms/nestest/nestest.nes").unwrap();
+    let buffer = SharedBuffer::default();
+
+    let mut nes = Nes::default()
+                    .with_cart(cart)
+                    .entry(0xC000)
+                    .trace_logger(TraceLogger::new(buffer.clone(), format));
+
+    // According to nestest logs the test ends at $C66E
+    nes.run_until(0xC66E);
+    // Log the final instruction
+    nes.step_cycle();
+
+    let log = buffer.0.borrow();
+    String::from_utf8(log.clone()).unwrap()
+}